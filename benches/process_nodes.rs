@@ -56,4 +56,13 @@ fn bench_topo_sort_nodes(c: &mut Criterion) {
 	});
 }
 
-criterion_group!(process_nodes, bench_topo_sort_nodes);
\ No newline at end of file
+fn bench_update_hierarchy(c: &mut Criterion) {
+	let mut state = create_big_state();
+	c.bench_function("update_hierarchy", |b| {
+		b.iter(|| {
+			state.update_hierarchy();
+		});
+	});
+}
+
+criterion_group!(process_nodes, bench_topo_sort_nodes, bench_update_hierarchy);
\ No newline at end of file
@@ -19,8 +19,8 @@ impl SceneViewer {
 		let name = scene.name.clone().unwrap_or_default();
 		log::info!("Scene added: {:?}", scene_id);
 		log::info!("scene bounding box: {:?}", state.get_scene_bounding_box(scene_id));
-		for (_, node) in state.nodes.iter_mut().filter(|(_, node)| node.parent == NodeParent::Scene(scene_id)) {
-			node.scale = Vec3::new(10.0, 10.0, 10.0);
+		for node_id in state.scene_children(scene_id).to_vec() {
+			state.nodes.get_mut(&node_id).unwrap().scale = Vec3::new(10.0, 10.0, 10.0);
 		}
 
 		let mut light_node = Node::new();
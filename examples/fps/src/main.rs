@@ -100,7 +100,7 @@ pub struct FpsShooter {
 	pitch: f32,
 	speed: f32,
 	dashing: bool,
-	movement_force: f32,
+	player_controller: Option<ArenaId<CharacterController>>,
 	player_ray: Option<ArenaId<RayCast>>,
     gripping: bool,
 	gripping_node: Option<ArenaId<Node>>,
@@ -110,8 +110,8 @@ pub struct FpsShooter {
 	firing_rate: Instant,
 	bullet_mesh: Option<ArenaId<Mesh>>,
 	main_scene: Option<ArenaId<Scene>>,
-	move_force: Vec3,
-	recoil_force: Vec3,
+	move_velocity: Vec3,
+	recoil_velocity: Vec3,
 	bullets: Vec<Bullet>,
 }
 
@@ -128,7 +128,7 @@ impl FpsShooter {
 			pitch: 0.0,
 			speed: 10.0,
 			light_circle_i: 0.0,
-			movement_force: 4000.0,
+			player_controller: None,
 			dashing: false,
 			player_ray: None,
 			gripping: false,
@@ -139,8 +139,8 @@ impl FpsShooter {
 			firing_rate: Instant::now(),
 			bullet_mesh: None,
 			main_scene: None,
-			move_force: Vec3::ZERO,
-			recoil_force: Vec3::ZERO,
+			move_velocity: Vec3::ZERO,
+			recoil_velocity: Vec3::ZERO,
 			bullets: Vec::new(),
 		}
 	}
@@ -215,7 +215,7 @@ impl FpsShooter {
 				None => return,
 			};
 			let dir = player.rotation * Vec3::new(0.0, 0.0, 1.0);
-			player.physics.velocity = dir * 100.0;
+			self.move_velocity = Vec3::new(dir.x, 0.0, dir.z).normalize_or_zero() * 100.0;
 		}
 	}
 
@@ -227,7 +227,7 @@ impl FpsShooter {
 		self.firing_rate = Instant::now();
 
 		if !self.shooting {
-			self.recoil_force = Vec3::ZERO;
+			self.recoil_velocity = Vec3::ZERO;
 			return;
 		}
 
@@ -244,8 +244,10 @@ impl FpsShooter {
 			bullet.mesh = Some(bullet_mesh_id);
 			bullet.physics.typ = PhycisObjectType::Dynamic;
 			bullet.physics.mass = 1.0;
+			bullet.physics.ccd = true;
 			bullet.collision_shape = Some(CollisionShape::Box { size: glam::Vec3::new(0.3, 0.3, 0.3) });
 			bullet.parent = NodeParent::Scene(self.main_scene.unwrap());
+			bullet.physics.ignored_nodes.push(player_inx);
 			let rotation = state.nodes.get(&player_inx).unwrap().rotation;
 			let mut translation = state.nodes.get(&player_inx).unwrap().translation;
 			// location in fron of player
@@ -267,7 +269,7 @@ impl FpsShooter {
 		};
 
 		let dir = player.rotation * Vec3::new(0.0, 0.0, 0.3);
-		self.recoil_force = dir * -100.0;
+		self.recoil_velocity = dir * -10.0;
 
 		// rotate comera up
 		self.pitch -= 0.05;
@@ -281,38 +283,9 @@ impl FpsShooter {
 			None => return,
 		};
 
-		if let Some(player) = state.nodes.get_mut(&player_id) {
-			let current_speed = player.physics.velocity.length();
-			if self.pressed_keys.any_pressed() {
-				let dir = self.pressed_keys.to_vec3();
-				let mut force = player.rotation * dir;
-
-				if force.x > 0.0 && player.physics.velocity.x < 0.0 {
-					force.x += -player.physics.velocity.x * self.movement_force;
-				} else if force.x < 0.0 && player.physics.velocity.x > 0.0 {
-					force.x += -player.physics.velocity.x * self.movement_force;
-				} else if current_speed < 25.0 {
-					force.x *= self.movement_force;
-				} 
-
-				if force.z > 0.0 && player.physics.velocity.z < 0.0 {
-					force.z += -player.physics.velocity.z * self.movement_force;
-				} else if force.z < 0.0 && player.physics.velocity.z > 0.0 {
-					force.z += -player.physics.velocity.z * self.movement_force;
-				} else if current_speed < 25.0 {
-					force.z *= self.movement_force;
-				}
-
-				force.y = 0.0;
-
-				self.move_force = force;
-				// log::info!("force: {:?}", player.physics.force);
-			} else {
-				// We calculate force opposite of momevement to slow down the player
-				let force = -player.physics.velocity.xz() * self.movement_force;
-				self.move_force = glam::Vec3::new(force.x, 0.0, force.y);
-				//player.physics.force = glam::Vec3::ZERO;
-			}
+		if let Some(player) = state.nodes.get(&player_id) {
+			let dir = player.rotation * self.pressed_keys.to_vec3();
+			self.move_velocity = Vec3::new(dir.x, 0.0, dir.z).normalize_or_zero() * self.speed;
 		}
 	}
 }
@@ -409,13 +382,14 @@ impl pge::App for FpsShooter {
 		let mut player = Node::new();
 		player.name = Some("Player".to_string());
 		player.set_translation(0.0, 30.0, 0.0);
-		player.physics.typ = PhycisObjectType::Dynamic;
-		player.physics.mass = 70.0;
+		player.physics.typ = PhycisObjectType::Kinematic;
 		//player.looking_at(0.0, 0.0, 0.0);
-		player.collision_shape = Some(CollisionShape::Box { size: glam::Vec3::new(1.0, 2.0, 1.0) });
+		player.collision_shape = Some(CollisionShape::Capsule { radius: 1.0, half_height: 1.0 });
 		player.parent = NodeParent::Scene(main_scene_id);
-		player.lock_rotation = true;
 		let player_id = state.nodes.insert(player);
+		let mut controller = CharacterController::new(player_id);
+		controller.step_height = 0.5;
+		self.player_controller = Some(state.characters.insert(controller));
 
 		{
 			let mut node = Node::new();
@@ -440,7 +414,8 @@ impl pge::App for FpsShooter {
 		}
 		
 
-		let raycast = RayCast::new(player_id, 10.0);
+		let mut raycast = RayCast::new(player_id, 10.0);
+		raycast.filter.exclude_sensors = true;
 		let player_ray_inx = state.raycasts.insert(raycast);
 		self.player_ray = Some(player_ray_inx);
 
@@ -485,12 +460,14 @@ impl pge::App for FpsShooter {
 					KeyboardKey::A => self.pressed_keys.left = true,
 					KeyboardKey::D => self.pressed_keys.right = true,
 					KeyboardKey::Space => {
-						let player_inx = match self.player_id {
-							Some(index) => index,
+						let controller_id = match self.player_controller {
+							Some(id) => id,
 							None => return,
 						};
-						let player = state.nodes.get_mut(&player_inx).unwrap();
-						player.physics.velocity.y = 10.0;
+						let controller = state.characters.get_mut(&controller_id).unwrap();
+						if controller.grounded {
+							controller.velocity.y = 10.0;
+						}
 					},
 					KeyboardKey::ShiftLeft => {
 						self.dashing = true;
@@ -583,6 +560,35 @@ impl pge::App for FpsShooter {
 		}
 	}
 
+	fn on_physics_event(&mut self, event: PhycicsEvent, state: &mut State) {
+		let (node1, node2, point) = match event {
+			PhycicsEvent::ContactBegin { node1, node2, point, .. } => (node1, node2, point),
+			_ => return,
+		};
+
+		self.bullets.retain(|bullet| {
+			let other = if bullet.node_id == node1 {
+				node2
+			} else if bullet.node_id == node2 {
+				node1
+			} else {
+				return true;
+			};
+			if Some(other) == self.player_id {
+				return true;
+			}
+			log::info!("bullet {} hit {}", bullet.node_id, other);
+			if let Some(bullet_node) = state.nodes.remove(&bullet.node_id) {
+				// Knock the target back where the bullet hit it
+				let impulse = bullet_node.physics.velocity * bullet_node.physics.mass;
+				if let Some(target) = state.nodes.get_mut(&other) {
+					target.apply_impulse(point, impulse);
+				}
+			}
+			false
+		});
+	}
+
 	fn on_process(&mut self, state: &mut State, delta: f32) {
 		for orc in &mut self.orcs {
 			orc.on_process(self.player_id.unwrap(), state);
@@ -601,10 +607,15 @@ impl pge::App for FpsShooter {
 		self.handle_rays(state);
 		self.handle_shooting(state);
 
-		if let Some(player_id) = self.player_id {
-			let player = state.nodes.get_mut(&player_id).unwrap();
-			player.physics.force = self.move_force;
-			player.physics.force += self.recoil_force;
+		if let Some(controller_id) = self.player_controller {
+			let controller = state.characters.get_mut(&controller_id).unwrap();
+			let horizontal = self.move_velocity + self.recoil_velocity;
+			let vertical = if controller.grounded && controller.velocity.y <= 0.0 {
+				0.0
+			} else {
+				controller.velocity.y - 9.81 * delta
+			};
+			controller.velocity = Vec3::new(horizontal.x, vertical, horizontal.z);
 		}
 
 		self.bullets.retain(|bullet| {
@@ -2,6 +2,8 @@ use std::fmt;
 use std::fmt::Display;
 use std::hash::Hash;
 use std::marker::PhantomData;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 
 #[derive(Debug)]
 pub struct ArenaId<T> {
@@ -10,7 +12,7 @@ pub struct ArenaId<T> {
 }
 
 impl<T> ArenaId<T> {
-    fn new(index: usize) -> Self {
+    pub(crate) fn new(index: usize) -> Self {
         Self {
             index,
             _phantom: PhantomData,
@@ -53,10 +55,14 @@ impl<T> Hash for ArenaId<T> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Source of arena versions, shared so two arenas never have the same one.
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone)]
 pub struct Arena<T> {
     items: Vec<Option<T>>,
     free_slots: Vec<usize>,
+    version: u64,
 }
 
 impl<T> Default for Arena<T> {
@@ -64,16 +70,24 @@ impl<T> Default for Arena<T> {
         Self {
             items: Vec::new(),
             free_slots: Vec::new(),
+            version: 0,
         }
     }
 }
 
+impl<T: PartialEq> PartialEq for Arena<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.items == other.items && self.free_slots == other.free_slots
+    }
+}
+
 impl<T> Arena<T> {
     pub fn new() -> Self {
         Arena::default()
     }
 
     pub fn insert(&mut self, item: T) -> ArenaId<T> {
+        self.changed();
         if let Some(index) = self.free_slots.pop() {
             self.items[index] = Some(item);
             ArenaId::new(index)
@@ -105,6 +119,7 @@ impl<T> Arena<T> {
             let removed_item = self.items[id.index].take();
             if removed_item.is_some() {
                 self.free_slots.push(id.index);
+                self.changed();
             }
             removed_item
         } else {
@@ -120,6 +135,46 @@ impl<T> Arena<T> {
         id.index < self.items.len() && self.items[id.index].is_some()
     }
 
+    /// Changes whenever an item is inserted or removed, to a value no other
+    /// arena has had. Changes made through `get_mut` don't count.
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn changed(&mut self) {
+        self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Moves every item `part` assigns to a part into one of `parts` arenas,
+    /// under the same id. Slots of moved items stay reserved until the arenas
+    /// are given back with `restore`.
+    pub(crate) fn split(&mut self, parts: usize, part: impl Fn(ArenaId<T>, &T) -> Option<usize>) -> Vec<Arena<T>> {
+        let mut arenas: Vec<Arena<T>> = (0..parts).map(|_| Arena::new()).collect();
+        for index in 0..self.items.len() {
+            let target = match &self.items[index] {
+                Some(item) => part(ArenaId::new(index), item),
+                None => None,
+            };
+            if let Some(target) = target {
+                let arena = &mut arenas[target];
+                if arena.items.len() <= index {
+                    arena.items.resize_with(index + 1, || None);
+                }
+                arena.items[index] = self.items[index].take();
+            }
+        }
+        arenas
+    }
+
+    /// Puts back the items of an arena made by `split`.
+    pub(crate) fn restore(&mut self, part: Arena<T>) {
+        for (index, item) in part.items.into_iter().enumerate() {
+            if item.is_some() {
+                self.items[index] = item;
+            }
+        }
+    }
+
     pub fn iter(&self) -> ArenaIterator<T> {
         ArenaIterator {
             arena: self,
@@ -170,7 +225,7 @@ impl<'a, T> Iterator for ArenaIterMut<'a, T> {
             while self.current < (*self.arena).items.len() {
                 let index = self.current;
                 self.current += 1;
-                if let Some(ref mut item) = (*self.arena).items[index] {
+                if let Some(ref mut item) = (&mut (*self.arena).items)[index] {
                     return Some((ArenaId::new(index), item));
                 }
             }
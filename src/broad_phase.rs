@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use glam::Vec3;
+use crate::bvh::Bvh;
+use crate::spatial_grid::SpatialGrid;
+use crate::ArenaId;
+use crate::BroadPhaseType;
+use crate::Node;
+use crate::AABB;
+
+/// Finds the nodes of a scene that may touch, before their shapes are tested.
+#[derive(Debug, Clone)]
+pub enum BroadPhase {
+	Grid(SpatialGrid),
+	Bvh(Bvh),
+}
+
+impl BroadPhase {
+	pub fn new(typ: &BroadPhaseType) -> Self {
+		match typ {
+			BroadPhaseType::Grid { cell_size } => Self::Grid(SpatialGrid::new(*cell_size)),
+			BroadPhaseType::Bvh => Self::Bvh(Bvh::new()),
+		}
+	}
+
+	/// Whether this broad phase is of the kind `typ` asks for.
+	pub fn is(&self, typ: &BroadPhaseType) -> bool {
+		match (self, typ) {
+			(Self::Grid(grid), BroadPhaseType::Grid { cell_size }) => grid.cell_size() == *cell_size,
+			(Self::Bvh(_), BroadPhaseType::Bvh) => true,
+			_ => false,
+		}
+	}
+
+	pub fn get_node_rect(&self, node_id: ArenaId<Node>) -> Option<&AABB> {
+		match self {
+			Self::Grid(grid) => grid.get_node_rect(node_id),
+			Self::Bvh(bvh) => bvh.get_node_rect(node_id),
+		}
+	}
+
+	pub fn set_node(&mut self, node_id: ArenaId<Node>, rect: AABB) {
+		match self {
+			Self::Grid(grid) => grid.set_node(node_id, rect),
+			Self::Bvh(bvh) => bvh.set_node(node_id, rect),
+		}
+	}
+
+	pub fn retain_nodes(&mut self, f: impl Fn(&ArenaId<Node>) -> bool) {
+		match self {
+			Self::Grid(grid) => grid.retain_nodes(f),
+			Self::Bvh(bvh) => bvh.retain_nodes(f),
+		}
+	}
+
+	/// Pairs of nodes that may be touching, each pair once.
+	pub fn pairs(&self) -> Vec<(ArenaId<Node>, ArenaId<Node>)> {
+		match self {
+			Self::Grid(grid) => {
+				let mut seen = HashSet::new();
+				let mut pairs = Vec::new();
+				for cell in grid.cells.values() {
+					for (i, node1) in cell.iter().enumerate() {
+						for node2 in &cell[i + 1..] {
+							let key = if node1.index() <= node2.index() { (*node1, *node2) } else { (*node2, *node1) };
+							if seen.insert(key) {
+								pairs.push((*node1, *node2));
+							}
+						}
+					}
+				}
+				pairs
+			}
+			Self::Bvh(bvh) => bvh.pairs(),
+		}
+	}
+
+	/// Nodes the ray may hit.
+	pub fn ray_candidates(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Vec<ArenaId<Node>> {
+		match self {
+			Self::Grid(grid) => grid.get_ray_nodes(origin, dir, max_dist),
+			Self::Bvh(bvh) => bvh.query_ray(origin, dir, max_dist),
+		}
+	}
+
+	/// Nodes that may overlap `aabb`.
+	pub fn aabb_candidates(&self, aabb: &AABB) -> Vec<ArenaId<Node>> {
+		match self {
+			Self::Grid(grid) => grid.get_aabb_nodes(aabb),
+			Self::Bvh(bvh) => bvh.query(aabb),
+		}
+	}
+}
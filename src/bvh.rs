@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+use crate::ArenaId;
+use crate::Node;
+use crate::AABB;
+
+/// How far the stored box of a leaf reaches past the node. Nodes moving
+/// within that distance don't change the tree.
+const FAT_MARGIN: f32 = 0.1;
+const NULL: usize = usize::MAX;
+
+#[derive(Debug, Clone)]
+struct TreeNode {
+	aabb: AABB,
+	parent: usize,
+	left: usize,
+	right: usize,
+	/// Leaves are at height 0.
+	height: i32,
+	/// Set for leaves, which have no children.
+	node_id: Option<ArenaId<Node>>,
+}
+
+impl TreeNode {
+	fn is_leaf(&self) -> bool {
+		self.left == NULL
+	}
+}
+
+fn area(aabb: &AABB) -> f32 {
+	let d = aabb.max - aabb.min;
+	2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+}
+
+fn encloses(outer: &AABB, inner: &AABB) -> bool {
+	outer.min.cmple(inner.min).all() && outer.max.cmpge(inner.max).all()
+}
+
+/// Dynamic bounding volume hierarchy over node boxes. Leaves store a box
+/// slightly larger than the node so small moves don't touch the tree, and
+/// parents are refit and rotated back into balance when a leaf is moved.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+	nodes: Vec<TreeNode>,
+	free: Vec<usize>,
+	root: Option<usize>,
+	leaves: HashMap<ArenaId<Node>, usize>,
+	rects: HashMap<ArenaId<Node>, AABB>,
+}
+
+impl Bvh {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn get_node_rect(&self, node_id: ArenaId<Node>) -> Option<&AABB> {
+		self.rects.get(&node_id)
+	}
+
+	pub fn set_node(&mut self, node_id: ArenaId<Node>, rect: AABB) {
+		if let Some(leaf) = self.leaves.get(&node_id) {
+			if encloses(&self.nodes[*leaf].aabb, &rect) {
+				self.rects.insert(node_id, rect);
+				return;
+			}
+			self.rem_node(node_id);
+		}
+		let margin = Vec3::splat(FAT_MARGIN);
+		let leaf = self.allocate(TreeNode {
+			aabb: AABB::new(rect.min - margin, rect.max + margin),
+			parent: NULL,
+			left: NULL,
+			right: NULL,
+			height: 0,
+			node_id: Some(node_id),
+		});
+		self.insert_leaf(leaf);
+		self.leaves.insert(node_id, leaf);
+		self.rects.insert(node_id, rect);
+	}
+
+	pub fn rem_node(&mut self, node_id: ArenaId<Node>) {
+		let leaf = match self.leaves.remove(&node_id) {
+			Some(leaf) => leaf,
+			None => return,
+		};
+		self.rects.remove(&node_id);
+		self.remove_leaf(leaf);
+		self.free.push(leaf);
+	}
+
+	pub fn retain_nodes(&mut self, f: impl Fn(&ArenaId<Node>) -> bool) {
+		let mut removed: Vec<_> = self.leaves.keys().filter(|node_id| !f(node_id)).copied().collect();
+		// Removal order shapes the tree, so keep it independent of the map
+		removed.sort_by_key(|node_id| node_id.index());
+		for node_id in removed {
+			self.rem_node(node_id);
+		}
+	}
+
+	/// Nodes whose boxes overlap `aabb`.
+	pub fn query(&self, aabb: &AABB) -> Vec<ArenaId<Node>> {
+		self.collect(|node| node.aabb.intersects(aabb))
+	}
+
+	/// Nodes whose boxes the ray passes within `max_dist`.
+	pub fn query_ray(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Vec<ArenaId<Node>> {
+		self.collect(|node| node.aabb.ray_range(origin, dir, max_dist).is_some())
+	}
+
+	/// Every pair of nodes with overlapping boxes, each pair once, ordered by
+	/// node index.
+	pub fn pairs(&self) -> Vec<(ArenaId<Node>, ArenaId<Node>)> {
+		let mut leaves: Vec<_> = self.leaves.iter().map(|(node_id, leaf)| (*node_id, *leaf)).collect();
+		leaves.sort_by_key(|(node_id, _)| node_id.index());
+		let mut pairs = Vec::new();
+		for (node_id, leaf) in leaves {
+			let mut others = self.query(&self.nodes[leaf].aabb);
+			others.sort_by_key(|other_id| other_id.index());
+			for other_id in others {
+				if node_id.index() < other_id.index() {
+					pairs.push((node_id, other_id));
+				}
+			}
+		}
+		pairs
+	}
+
+	fn collect(&self, visit: impl Fn(&TreeNode) -> bool) -> Vec<ArenaId<Node>> {
+		let mut found = Vec::new();
+		let mut stack: Vec<usize> = self.root.into_iter().collect();
+		while let Some(index) = stack.pop() {
+			let node = &self.nodes[index];
+			if !visit(node) {
+				continue;
+			}
+			match node.node_id {
+				Some(node_id) => found.push(node_id),
+				None => {
+					stack.push(node.left);
+					stack.push(node.right);
+				}
+			}
+		}
+		found
+	}
+
+	fn allocate(&mut self, node: TreeNode) -> usize {
+		match self.free.pop() {
+			Some(index) => {
+				self.nodes[index] = node;
+				index
+			}
+			None => {
+				self.nodes.push(node);
+				self.nodes.len() - 1
+			}
+		}
+	}
+
+	fn insert_leaf(&mut self, leaf: usize) {
+		let root = match self.root {
+			Some(root) => root,
+			None => {
+				self.root = Some(leaf);
+				return;
+			}
+		};
+
+		// Walk down to the sibling that grows the tree's surface area the least
+		let leaf_aabb = self.nodes[leaf].aabb.clone();
+		let mut index = root;
+		while !self.nodes[index].is_leaf() {
+			let node = &self.nodes[index];
+			let combined = area(&node.aabb.merge(&leaf_aabb));
+			let cost = 2.0 * combined;
+			let inheritance = 2.0 * (combined - area(&node.aabb));
+
+			let child_cost = |child: usize| {
+				let child = &self.nodes[child];
+				let merged = area(&child.aabb.merge(&leaf_aabb));
+				if child.is_leaf() {
+					merged + inheritance
+				} else {
+					merged - area(&child.aabb) + inheritance
+				}
+			};
+			let left_cost = child_cost(node.left);
+			let right_cost = child_cost(node.right);
+			if cost < left_cost && cost < right_cost {
+				break;
+			}
+			index = if left_cost < right_cost { node.left } else { node.right };
+		}
+
+		let sibling = index;
+		let old_parent = self.nodes[sibling].parent;
+		let new_parent = self.allocate(TreeNode {
+			aabb: self.nodes[sibling].aabb.merge(&leaf_aabb),
+			parent: old_parent,
+			left: sibling,
+			right: leaf,
+			height: self.nodes[sibling].height + 1,
+			node_id: None,
+		});
+		self.nodes[sibling].parent = new_parent;
+		self.nodes[leaf].parent = new_parent;
+		if old_parent == NULL {
+			self.root = Some(new_parent);
+		} else if self.nodes[old_parent].left == sibling {
+			self.nodes[old_parent].left = new_parent;
+		} else {
+			self.nodes[old_parent].right = new_parent;
+		}
+		self.refit(old_parent);
+	}
+
+	fn remove_leaf(&mut self, leaf: usize) {
+		if self.root == Some(leaf) {
+			self.root = None;
+			return;
+		}
+		let parent = self.nodes[leaf].parent;
+		let grand_parent = self.nodes[parent].parent;
+		let sibling = if self.nodes[parent].left == leaf { self.nodes[parent].right } else { self.nodes[parent].left };
+
+		if grand_parent == NULL {
+			self.root = Some(sibling);
+			self.nodes[sibling].parent = NULL;
+		} else {
+			if self.nodes[grand_parent].left == parent {
+				self.nodes[grand_parent].left = sibling;
+			} else {
+				self.nodes[grand_parent].right = sibling;
+			}
+			self.nodes[sibling].parent = grand_parent;
+			self.refit(grand_parent);
+		}
+		self.free.push(parent);
+	}
+
+	/// Recomputes the boxes and heights from `index` up to the root,
+	/// rotating unbalanced nodes on the way.
+	fn refit(&mut self, mut index: usize) {
+		while index != NULL {
+			index = self.balance(index);
+			self.update(index);
+			index = self.nodes[index].parent;
+		}
+	}
+
+	fn update(&mut self, index: usize) {
+		let (left, right) = (self.nodes[index].left, self.nodes[index].right);
+		self.nodes[index].aabb = self.nodes[left].aabb.merge(&self.nodes[right].aabb);
+		self.nodes[index].height = 1 + self.nodes[left].height.max(self.nodes[right].height);
+	}
+
+	/// Lifts the taller child of `a` into its place when the children's
+	/// heights differ by more than one. Returns the node now at that place.
+	fn balance(&mut self, a: usize) -> usize {
+		if self.nodes[a].is_leaf() || self.nodes[a].height < 2 {
+			return a;
+		}
+		let (left, right) = (self.nodes[a].left, self.nodes[a].right);
+		let diff = self.nodes[right].height - self.nodes[left].height;
+		if diff > 1 {
+			self.rotate_up(a, right, false)
+		} else if diff < -1 {
+			self.rotate_up(a, left, true)
+		} else {
+			a
+		}
+	}
+
+	/// Moves `child` into the place of its parent `a`. `a` keeps the other
+	/// child and takes the shorter grandchild, `child` keeps the taller one.
+	fn rotate_up(&mut self, a: usize, child: usize, child_is_left: bool) -> usize {
+		let (first, second) = (self.nodes[child].left, self.nodes[child].right);
+		let (taller, shorter) = if self.nodes[first].height > self.nodes[second].height { (first, second) } else { (second, first) };
+
+		let parent = self.nodes[a].parent;
+		self.nodes[child].parent = parent;
+		self.nodes[a].parent = child;
+		if parent == NULL {
+			self.root = Some(child);
+		} else if self.nodes[parent].left == a {
+			self.nodes[parent].left = child;
+		} else {
+			self.nodes[parent].right = child;
+		}
+
+		self.nodes[child].left = a;
+		self.nodes[child].right = taller;
+		if child_is_left {
+			self.nodes[a].left = shorter;
+		} else {
+			self.nodes[a].right = shorter;
+		}
+		self.nodes[shorter].parent = a;
+		self.update(a);
+		self.update(child);
+		child
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Arena;
+
+	fn cube(center: Vec3, half: f32) -> AABB {
+		AABB::new(center - Vec3::splat(half), center + Vec3::splat(half))
+	}
+
+	#[test]
+	fn pairs_match_brute_force() {
+		let mut arena = Arena::new();
+		let mut bvh = Bvh::new();
+		let mut rects = Vec::new();
+		// A huge tile with small bodies scattered over it
+		let tile = arena.insert(Node::new());
+		bvh.set_node(tile, AABB::new(Vec3::new(-250.0, -1.0, -250.0), Vec3::new(250.0, 0.0, 250.0)));
+		rects.push((tile, AABB::new(Vec3::new(-250.0, -1.0, -250.0), Vec3::new(250.0, 0.0, 250.0))));
+		for i in 0..60 {
+			let id = arena.insert(Node::new());
+			let center = Vec3::new((i % 8) as f32 * 0.15, (i / 8) as f32 * 0.15 - 0.5, (i % 3) as f32 * 20.0);
+			rects.push((id, cube(center, 0.05)));
+			bvh.set_node(id, cube(center, 0.05));
+		}
+		// Moving a node keeps the tree in sync
+		let (moved, _) = rects[10];
+		bvh.set_node(moved, cube(Vec3::new(100.0, 50.0, 0.0), 0.05));
+		rects[10].1 = cube(Vec3::new(100.0, 50.0, 0.0), 0.05);
+		bvh.rem_node(rects[20].0);
+		rects.remove(20);
+
+		let mut expected = Vec::new();
+		for (i, (a, rect_a)) in rects.iter().enumerate() {
+			for (b, rect_b) in &rects[i + 1..] {
+				if rect_a.intersects(rect_b) {
+					expected.push((*a, *b));
+				}
+			}
+		}
+		// Fat boxes may report a few more candidates, never fewer
+		let pairs = bvh.pairs();
+		for (a, b) in &expected {
+			assert!(pairs.contains(&(*a, *b)) || pairs.contains(&(*b, *a)), "missing {:?} {:?}", a, b);
+		}
+		assert!(pairs.iter().all(|(a, b)| a != b));
+		let rect = bvh.get_node_rect(moved).unwrap();
+		assert!(rect.min.abs_diff_eq(Vec3::new(99.95, 49.95, -0.05), 1e-4));
+		assert!(bvh.get_node_rect(rects[0].0).is_some());
+	}
+
+	#[test]
+	fn tree_stays_balanced_as_bodies_move() {
+		let mut arena = Arena::new();
+		let mut bvh = Bvh::new();
+		let ids: Vec<_> = (0..256).map(|_| arena.insert(Node::new())).collect();
+		// Boxes inserted in sorted order along a line degenerate into a list
+		// without rotations
+		for (i, id) in ids.iter().enumerate() {
+			bvh.set_node(*id, cube(Vec3::new(i as f32, 0.0, 0.0), 0.4));
+		}
+		for step in 1..20 {
+			for (i, id) in ids.iter().enumerate() {
+				bvh.set_node(*id, cube(Vec3::new(i as f32, step as f32, 0.0), 0.4));
+			}
+		}
+		let height = bvh.nodes[bvh.root.unwrap()].height;
+		assert!(height <= 16, "height {}", height);
+	}
+
+	#[test]
+	fn pairs_do_not_depend_on_insertion_order() {
+		let mut arena = Arena::new();
+		let ids: Vec<_> = (0..40).map(|_| arena.insert(Node::new())).collect();
+		let rect = |i: usize| cube(Vec3::new((i % 7) as f32 * 0.5, (i / 7) as f32 * 0.5, 0.0), 0.3);
+		let mut forward = Bvh::new();
+		for (i, id) in ids.iter().enumerate() {
+			forward.set_node(*id, rect(i));
+		}
+		let mut backward = Bvh::new();
+		for (i, id) in ids.iter().enumerate().rev() {
+			backward.set_node(*id, rect(i));
+		}
+		assert!(!forward.pairs().is_empty());
+		assert_eq!(forward.pairs(), backward.pairs());
+	}
+
+	#[test]
+	fn ray_visits_only_boxes_on_its_way() {
+		let mut arena = Arena::new();
+		let mut bvh = Bvh::new();
+		let near = arena.insert(Node::new());
+		let far = arena.insert(Node::new());
+		let aside = arena.insert(Node::new());
+		bvh.set_node(near, cube(Vec3::new(0.0, 0.0, 5.0), 1.0));
+		bvh.set_node(far, cube(Vec3::new(0.0, 0.0, 50.0), 1.0));
+		bvh.set_node(aside, cube(Vec3::new(10.0, 0.0, 5.0), 1.0));
+
+		let found = bvh.query_ray(Vec3::ZERO, Vec3::Z, 100.0);
+		assert!(found.contains(&near) && found.contains(&far) && !found.contains(&aside));
+		assert_eq!(bvh.query_ray(Vec3::ZERO, Vec3::Z, 10.0), vec![near]);
+	}
+}
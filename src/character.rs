@@ -0,0 +1,508 @@
+use glam::Quat;
+use glam::Vec3;
+use crate::physics::collision_allowed;
+use crate::query::closest_on_segment;
+use crate::state::State;
+use crate::ArenaId;
+use crate::CharacterController;
+use crate::CollisionShape;
+use crate::Node;
+use crate::PhycisObjectType;
+use crate::AABB;
+
+const DEPENETRATION_ITERATIONS: usize = 4;
+const CLOSEST_POINT_ITERATIONS: usize = 8;
+
+/// Upright capsule described by the segment between its two sphere centers.
+#[derive(Debug, Clone)]
+struct Capsule {
+	radius: f32,
+	half_height: f32,
+}
+
+impl Capsule {
+	fn from_shape(shape: &CollisionShape) -> Self {
+		match shape {
+			CollisionShape::Capsule { radius, half_height } => Self {
+				radius: *radius,
+				half_height: *half_height,
+			},
+			CollisionShape::Sphere { radius } => Self {
+				radius: *radius,
+				half_height: 0.0,
+			},
+			CollisionShape::Box { .. } | CollisionShape::Compound { .. } => {
+				let size = shape.half_extents();
+				let radius = size.x.min(size.z);
+				Self {
+					radius,
+					half_height: (size.y - radius).max(0.0),
+				}
+			}
+		}
+	}
+
+	fn segment(&self, position: Vec3) -> (Vec3, Vec3) {
+		let offset = Vec3::Y * self.half_height;
+		(position - offset, position + offset)
+	}
+}
+
+/// Shape the character collides with. Spheres are capsules with both ends
+/// at the center.
+#[derive(Debug, Clone)]
+enum Obstacle {
+	Box { center: Vec3, rotation: Quat, half: Vec3 },
+	Capsule { a: Vec3, b: Vec3, radius: f32 },
+}
+
+impl Obstacle {
+	fn new(shape: &CollisionShape, center: Vec3, rotation: Quat) -> Self {
+		match shape {
+			CollisionShape::Capsule { radius, half_height } => {
+				let offset = rotation * Vec3::Y * *half_height;
+				Obstacle::Capsule { a: center - offset, b: center + offset, radius: *radius }
+			}
+			CollisionShape::Sphere { radius } => Obstacle::Capsule { a: center, b: center, radius: *radius },
+			CollisionShape::Box { .. } | CollisionShape::Compound { .. } => Obstacle::Box {
+				center,
+				rotation,
+				half: shape.half_extents(),
+			},
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+struct Contact {
+	normal: Vec3,
+	/// Negative when the capsule is only within the skin distance.
+	depth: f32,
+	point: Vec3,
+	/// Touching an edge or corner of the box rather than a face.
+	edge: bool,
+}
+
+#[derive(Debug, Clone)]
+struct MoveResult {
+	grounded: bool,
+	ground_normal: Vec3,
+	hit_wall: bool,
+}
+
+impl Default for MoveResult {
+	fn default() -> Self {
+		Self {
+			grounded: false,
+			ground_normal: Vec3::Y,
+			hit_wall: false,
+		}
+	}
+}
+
+impl MoveResult {
+	fn merge(&mut self, other: MoveResult) {
+		if other.grounded && (!self.grounded || other.ground_normal.y > self.ground_normal.y) {
+			self.ground_normal = other.ground_normal;
+		}
+		self.grounded |= other.grounded;
+		self.hit_wall |= other.hit_wall;
+	}
+}
+
+fn capsule_contact(capsule: &Capsule, position: Vec3, obstacle: &Obstacle, skin: f32) -> Option<Contact> {
+	match obstacle {
+		Obstacle::Box { center, rotation, half } => box_contact(capsule, position, *center, *rotation, *half, skin),
+		Obstacle::Capsule { a, b, radius } => rounded_contact(capsule, position, *a, *b, *radius, skin),
+	}
+}
+
+fn box_contact(capsule: &Capsule, position: Vec3, center: Vec3, rotation: Quat, half: Vec3, skin: f32) -> Option<Contact> {
+	let inv = rotation.inverse();
+	let (a, b) = capsule.segment(position);
+	let a = inv * (a - center);
+	let b = inv * (b - center);
+
+	// Alternate between the closest points on the segment and on the box
+	let mut on_segment = (a + b) * 0.5;
+	let mut on_box = on_segment.clamp(-half, half);
+	for _ in 0..CLOSEST_POINT_ITERATIONS {
+		on_segment = closest_on_segment(a, b, on_box);
+		on_box = on_segment.clamp(-half, half);
+	}
+
+	let diff = on_segment - on_box;
+	let distance = diff.length();
+	if distance > capsule.radius + skin {
+		return None;
+	}
+
+	let (normal, depth) = if distance > 1e-5 {
+		(diff / distance, capsule.radius - distance)
+	} else {
+		// Segment is inside the box, leave through the closest face
+		let penetration = half - on_segment.abs();
+		let axis = if penetration.x < penetration.y && penetration.x < penetration.z {
+			0
+		} else if penetration.y < penetration.z {
+			1
+		} else {
+			2
+		};
+		let mut normal = Vec3::ZERO;
+		normal[axis] = if on_segment[axis] < 0.0 { -1.0 } else { 1.0 };
+		(normal, penetration[axis] + capsule.radius)
+	};
+
+	let clamped_axes = (0..3)
+		.filter(|i| on_box[*i].abs() >= half[*i] - 1e-4)
+		.count();
+
+	Some(Contact {
+		normal: rotation * normal,
+		depth,
+		point: center + rotation * on_box,
+		edge: clamped_axes >= 2,
+	})
+}
+
+/// Contact with a capsule or sphere obstacle running from `a` to `b`.
+fn rounded_contact(capsule: &Capsule, position: Vec3, a: Vec3, b: Vec3, radius: f32, skin: f32) -> Option<Contact> {
+	let (start, end) = capsule.segment(position);
+
+	// Alternate between the closest points on the two segments
+	let mut on_other = closest_on_segment(a, b, position);
+	let mut on_segment = closest_on_segment(start, end, on_other);
+	for _ in 0..CLOSEST_POINT_ITERATIONS {
+		on_other = closest_on_segment(a, b, on_segment);
+		on_segment = closest_on_segment(start, end, on_other);
+	}
+
+	let diff = on_segment - on_other;
+	let distance = diff.length();
+	if distance > capsule.radius + radius + skin {
+		return None;
+	}
+	// Coinciding axes, push out sideways
+	let normal = match distance > 1e-5 {
+		true => diff / distance,
+		false => Vec3::new(position.x - a.x, 0.0, position.z - a.z).try_normalize().unwrap_or(Vec3::X),
+	};
+
+	Some(Contact {
+		normal,
+		depth: capsule.radius + radius - distance,
+		point: on_other + normal * radius,
+		edge: false,
+	})
+}
+
+struct Mover<'a> {
+	capsule: Capsule,
+	obstacles: &'a [Obstacle],
+	/// Smallest normal y component of walkable ground.
+	min_ground_y: f32,
+	/// Edges up to this height above the feet are walked over.
+	step_height: f32,
+	skin: f32,
+}
+
+impl<'a> Mover<'a> {
+	/// Pushes the capsule out of the obstacles. Walkable ground and low
+	/// ledges push straight up so the character does not creep down slopes,
+	/// steep surfaces push sideways so they can't be climbed.
+	fn depenetrate(&self, position: &mut Vec3) -> MoveResult {
+		let mut result = MoveResult::default();
+		for _ in 0..DEPENETRATION_ITERATIONS {
+			let mut pushed = false;
+			for obstacle in self.obstacles {
+				let contact = match capsule_contact(&self.capsule, *position, obstacle, self.skin) {
+					Some(contact) => contact,
+					None => continue,
+				};
+				let normal = contact.normal;
+				let feet = position.y - self.capsule.half_height - self.capsule.radius;
+				let ledge = contact.edge && normal.y > 0.0 && contact.point.y - feet <= self.step_height;
+
+				if normal.y >= self.min_ground_y || ledge {
+					result.merge(MoveResult {
+						grounded: true,
+						ground_normal: normal,
+						hit_wall: false,
+					});
+					if contact.depth > 0.0 {
+						position.y += (contact.depth / normal.y).min(contact.depth.max(self.step_height));
+						pushed = true;
+					}
+					continue;
+				}
+
+				if normal.y > -self.min_ground_y {
+					result.hit_wall = true;
+				}
+				if contact.depth <= 0.0 {
+					continue;
+				}
+				let horizontal = Vec3::new(normal.x, 0.0, normal.z);
+				if normal.y > 0.0 && horizontal.length() > 1e-4 {
+					*position += horizontal.normalize() * (contact.depth / horizontal.length());
+				} else {
+					*position += normal * contact.depth;
+				}
+				pushed = true;
+			}
+			if !pushed {
+				break;
+			}
+		}
+		result
+	}
+
+	/// Moves in steps no longer than half the radius so thin geometry is not skipped.
+	fn move_by(&self, start: Vec3, delta: Vec3) -> (Vec3, MoveResult) {
+		let steps = (delta.length() / (self.capsule.radius * 0.5)).ceil().max(1.0) as usize;
+		let step = delta / steps as f32;
+		let mut position = start;
+		let mut result = MoveResult::default();
+		for _ in 0..steps {
+			position += step;
+			result.merge(self.depenetrate(&mut position));
+		}
+		(position, result)
+	}
+
+	/// Retries a blocked horizontal move from `step_height` higher up and
+	/// settles back down. Fails when there is no walkable ground to land on.
+	fn step_up(&self, start: Vec3, horizontal: Vec3, step_height: f32) -> Option<Vec3> {
+		let (raised, _) = self.move_by(start, Vec3::Y * step_height);
+		let (across, _) = self.move_by(raised, horizontal);
+		let (landed, result) = self.move_by(across, Vec3::NEG_Y * (raised.y - start.y));
+		match result.grounded {
+			true => Some(landed),
+			false => None,
+		}
+	}
+}
+
+/// Shapes within `reach` of the node. Scenes with a broad phase only look at
+/// the nodes it finds around the character.
+fn collect_obstacles(state: &State, node_id: ArenaId<Node>, node: &Node, reach: f32) -> Vec<Obstacle> {
+	let bounds = AABB::new(node.translation - Vec3::splat(reach), node.translation + Vec3::splat(reach));
+	let candidates = node.scene_id
+		.and_then(|scene_id| state.broad_phases.get(&scene_id))
+		.map(|broad_phase| broad_phase.aabb_candidates(&bounds));
+	let nearby: Box<dyn Iterator<Item = (ArenaId<Node>, &Node)>> = match candidates {
+		Some(ids) => Box::new(ids.into_iter().filter_map(|id| Some((id, state.nodes.get(&id)?)))),
+		None => Box::new(state.nodes.iter()),
+	};
+
+	let mut obstacles = Vec::new();
+	for (other_id, other) in nearby {
+		if other_id == node_id || other.scene_id != node.scene_id {
+			continue;
+		}
+		// Dynamic bodies are pushed away by the physics system instead
+		if other.physics.typ == PhycisObjectType::Dynamic || other.physics.sensor {
+			continue;
+		}
+		let shape = match &other.collision_shape {
+			Some(shape) => shape,
+			None => continue,
+		};
+		if !collision_allowed(node_id, node, other_id, other) {
+			continue;
+		}
+		for part in shape.parts() {
+			let center = other.translation + other.rotation * part.offset;
+			let half = part.shape.half_extents();
+			if center.distance(node.translation) > half.length() + reach {
+				continue;
+			}
+			obstacles.push(Obstacle::new(&part.shape, center, other.rotation * part.rotation));
+		}
+	}
+	obstacles
+}
+
+fn move_character(controller: &mut CharacterController, state: &mut State, dt: f32) {
+	let node = match state.nodes.get(&controller.node_id) {
+		Some(node) => node,
+		None => return,
+	};
+	let capsule = match &node.collision_shape {
+		Some(shape) => Capsule::from_shape(shape),
+		None => return,
+	};
+
+	let start = node.translation;
+	let delta = controller.velocity * dt;
+	let reach = capsule.radius + capsule.half_height + delta.length() + controller.step_height + controller.snap_distance;
+	let obstacles = collect_obstacles(state, controller.node_id, node, reach);
+	let mover = Mover {
+		capsule,
+		obstacles: &obstacles,
+		min_ground_y: controller.max_slope.cos(),
+		step_height: match controller.grounded {
+			true => controller.step_height,
+			false => 0.0,
+		},
+		skin: controller.skin_width,
+	};
+
+	// Horizontal and vertical motion are separate so a blocked walk can be
+	// retried as a step up without the fall getting in the way.
+	let horizontal = Vec3::new(delta.x, 0.0, delta.z);
+	let (mut position, result) = mover.move_by(start, horizontal);
+	if result.hit_wall && controller.grounded && controller.step_height > 0.0 {
+		if let Some(stepped) = mover.step_up(start, horizontal, controller.step_height) {
+			if (stepped - start).length() > (position - start).length() + 1e-4 {
+				position = stepped;
+			}
+		}
+	}
+	let (moved, _) = mover.move_by(position, Vec3::Y * delta.y);
+	position = moved;
+
+	let mut result = mover.depenetrate(&mut position);
+	// Stick to the ground when walking down slopes and stairs
+	if !result.grounded && controller.grounded && controller.velocity.y <= 0.0 {
+		let (snapped, snap_result) = mover.move_by(position, Vec3::NEG_Y * controller.snap_distance);
+		if snap_result.grounded {
+			position = snapped;
+			result = snap_result;
+		}
+	}
+
+	controller.grounded = result.grounded;
+	controller.ground_normal = result.ground_normal;
+	controller.actual_velocity = (position - start) / dt;
+	if let Some(node) = state.nodes.get_mut(&controller.node_id) {
+		node.translation = position;
+	}
+}
+
+/// Moves every character controller by its velocity for one physics step.
+pub fn move_characters(state: &mut State, dt: f32) {
+	let ids: Vec<_> = state.characters.iter().map(|(id, _)| id).collect();
+	for id in ids {
+		let mut controller = match state.characters.get(&id) {
+			Some(controller) => controller.clone(),
+			None => continue,
+		};
+		move_character(&mut controller, state, dt);
+		if let Some(c) = state.characters.get_mut(&id) {
+			*c = controller;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::PhysicsProps;
+
+	fn static_box(state: &mut State, translation: Vec3, rotation: Quat, size: Vec3) -> ArenaId<Node> {
+		state.nodes.insert(Node {
+			translation,
+			rotation,
+			physics: PhysicsProps {
+				typ: PhycisObjectType::Static,
+				..Default::default()
+			},
+			collision_shape: Some(CollisionShape::Box { size }),
+			..Default::default()
+		})
+	}
+
+	fn character(state: &mut State, translation: Vec3) -> ArenaId<CharacterController> {
+		let node_id = state.nodes.insert(Node {
+			translation,
+			physics: PhysicsProps {
+				typ: PhycisObjectType::Kinematic,
+				..Default::default()
+			},
+			collision_shape: Some(CollisionShape::Capsule { radius: 0.5, half_height: 0.5 }),
+			..Default::default()
+		});
+		state.characters.insert(CharacterController::new(node_id))
+	}
+
+	fn walk(state: &mut State, id: ArenaId<CharacterController>, velocity: Vec3, steps: usize) {
+		for _ in 0..steps {
+			let controller = state.characters.get_mut(&id).unwrap();
+			controller.velocity.x = velocity.x;
+			controller.velocity.z = velocity.z;
+			controller.velocity.y = match controller.grounded {
+				true => 0.0,
+				false => controller.velocity.y - 9.81 / 60.0,
+			};
+			move_characters(state, 1.0 / 60.0);
+		}
+	}
+
+	fn translation(state: &State, id: ArenaId<CharacterController>) -> Vec3 {
+		let node_id = state.characters.get(&id).unwrap().node_id;
+		state.nodes.get(&node_id).unwrap().translation
+	}
+
+	#[test]
+	fn character_lands_and_climbs_step() {
+		let mut state = State::default();
+		static_box(&mut state, Vec3::ZERO, Quat::IDENTITY, Vec3::new(10.0, 0.5, 10.0));
+		// 0.25 high step starting at x = 2
+		static_box(&mut state, Vec3::new(4.0, 0.625, 0.0), Quat::IDENTITY, Vec3::new(2.0, 0.125, 2.0));
+		let id = character(&mut state, Vec3::new(0.0, 3.0, 0.0));
+
+		walk(&mut state, id, Vec3::ZERO, 60);
+		let controller = state.characters.get(&id).unwrap();
+		assert!(controller.grounded);
+		assert_eq!(controller.ground_normal, Vec3::Y);
+		assert!((translation(&state, id).y - 1.5).abs() < 0.05, "{:?}", translation(&state, id));
+
+		walk(&mut state, id, Vec3::new(3.0, 0.0, 0.0), 60);
+		let position = translation(&state, id);
+		assert!(position.x > 2.5, "Character should walk onto the step {:?}", position);
+		assert!((position.y - 1.75).abs() < 0.05, "{:?}", position);
+		assert!(state.characters.get(&id).unwrap().grounded);
+	}
+
+	#[test]
+	fn character_slides_along_wall_and_not_up_steep_slope() {
+		let mut state = State::default();
+		static_box(&mut state, Vec3::ZERO, Quat::IDENTITY, Vec3::new(20.0, 0.5, 20.0));
+		static_box(&mut state, Vec3::new(3.0, 2.5, 0.0), Quat::IDENTITY, Vec3::new(0.5, 2.0, 10.0));
+		let id = character(&mut state, Vec3::new(0.0, 1.5, 0.0));
+
+		walk(&mut state, id, Vec3::new(3.0, 0.0, 3.0), 60);
+		let position = translation(&state, id);
+		assert!((position.x - 2.0).abs() < 0.05, "Wall should stop the character {:?}", position);
+		assert!(position.z > 2.5, "Character should slide along the wall {:?}", position);
+
+		// 60 degree ramp rising towards negative x
+		let mut state = State::default();
+		static_box(&mut state, Vec3::ZERO, Quat::IDENTITY, Vec3::new(20.0, 0.5, 20.0));
+		let ramp = Quat::from_rotation_z(-60.0_f32.to_radians());
+		static_box(&mut state, Vec3::new(-4.0, 0.5, 0.0), ramp, Vec3::new(3.0, 0.2, 3.0));
+		let id = character(&mut state, Vec3::new(0.0, 1.5, 0.0));
+
+		walk(&mut state, id, Vec3::new(-3.0, 0.0, 0.0), 120);
+		let position = translation(&state, id);
+		assert!(position.y < 1.6, "Character climbed a steep slope {:?}", position);
+	}
+
+	#[test]
+	fn character_slides_around_other_character() {
+		let mut state = State::default();
+		static_box(&mut state, Vec3::ZERO, Quat::IDENTITY, Vec3::new(20.0, 0.5, 20.0));
+		let other = character(&mut state, Vec3::new(2.0, 1.5, 0.3));
+		let id = character(&mut state, Vec3::new(0.0, 1.5, 0.0));
+
+		// A box around the other capsule would stop the character flat
+		walk(&mut state, id, Vec3::new(3.0, 0.0, 0.0), 120);
+		let position = translation(&state, id);
+		let other = translation(&state, other);
+		assert!(position.x > 3.0, "Character should slide around the capsule {:?}", position);
+		assert!(position.z < 0.0, "{:?}", position);
+		assert_eq!(other, Vec3::new(2.0, 1.5, 0.3));
+	}
+}
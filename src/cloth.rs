@@ -0,0 +1,567 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use glam::Mat3;
+use glam::Mat4;
+use glam::Quat;
+use glam::Vec3;
+use crate::query::closest_on_segment;
+use crate::state::State;
+use crate::Arena;
+use crate::ArenaId;
+use crate::Cloth;
+use crate::CollisionShape;
+use crate::Node;
+use crate::Primitive;
+use crate::PrimitiveTopology;
+use crate::Scene;
+use crate::AABB;
+
+/// Collider part the cloth is kept out of, in world space.
+#[derive(Debug, Clone)]
+enum Obstacle {
+	Sphere { center: Vec3, radius: f32 },
+	Capsule { a: Vec3, b: Vec3, radius: f32 },
+	Box { center: Vec3, rotation: Quat, half: Vec3 },
+}
+
+impl Obstacle {
+	/// Moves `point` out to `thickness` from the surface. Returns the surface
+	/// normal when the point was pushed.
+	fn push_out(&self, point: &mut Vec3, thickness: f32) -> Option<Vec3> {
+		match self {
+			Obstacle::Sphere { center, radius } => push_out_of_sphere(point, *center, radius + thickness),
+			Obstacle::Capsule { a, b, radius } => {
+				let center = closest_on_segment(*a, *b, *point);
+				push_out_of_sphere(point, center, radius + thickness)
+			}
+			Obstacle::Box { center, rotation, half } => {
+				let local = rotation.inverse() * (*point - *center);
+				let clamped = local.clamp(-*half, *half);
+				let diff = local - clamped;
+				let distance = diff.length();
+				let (normal, pushed) = if distance > 1e-6 {
+					if distance >= thickness {
+						return None;
+					}
+					let normal = diff / distance;
+					(normal, clamped + normal * thickness)
+				} else {
+					// Inside the box, leave through the closest face
+					let penetration = *half - local.abs();
+					let axis = if penetration.x < penetration.y && penetration.x < penetration.z {
+						0
+					} else if penetration.y < penetration.z {
+						1
+					} else {
+						2
+					};
+					let mut normal = Vec3::ZERO;
+					normal[axis] = if local[axis] < 0.0 { -1.0 } else { 1.0 };
+					let mut pushed = local;
+					pushed[axis] = normal[axis] * (half[axis] + thickness);
+					(normal, pushed)
+				};
+				*point = *center + *rotation * pushed;
+				Some(*rotation * normal)
+			}
+		}
+	}
+}
+
+fn push_out_of_sphere(point: &mut Vec3, center: Vec3, radius: f32) -> Option<Vec3> {
+	let diff = *point - center;
+	let distance = diff.length();
+	if distance >= radius {
+		return None;
+	}
+	let normal = if distance > 1e-6 { diff / distance } else { Vec3::Y };
+	*point = center + normal * radius;
+	Some(normal)
+}
+
+/// Colliders near `bounds`, found through the scene's broad phase. Scenes
+/// that haven't been stepped yet have none, so all their nodes are tested.
+fn collect_obstacles(state: &State, cloth: &Cloth, scene_id: ArenaId<Scene>, bounds: &AABB) -> Vec<Obstacle> {
+	let nearby: Box<dyn Iterator<Item = (ArenaId<Node>, &Node)>> = match state.broad_phases.get(&scene_id) {
+		Some(broad_phase) => Box::new(broad_phase
+			.aabb_candidates(bounds)
+			.into_iter()
+			.filter_map(|node_id| Some((node_id, state.nodes.get(&node_id)?)))),
+		None => Box::new(state.nodes.iter()),
+	};
+	let mut obstacles = Vec::new();
+	for (node_id, node) in nearby {
+		if node_id == cloth.node_id || node.scene_id != Some(scene_id) || !cloth.filter.accepts(node_id, node) {
+			continue;
+		}
+		let shape = match &node.collision_shape {
+			Some(shape) => shape,
+			None => continue,
+		};
+		let (scale, rotation, translation) = node.global_transform.to_scale_rotation_translation();
+		let shape = shape.scaled(scale);
+		if !shape.bounds(translation, rotation).intersects(bounds) {
+			continue;
+		}
+		for part in shape.parts() {
+			let center = translation + rotation * part.offset;
+			let rotation = rotation * part.rotation;
+			obstacles.push(match part.shape {
+				CollisionShape::Sphere { radius } => Obstacle::Sphere { center, radius },
+				CollisionShape::Capsule { radius, half_height } => Obstacle::Capsule {
+					a: center - rotation * Vec3::Y * half_height,
+					b: center + rotation * Vec3::Y * half_height,
+					radius,
+				},
+				CollisionShape::Box { size } => Obstacle::Box { center, rotation, half: size },
+				CollisionShape::Compound { .. } => continue,
+			});
+		}
+	}
+	obstacles
+}
+
+/// Triangles, or lines for ropes, of the primitive as vertex indices.
+fn elements(primitive: &Primitive) -> (Vec<[usize; 3]>, Vec<[usize; 2]>) {
+	let indices: Vec<usize> = match primitive.indices.is_empty() {
+		true => (0..primitive.vertices.len()).collect(),
+		false => primitive.indices.iter().map(|index| *index as usize).collect(),
+	};
+	match primitive.topology {
+		PrimitiveTopology::TriangleList => (indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(), vec![]),
+		PrimitiveTopology::TriangleStrip => {
+			// Every other triangle of a strip is flipped to keep the winding
+			let triangles = indices.windows(3).enumerate().map(|(i, t)| match i % 2 {
+				0 => [t[0], t[1], t[2]],
+				_ => [t[1], t[0], t[2]],
+			}).collect();
+			(triangles, vec![])
+		}
+		PrimitiveTopology::LineList => (vec![], indices.chunks_exact(2).map(|l| [l[0], l[1]]).collect()),
+		PrimitiveTopology::LineStrip => (vec![], indices.windows(2).map(|l| [l[0], l[1]]).collect()),
+		PrimitiveTopology::PointList => (vec![], vec![]),
+	}
+}
+
+/// Builds the particles and constraints from the primitive's current vertices.
+fn setup(cloth: &mut Cloth, primitive: &Primitive, transform: Mat4, state: &State) {
+	let mut welded: HashMap<[u32; 3], usize> = HashMap::new();
+	cloth.particles.clear();
+	cloth.vertex_particles.clear();
+	for vertex in &primitive.vertices {
+		let key = vertex.map(f32::to_bits);
+		let particle = *welded.entry(key).or_insert_with(|| {
+			cloth.particles.push(transform.transform_point3(Vec3::from(*vertex)));
+			cloth.particles.len() - 1
+		});
+		cloth.vertex_particles.push(particle);
+	}
+	cloth.previous = cloth.particles.clone();
+
+	// Edges, and for each edge the vertices opposite to it
+	let (triangles, lines) = elements(primitive);
+	let mut edges: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+	let mut edge = |a: usize, b: usize, opposite: Option<usize>| {
+		let (a, b) = (cloth.vertex_particles[a], cloth.vertex_particles[b]);
+		if a != b {
+			let opposites = edges.entry((a.min(b), a.max(b))).or_default();
+			opposites.extend(opposite.map(|opposite| cloth.vertex_particles[opposite]));
+		}
+	};
+	for [a, b, c] in &triangles {
+		edge(*a, *b, Some(*c));
+		edge(*b, *c, Some(*a));
+		edge(*c, *a, Some(*b));
+	}
+	for [a, b] in &lines {
+		edge(*a, *b, None);
+	}
+
+	let particles = &cloth.particles;
+	let constraint = |a: usize, b: usize| (a, b, particles[a].distance(particles[b]));
+	cloth.edges = edges.keys().map(|(a, b)| constraint(*a, *b)).collect();
+	// Cloth bends around edges shared by two triangles, ropes around every particle
+	cloth.bends = match triangles.is_empty() {
+		false => edges.values().filter(|opposites| opposites.len() == 2).map(|o| constraint(o[0], o[1])).collect(),
+		true => {
+			let mut neighbors: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+			for (a, b) in edges.keys() {
+				neighbors.entry(*a).or_default().push(*b);
+				neighbors.entry(*b).or_default().push(*a);
+			}
+			neighbors.values().filter(|n| n.len() == 2).map(|n| constraint(n[0], n[1])).collect()
+		}
+	};
+
+	for pin in &mut cloth.pins {
+		let node = match state.nodes.get(&pin.node_id) {
+			Some(node) => node,
+			None => continue,
+		};
+		if let (None, Some(particle)) = (pin.offset, cloth.vertex_particles.get(pin.vertex)) {
+			pin.offset = Some(node.global_transform.inverse().transform_point3(cloth.particles[*particle]));
+		}
+	}
+}
+
+/// Simulated vertices and normals in the local space of the cloth's node.
+struct ClothVertices {
+	vertices: Vec<[f32; 3]>,
+	normals: Vec<[f32; 3]>,
+}
+
+fn solve_distance(particles: &mut [Vec3], weights: &[f32], (a, b, rest): (usize, usize, f32), stiffness: f32) {
+	let w = weights[a] + weights[b];
+	let delta = particles[b] - particles[a];
+	let distance = delta.length();
+	if w == 0.0 || distance < 1e-6 {
+		return;
+	}
+	let correction = delta * ((distance - rest) / (distance * w) * stiffness);
+	particles[a] += correction * weights[a];
+	particles[b] -= correction * weights[b];
+}
+
+/// Advances the cloth by one step.
+fn step_cloth(cloth: &mut Cloth, state: &State, dt: f32) -> Option<ClothVertices> {
+	let node = state.nodes.get(&cloth.node_id)?;
+	let scene_id = node.scene_id?;
+	let scene = state.scenes.get(&scene_id)?;
+	if !scene.physics_on {
+		return None;
+	}
+	let transform = node.global_transform;
+	let primitive = state.meshes.get(&cloth.mesh_id)?.primitives.get(cloth.primitive)?;
+	if cloth.vertex_particles.len() != primitive.vertices.len() {
+		setup(cloth, primitive, transform, state);
+	}
+	let count = cloth.particles.len();
+	if count == 0 {
+		return None;
+	}
+
+	let mut weights = vec![1.0; count];
+	let mut targets = Vec::new();
+	for pin in &cloth.pins {
+		let (node, particle, offset) = match (state.nodes.get(&pin.node_id), cloth.vertex_particles.get(pin.vertex), pin.offset) {
+			(Some(node), Some(particle), Some(offset)) => (node, particle, offset),
+			_ => continue,
+		};
+		weights[*particle] = 0.0;
+		targets.push((*particle, node.global_transform.transform_point3(offset)));
+	}
+
+	// Air pushes on triangles along their normals
+	let (triangles, _) = elements(primitive);
+	let triangles: Vec<[usize; 3]> = triangles.iter().map(|t| t.map(|vertex| cloth.vertex_particles[vertex])).collect();
+	let mut accelerations = vec![scene.gravity; count];
+	if cloth.wind != Vec3::ZERO && cloth.drag > 0.0 {
+		let mut pushes = vec![(Vec3::ZERO, 0); count];
+		for triangle in &triangles {
+			let [a, b, c] = triangle.map(|particle| cloth.particles[particle]);
+			let normal = (b - a).cross(c - a).normalize_or_zero();
+			let velocity = triangle.iter().map(|particle| cloth.particles[*particle] - cloth.previous[*particle]).sum::<Vec3>() / (3.0 * dt);
+			let push = normal * (cloth.wind - velocity).dot(normal) * cloth.drag;
+			for particle in triangle {
+				pushes[*particle].0 += push;
+				pushes[*particle].1 += 1;
+			}
+		}
+		for (acceleration, (push, n)) in accelerations.iter_mut().zip(pushes) {
+			if n > 0 {
+				*acceleration += push / n as f32;
+			}
+		}
+	}
+
+	// Verlet integration, pinned particles follow their nodes
+	for i in 0..count {
+		let velocity = (cloth.particles[i] - cloth.previous[i]) * (1.0 - cloth.damping.clamp(0.0, 1.0));
+		cloth.previous[i] = cloth.particles[i];
+		if weights[i] > 0.0 {
+			cloth.particles[i] += velocity + accelerations[i] * dt * dt;
+		}
+	}
+	for (particle, target) in targets {
+		cloth.particles[particle] = target;
+	}
+
+	let mut bounds = AABB::new(cloth.particles[0], cloth.particles[0]);
+	for (particle, previous) in cloth.particles.iter().zip(&cloth.previous) {
+		bounds = bounds.merge(&AABB::new(particle.min(*previous), particle.max(*previous)));
+	}
+	let margin = Vec3::splat(cloth.thickness);
+	let obstacles = collect_obstacles(state, cloth, scene_id, &AABB::new(bounds.min - margin, bounds.max + margin));
+
+	// Stiffness per iteration so the overall stiffness doesn't depend on the count
+	let iterations = cloth.iterations.max(1);
+	let per_iteration = |stiffness: f32| 1.0 - (1.0 - stiffness.clamp(0.0, 1.0)).powf(1.0 / iterations as f32);
+	let (stiffness, bend_stiffness) = (per_iteration(cloth.stiffness), per_iteration(cloth.bend_stiffness));
+	let mut normals = vec![None; count];
+	for _ in 0..iterations {
+		for edge in &cloth.edges {
+			solve_distance(&mut cloth.particles, &weights, *edge, stiffness);
+		}
+		for bend in &cloth.bends {
+			solve_distance(&mut cloth.particles, &weights, *bend, bend_stiffness);
+		}
+		for (i, particle) in cloth.particles.iter_mut().enumerate() {
+			if weights[i] == 0.0 {
+				continue;
+			}
+			for obstacle in &obstacles {
+				if let Some(normal) = obstacle.push_out(particle, cloth.thickness) {
+					normals[i] = Some(normal);
+				}
+			}
+		}
+	}
+
+	// Touching particles lose their motion into the collider and some of their sliding
+	for (i, normal) in normals.iter().enumerate() {
+		let normal = match normal {
+			Some(normal) => normal,
+			None => continue,
+		};
+		let velocity = cloth.particles[i] - cloth.previous[i];
+		let sliding = velocity - *normal * velocity.dot(*normal);
+		let normal_velocity = velocity.dot(*normal).max(0.0);
+		cloth.previous[i] = cloth.particles[i] - sliding * (1.0 - cloth.friction.clamp(0.0, 1.0)) - *normal * normal_velocity;
+	}
+
+	let inverse = transform.inverse();
+	let vertices = cloth.vertex_particles.iter()
+		.map(|particle| inverse.transform_point3(cloth.particles[*particle]).to_array())
+		.collect();
+	if triangles.is_empty() {
+		return Some(ClothVertices {
+			vertices,
+			normals: primitive.normals.clone(),
+		});
+	}
+	let mut particle_normals = vec![Vec3::ZERO; count];
+	for triangle in &triangles {
+		let [a, b, c] = triangle.map(|particle| cloth.particles[particle]);
+		// Not normalized so larger triangles weigh more
+		let normal = (b - a).cross(c - a);
+		for particle in triangle {
+			particle_normals[*particle] += normal;
+		}
+	}
+	let to_local = Mat3::from_mat4(transform).transpose();
+	let normals = cloth.vertex_particles.iter()
+		.map(|particle| (to_local * particle_normals[*particle]).normalize_or_zero().to_array())
+		.collect();
+	Some(ClothVertices { vertices, normals })
+}
+
+/// Gives the cloth a copy of its mesh when other nodes or cloths draw the
+/// same mesh, so the simulated vertices only show up on the cloth's node.
+fn own_mesh(cloth_id: ArenaId<Cloth>, cloths: &mut Arena<Cloth>, state: &mut State) {
+	let cloth = cloths.get(&cloth_id).unwrap();
+	let (mesh_id, node_id) = (cloth.mesh_id, cloth.node_id);
+	let shared = state.nodes.iter().any(|(other_id, other)| other_id != node_id && other.mesh == Some(mesh_id))
+		|| cloths.iter().any(|(other_id, other)| other_id != cloth_id && other.mesh_id == mesh_id);
+	if !shared {
+		return;
+	}
+	let mesh = match state.meshes.get(&mesh_id) {
+		Some(mesh) => mesh.clone(),
+		None => return,
+	};
+	let copy = state.meshes.insert(mesh);
+	cloths.get_mut(&cloth_id).unwrap().mesh_id = copy;
+	if let Some(node) = state.nodes.get_mut(&node_id) {
+		if node.mesh == Some(mesh_id) {
+			node.mesh = Some(copy);
+		}
+	}
+}
+
+/// Simulates every cloth for one physics step and writes the results into
+/// their meshes.
+pub fn simulate_cloths(state: &mut State, dt: f32) {
+	let mut cloths = std::mem::take(&mut state.cloths);
+	let cloth_ids: Vec<_> = cloths.iter().map(|(cloth_id, _)| cloth_id).collect();
+	for cloth_id in cloth_ids {
+		// Checked once, when the cloth is set up from its mesh
+		if cloths.get(&cloth_id).unwrap().particles.is_empty() {
+			own_mesh(cloth_id, &mut cloths, state);
+		}
+		let cloth = cloths.get_mut(&cloth_id).unwrap();
+		let result = match step_cloth(cloth, state, dt) {
+			Some(result) => result,
+			None => continue,
+		};
+		let primitive = state.meshes.get_mut(&cloth.mesh_id)
+			.and_then(|mesh| mesh.primitives.get_mut(cloth.primitive));
+		if let Some(primitive) = primitive {
+			primitive.vertices = result.vertices;
+			primitive.normals = result.normals;
+		}
+	}
+	state.cloths = cloths;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Mesh;
+
+	fn collider(state: &mut State, scene_id: ArenaId<crate::Scene>, translation: Vec3, shape: CollisionShape) -> ArenaId<Node> {
+		state.nodes.insert(Node {
+			translation,
+			global_transform: Mat4::from_translation(translation),
+			scene_id: Some(scene_id),
+			collision_shape: Some(shape),
+			..Default::default()
+		})
+	}
+
+	fn cloth_node(state: &mut State, scene_id: ArenaId<crate::Scene>, primitive: Primitive) -> (ArenaId<Node>, ArenaId<Mesh>) {
+		let mut mesh = Mesh::new();
+		mesh.primitives.push(primitive);
+		let mesh_id = state.meshes.insert(mesh);
+		let node_id = state.nodes.insert(Node {
+			mesh: Some(mesh_id),
+			scene_id: Some(scene_id),
+			..Default::default()
+		});
+		(node_id, mesh_id)
+	}
+
+	fn simulate(state: &mut State, steps: usize) {
+		for _ in 0..steps {
+			simulate_cloths(state, 1.0 / 60.0);
+		}
+	}
+
+	#[test]
+	fn pinned_rope_hangs_below_its_node() {
+		let mut state = State::default();
+		let scene_id = state.scenes.insert(Scene::new());
+		let mut rope = Primitive::new(PrimitiveTopology::LineStrip);
+		rope.vertices = (0..11).map(|i| [i as f32 * 0.1, 2.0, 0.0]).collect();
+		let (node_id, mesh_id) = cloth_node(&mut state, scene_id, rope);
+		let hook = state.nodes.insert(Node {
+			global_transform: Mat4::from_translation(Vec3::new(0.0, 2.0, 0.0)),
+			scene_id: Some(scene_id),
+			..Default::default()
+		});
+		let cloth_id = state.cloths.insert(Cloth::new(node_id, mesh_id).pin(0, hook));
+
+		simulate(&mut state, 600);
+		let cloth = state.cloths.get(&cloth_id).unwrap();
+		let end = cloth.particles[10];
+		assert!(end.x.abs() < 0.1 && (end.y - 1.0).abs() < 0.05, "Rope should hang straight down {:?}", end);
+		for pair in cloth.particles.windows(2) {
+			assert!((pair[0].distance(pair[1]) - 0.1).abs() < 0.005, "{:?}", pair);
+		}
+		let vertices = &state.meshes.get(&mesh_id).unwrap().primitives[0].vertices;
+		assert_eq!(vertices[0], [0.0, 2.0, 0.0]);
+		assert!(Vec3::from(vertices[10]).abs_diff_eq(end, 1e-6));
+
+		// The rope follows its hook
+		state.nodes.get_mut(&hook).unwrap().global_transform = Mat4::from_translation(Vec3::new(3.0, 2.0, 0.0));
+		simulate(&mut state, 600);
+		let end = state.cloths.get(&cloth_id).unwrap().particles[10];
+		assert!((end.x - 3.0).abs() < 0.1 && (end.y - 1.0).abs() < 0.05, "{:?}", end);
+	}
+
+	#[test]
+	fn cloths_sharing_a_mesh_simulate_their_own_copies() {
+		let mut state = State::default();
+		let scene_id = state.scenes.insert(Scene::new());
+		let mut rope = Primitive::new(PrimitiveTopology::LineStrip);
+		rope.vertices = (0..5).map(|i| [i as f32 * 0.1, 0.0, 0.0]).collect();
+		let (first, mesh_id) = cloth_node(&mut state, scene_id, rope.clone());
+		let second = state.nodes.insert(Node {
+			mesh: Some(mesh_id),
+			global_transform: Mat4::from_translation(Vec3::new(5.0, 0.0, 0.0)),
+			scene_id: Some(scene_id),
+			..Default::default()
+		});
+		// Drawn as is by a node without a cloth
+		let statue = state.nodes.insert(Node {
+			mesh: Some(mesh_id),
+			scene_id: Some(scene_id),
+			..Default::default()
+		});
+		let first_cloth = state.cloths.insert(Cloth::new(first, mesh_id));
+		let second_cloth = state.cloths.insert(Cloth::new(second, mesh_id));
+
+		simulate(&mut state, 10);
+		let first_mesh = state.cloths.get(&first_cloth).unwrap().mesh_id;
+		let second_mesh = state.cloths.get(&second_cloth).unwrap().mesh_id;
+		assert!(first_mesh != mesh_id && second_mesh != mesh_id && first_mesh != second_mesh);
+		assert_eq!(state.nodes.get(&first).unwrap().mesh, Some(first_mesh));
+		assert_eq!(state.nodes.get(&second).unwrap().mesh, Some(second_mesh));
+		assert_eq!(state.nodes.get(&statue).unwrap().mesh, Some(mesh_id));
+		assert_eq!(state.meshes.get(&mesh_id).unwrap().primitives[0].vertices, rope.vertices);
+		// Both ropes fall, each written into its own mesh
+		for mesh_id in [first_mesh, second_mesh] {
+			let vertex = state.meshes.get(&mesh_id).unwrap().primitives[0].vertices[0];
+			assert!(vertex[1] < 0.0, "{:?}", vertex);
+		}
+	}
+
+	#[test]
+	fn obstacles_come_from_the_broad_phase() {
+		let mut state = State::default();
+		let scene_id = state.scenes.insert(Scene::new());
+		let listed = collider(&mut state, scene_id, Vec3::ZERO, CollisionShape::Sphere { radius: 1.0 });
+		collider(&mut state, scene_id, Vec3::new(0.5, 0.0, 0.0), CollisionShape::Sphere { radius: 1.0 });
+		let (node_id, mesh_id) = cloth_node(&mut state, scene_id, Primitive::new(PrimitiveTopology::LineStrip));
+		let cloth = Cloth::new(node_id, mesh_id);
+		let bounds = AABB::new(Vec3::splat(-2.0), Vec3::splat(2.0));
+		assert_eq!(collect_obstacles(&state, &cloth, scene_id, &bounds).len(), 2);
+
+		// Only the nodes the broad phase knows about are tested
+		let mut broad_phase = crate::broad_phase::BroadPhase::new(&crate::BroadPhaseType::Bvh);
+		broad_phase.set_node(listed, AABB::new(Vec3::splat(-1.0), Vec3::splat(1.0)));
+		state.broad_phases.insert(scene_id, broad_phase);
+		assert_eq!(collect_obstacles(&state, &cloth, scene_id, &bounds).len(), 1);
+	}
+
+	#[test]
+	fn cloth_drapes_over_sphere_and_box() {
+		let mut state = State::default();
+		let scene_id = state.scenes.insert(Scene::new());
+		collider(&mut state, scene_id, Vec3::ZERO, CollisionShape::Sphere { radius: 1.0 });
+		collider(&mut state, scene_id, Vec3::new(0.0, -2.0, 0.0), CollisionShape::Box { size: Vec3::new(5.0, 0.5, 5.0) });
+
+		// 3 by 3 sheet of 16 by 16 quads, normals facing up
+		let n = 16;
+		let mut sheet = Primitive::new(PrimitiveTopology::TriangleList);
+		for z in 0..=n {
+			for x in 0..=n {
+				sheet.vertices.push([x as f32 * 3.0 / n as f32 - 1.5, 1.5, z as f32 * 3.0 / n as f32 - 1.5]);
+			}
+		}
+		for z in 0..n {
+			for x in 0..n {
+				let i = (z * (n + 1) + x) as u16;
+				let row = n as u16 + 1;
+				sheet.indices.extend([i, i + row, i + 1, i + 1, i + row, i + row + 1]);
+			}
+		}
+		let (node_id, mesh_id) = cloth_node(&mut state, scene_id, sheet);
+		let cloth_id = state.cloths.insert(Cloth::new(node_id, mesh_id));
+
+		simulate(&mut state, 300);
+		let cloth = state.cloths.get(&cloth_id).unwrap();
+		for particle in &cloth.particles {
+			assert!(particle.length() > 1.0 + cloth.thickness - 0.01, "Cloth went into the sphere {:?}", particle);
+			assert!(particle.y > -1.5 + cloth.thickness - 0.01, "Cloth went into the box {:?}", particle);
+		}
+		let center = (n / 2) * (n + 1) + n / 2;
+		// The middle rests on top of the sphere
+		let middle = cloth.particles[center];
+		assert!((middle.length() - 1.0 - cloth.thickness).abs() < 0.01 && middle.y > 0.95, "{:?}", middle);
+
+		let primitive = &state.meshes.get(&mesh_id).unwrap().primitives[0];
+		assert!(Vec3::from(primitive.normals[center]).dot(middle.normalize()) > 0.95, "{:?}", primitive.normals[center]);
+		// Corners hang down the sides of the sphere
+		assert!(primitive.vertices[0][1] < 0.5, "{:?}", primitive.vertices[0]);
+	}
+}
@@ -11,11 +11,20 @@ use crate::hardware::RenderEncoder;
 use crate::hardware::TextureHandle;
 use crate::hardware::WindowHandle;
 use crate::internal_types::*;
+use crate::character::move_characters;
+use crate::vehicle::drive_vehicles;
+use crate::cloth::simulate_cloths;
+use crate::force_fields::apply_force_fields;
+use crate::physics::enter_world_space;
+use crate::physics::leave_world_space;
+use crate::physics::WorldFrame;
+use crate::physics::update_kinematic_velocities;
+use crate::physics::step_scenes;
 use crate::physics::PhysicsSystem;
 use crate::spatial_grid::SpatialGrid;
+use crate::broad_phase::BroadPhase;
 use crate::state::State;
 use crate::types::*;
-use crate::utility::topo_sort_nodes;
 use crate::Arena;
 use crate::ArenaId;
 use crate::GUIElement;
@@ -78,7 +87,6 @@ impl GuiBuffers {
 
 #[derive(Debug, Clone)]
 pub struct SceneCollection {
-	grid: SpatialGrid,
 	physics_system: PhysicsSystem,
 }
 
@@ -118,7 +126,21 @@ pub struct Engine<A, H> {
 	//nodes: HashMap<ArenaId<Node>, NodeComputedMetadata>,
 	mesh_nodes: HashMap<ArenaId<Mesh>, Vec<ArenaId<Node>>>,
 	topo_sorted_nodes: Vec<ArenaId<Node>>,
+	/// Hierarchy and scene arena versions `topo_sorted_nodes` was built from.
+	sorted_version: Option<(u64, u64)>,
+	/// Local poses of the nodes while a physics step runs in world space.
+	world_frames: Vec<(ArenaId<Node>, WorldFrame)>,
 	scene_collections: HashMap<ArenaId<Scene>, SceneCollection>,
+	/// Simulation time not yet consumed by fixed physics steps.
+	physics_accumulator: f32,
+	/// Position of the rendered frame between the previous and the current physics step.
+	physics_alpha: f32,
+	/// Local translation and rotation of dynamic nodes before the last physics step.
+	prev_physics_transforms: HashMap<ArenaId<Node>, (glam::Vec3, glam::Quat)>,
+	/// Pose of kinematic nodes after the last physics step.
+	kinematic_poses: HashMap<ArenaId<Node>, (glam::Vec3, glam::Quat)>,
+	/// Interpolated world transforms used for drawing.
+	render_transforms: HashMap<ArenaId<Node>, glam::Mat4>,
 	fps: u32
 }
 
@@ -174,58 +196,110 @@ where
 			mesh_nodes: HashMap::new(),
 			fps: 0,
 			topo_sorted_nodes: Vec::new(),
+			sorted_version: None,
+			world_frames: Vec::new(),
 			scene_collections: HashMap::new(),
+			physics_accumulator: 0.0,
+			physics_alpha: 1.0,
+			prev_physics_transforms: HashMap::new(),
+			kinematic_poses: HashMap::new(),
+			render_transforms: HashMap::new(),
         }
     }
 
+	/// Local matrix of the node as it should be drawn, blended between the
+	/// last two physics steps.
+	fn interpolated_matrix(&self, node_id: ArenaId<Node>, node: &Node) -> glam::Mat4 {
+		if !self.state.physics_settings.interpolate {
+			return node.matrix();
+		}
+		match self.prev_physics_transforms.get(&node_id) {
+			Some((translation, rotation)) => glam::Mat4::from_scale_rotation_translation(
+				node.scale,
+				rotation.slerp(node.rotation, self.physics_alpha),
+				translation.lerp(node.translation, self.physics_alpha),
+			),
+			None => node.matrix(),
+		}
+	}
+
+	fn render_transform(&self, node_id: &ArenaId<Node>, node: &Node) -> glam::Mat4 {
+		match self.render_transforms.get(node_id) {
+			Some(transform) => *transform,
+			None => node.global_transform,
+		}
+	}
+
 	fn process_nodes(&mut self) {
 		let timer = Instant::now();
 		for (_, nodes) in &mut self.mesh_nodes {
 			nodes.clear();
 		}
 
-		self.topo_sorted_nodes.clear();
+		// Parents can be assigned directly on the nodes, so check all of them,
+		// but only rebuild the order when the hierarchy or the scenes changed
 		let sort_timer = Instant::now();
-		topo_sort_nodes(&self.state.nodes, &mut self.topo_sorted_nodes);
+		self.state.update_hierarchy();
+		let version = (self.state.hierarchy.version(), self.state.scenes.version());
+		if self.sorted_version != Some(version) {
+			self.topo_sorted_nodes.clear();
+			self.state.hierarchy.sorted(&self.state.scenes, &mut self.topo_sorted_nodes);
+			self.sorted_version = Some(version);
+		}
 		if sort_timer.elapsed() > Duration::from_millis(10) {
 			log::info!("Topo sort {} nodes took {:?}", self.topo_sorted_nodes.len(), sort_timer.elapsed());
 		}
 
+		// Scenes that switched broad phase start over with an empty one
+		let scenes = &self.state.scenes;
+		self.state.broad_phases.retain(|scene_id, broad_phase| {
+			scenes.get(scene_id).is_some_and(|scene| broad_phase.is(&scene.broad_phase))
+		});
+
+		self.render_transforms.clear();
 		for node_id in &self.topo_sorted_nodes {
 			let mut scene_id: Option<ArenaId<Scene>> = None;
-			let transform = {
+			let (transform, render_transform) = {
 				let node = match self.state.nodes.get(node_id) {
 					Some(node) => node,
 					None => continue,
 				};
+				let render_matrix = self.interpolated_matrix(*node_id, node);
 				match node.parent {
 					NodeParent::Scene(id) => {
 						scene_id = Some(id);
 						let scene = self.state.scenes.get(&id).unwrap();
-						node.matrix() * glam::Mat4::from_scale(scene.scale)
+						let scale = glam::Mat4::from_scale(scene.scale);
+						(node.matrix() * scale, render_matrix * scale)
 					}
-					NodeParent::Orphan => node.matrix(),
+					NodeParent::Orphan => (node.matrix(), render_matrix),
 					NodeParent::Node(parent_node_id) => {
 						let parent = match self.state.nodes.get(&parent_node_id) {
 							Some(parent) => parent,
 							None => continue,
 						};
 						scene_id = parent.scene_id;
-						parent.global_transform * node.matrix()
+						let parent_render = self.render_transform(&parent_node_id, parent);
+						(parent.global_transform * node.matrix(), parent_render * render_matrix)
 					}
 				}
 			};
+			self.render_transforms.insert(*node_id, render_transform);
 
 			let node = self.state.nodes.get_mut(node_id).unwrap();
 			if let Some(scene_id) = scene_id {
 				if let Some(collision_shape) = &node.collision_shape {
-					if node.global_transform != transform {
-						let aabb = collision_shape.aabb(node.translation);
-						let collection = self.scene_collections.entry(scene_id).or_insert(SceneCollection {
-							grid: SpatialGrid::new(5.0),
-							physics_system: PhysicsSystem::new(),
-						});
-						collection.grid.set_node(*node_id, aabb);
+					self.scene_collections.entry(scene_id).or_insert(SceneCollection {
+						physics_system: PhysicsSystem::new(scene_id),
+					});
+					let scenes = &self.state.scenes;
+					let broad_phase = self.state.broad_phases.entry(scene_id)
+						.or_insert_with(|| BroadPhase::new(&scenes.get(&scene_id).unwrap().broad_phase));
+					// Nodes left at the origin keep the default transform but still need a rect
+					if node.global_transform != transform || broad_phase.get_node_rect(*node_id).is_none() {
+						let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+						let aabb = collision_shape.scaled(scale).bounds(translation, rotation);
+						broad_phase.set_node(*node_id, aabb);
 					}
 				}
 			}
@@ -240,8 +314,9 @@ where
 			}
 		}
 
-		for (_, c) in &mut self.scene_collections {
-			c.grid.retain_nodes(|node_id| self.state.nodes.contains(node_id));
+		let nodes = &self.state.nodes;
+		for (_, broad_phase) in &mut self.state.broad_phases {
+			broad_phase.retain_nodes(|node_id| nodes.contains(node_id));
 		}
 		let elapsed = timer.elapsed();
 		if elapsed > Duration::from_millis(5) {
@@ -362,7 +437,7 @@ where
 							None => continue,
 						};
 						let instance = RawInstance {
-							model: node.global_transform.to_cols_array_2d(),
+							model: self.render_transform(node_id, node).to_cols_array_2d(),
 						};
 						let buffer = self.scene_instance_buffers.entry(scene_id)
 							.or_insert_with(|| Buffer::new(self.hardware.create_buffer(&format!("instances_{:?}", scene_id.index()), 1000)));
@@ -420,7 +495,7 @@ where
 				None => continue,
 			};
 			let model = glam::Mat4::perspective_lh(cam.fovy, cam.aspect, cam.znear, cam.zfar)
-				* node.global_transform.inverse();
+				* self.render_transform(&node_id, node).inverse();
 
 			let cam = RawCamera {
 				model: model.to_cols_array_2d(),
@@ -450,7 +525,7 @@ where
 				Some(id) => id,
 				None => continue,
 			};
-			let model = node.global_transform;
+			let model = self.render_transform(&node_id, node);
 			let pos = model.w_axis.truncate().into();
 			let light = RawPointLight::new(light.color, light.intensity, pos);
 
@@ -600,7 +675,76 @@ where
 		}
 	}
 
+	/// Advances physics in fixed steps. Frame time is accumulated and consumed
+	/// in `fixed_dt` chunks so the simulation does not depend on the frame rate.
 	fn process_physics(&mut self, dt: f32) {
+		let settings = self.state.physics_settings.clone();
+		self.physics_accumulator += dt;
+
+		let mut steps = 0;
+		while self.physics_accumulator >= settings.fixed_dt && steps < settings.max_substeps {
+			self.physics_accumulator -= settings.fixed_dt;
+			steps += 1;
+		}
+		if self.physics_accumulator >= settings.fixed_dt {
+			// Too far behind, drop the time we could not simulate
+			self.physics_accumulator %= settings.fixed_dt;
+		}
+		self.physics_alpha = self.physics_accumulator / settings.fixed_dt;
+		if steps == 0 {
+			return;
+		}
+
+		// Kinematic nodes moved during the frame follow that motion evenly over
+		// the steps instead of jumping there in the first one. That depends on
+		// the frame rate, so deterministic simulations skip it. Nodes the app
+		// moves in `on_phycis_update` stop following it.
+		let mut kinematic_moves = Vec::new();
+		for (node_id, node) in &mut self.state.nodes {
+			if node.physics.typ != PhycisObjectType::Kinematic || settings.deterministic {
+				continue;
+			}
+			if let Some((translation, rotation)) = self.kinematic_poses.get(&node_id) {
+				kinematic_moves.push((node_id, *translation, *rotation, node.translation, node.rotation));
+				node.translation = *translation;
+				node.rotation = *rotation;
+			}
+		}
+
+		for step in 0..steps {
+			self.prev_physics_transforms.clear();
+			for (node_id, node) in &self.state.nodes {
+				if matches!(node.physics.typ, PhycisObjectType::Dynamic | PhycisObjectType::Kinematic) {
+					self.prev_physics_transforms.insert(node_id, (node.translation, node.rotation));
+				}
+			}
+
+			let t = (step + 1) as f32 / steps as f32;
+			for (node_id, from_translation, from_rotation, to_translation, to_rotation) in &kinematic_moves {
+				if let Some(node) = self.state.nodes.get_mut(node_id) {
+					node.translation = from_translation.lerp(*to_translation, t);
+					node.rotation = from_rotation.slerp(*to_rotation, t);
+				}
+			}
+
+			self.app.on_phycis_update(&mut self.state, settings.fixed_dt);
+			let nodes = &self.state.nodes;
+			kinematic_moves.retain(|(node_id, from_translation, from_rotation, to_translation, to_rotation)| match nodes.get(node_id) {
+				Some(node) => node.translation == from_translation.lerp(*to_translation, t) && node.rotation == from_rotation.slerp(*to_rotation, t),
+				None => false,
+			});
+			update_kinematic_velocities(&mut self.state, &mut self.kinematic_poses, settings.fixed_dt);
+			self.step_physics(settings.fixed_dt);
+			self.state.physics_step += 1;
+		}
+	}
+
+	fn step_physics(&mut self, dt: f32) {
+		enter_world_space(&mut self.state, &self.topo_sorted_nodes, &mut self.world_frames);
+		move_characters(&mut self.state, dt);
+		drive_vehicles(&mut self.state, dt);
+		apply_force_fields(&mut self.state, dt);
+
 		// for (scene_id, scene) in &self.state.scenes {
 		// 	self.scene_collections.entry(scene_id).or_insert(SceneCollection {
 		// 		moved_nodes: Vec::new(),
@@ -609,49 +753,51 @@ where
 		// 	});
 		// }
 
-		for (_, c) in &mut self.scene_collections {
-			let timings = c
-				.physics_system
-				.physics_update(&mut self.state, &mut c.grid, dt);
-
-			for (_, ray_cast) in &mut self.state.raycasts {
-				ray_cast.intersects.clear();
-
-				let node = match self.state.nodes.get(&ray_cast.node_id) {
-					Some(node) => node,
-					None => continue,
-				};
-
-				let start = node.translation;
-				let end = start + node.rotation * glam::Vec3::new(0.0, 0.0, 1.0) * ray_cast.len;
-				let nodes = c.grid.get_line_ray_nodes(start, end);
-
-				let mut intersections = Vec::new();
-
-				for node_inx in nodes {
-					if node_inx == ray_cast.node_id {
-						continue;
-					}
+		let mut broad_phases = std::mem::take(&mut self.state.broad_phases);
+		let mut scene_broad_phases: HashMap<_, _> = broad_phases.iter_mut().collect();
+		let mut scenes: Vec<_> = self.scene_collections
+			.iter_mut()
+			.filter_map(|(scene_id, c)| Some((*scene_id, &mut c.physics_system, scene_broad_phases.remove(scene_id)?)))
+			.collect();
+		if self.state.physics_settings.deterministic {
+			scenes.sort_by_key(|(scene_id, _, _)| scene_id.index());
+		}
+		let events = step_scenes(&mut self.state, scenes, dt);
+		self.state.broad_phases = broad_phases;
+		leave_world_space(&mut self.state, &mut self.world_frames);
+		simulate_cloths(&mut self.state, dt);
+
+		// Applied forces only last for one step
+		for (_, node) in &mut self.state.nodes {
+			node.physics.applied_force = glam::Vec3::ZERO;
+			node.physics.applied_torque = glam::Vec3::ZERO;
+		}
 
-					let aabb = match c.grid.get_node_rect(node_inx) {
-						Some(aabb) => aabb,
-						None => continue,
-					};
+		let mut raycasts = std::mem::take(&mut self.state.raycasts);
+		for (_, ray_cast) in &mut raycasts {
+			ray_cast.intersects.clear();
+			ray_cast.hits.clear();
 
-					if let Some((tmin, _tmax)) = aabb.intersect_ray(start, end) {
-						intersections.push((tmin, node_inx));
-					}
-				}
+			let node = match self.state.nodes.get(&ray_cast.node_id) {
+				Some(node) => node,
+				None => continue,
+			};
+			let scene_id = match node.scene_id {
+				Some(id) => id,
+				None => continue,
+			};
 
-				// Sort the intersections by tmin
-				intersections.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+			let (_, rotation, origin) = node.global_transform.to_scale_rotation_translation();
+			let dir = rotation * glam::Vec3::new(0.0, 0.0, 1.0);
+			let mut filter = ray_cast.filter.clone();
+			filter.exclude.push(ray_cast.node_id);
+			ray_cast.hits = self.state.raycast_all(scene_id, origin, dir, ray_cast.len, &filter);
+			ray_cast.intersects = ray_cast.hits.iter().map(|hit| hit.node_id).collect();
+		}
+		self.state.raycasts = raycasts;
 
-				// Store the sorted node indices
-				ray_cast.intersects = intersections
-					.into_iter()
-					.map(|(_, node_inx)| node_inx)
-					.collect();
-			}
+		for event in events {
+			self.app.on_physics_event(event, &mut self.state);
 		}
 	}
 
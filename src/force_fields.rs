@@ -0,0 +1,150 @@
+use glam::Vec3;
+use crate::query::step_overlap;
+use crate::state::State;
+use crate::ArenaId;
+use crate::ForceField;
+use crate::ForceFieldKind;
+use crate::Node;
+use crate::PhycisObjectType;
+
+/// Force or impulse a field applies to one body.
+struct Push {
+	node_id: ArenaId<Node>,
+	point: Vec3,
+	force: Vec3,
+	torque: Vec3,
+	impulse: bool,
+}
+
+impl Push {
+	fn force(node_id: ArenaId<Node>, point: Vec3, force: Vec3) -> Self {
+		Self {
+			node_id,
+			point,
+			force,
+			torque: Vec3::ZERO,
+			impulse: false,
+		}
+	}
+}
+
+/// Smooth noise between -1 and 1 built from sines with unrelated
+/// frequencies, so gusts come out the same in every run.
+fn gust(time: f32, position: Vec3, seed: f32) -> f32 {
+	let t = time + seed * 17.0;
+	(t * 1.3 + position.x * 0.37).sin() * 0.5
+		+ (t * 2.9 + position.z * 0.53 + seed).sin() * 0.3
+		+ (t * 6.1 + position.y * 0.71).sin() * 0.2
+}
+
+/// What the field does to the bodies overlapping it this step. `None` when
+/// the field is not in a simulated scene.
+fn field_pushes(field: &ForceField, state: &State, time: f32) -> Option<Vec<Push>> {
+	let node = state.nodes.get(&field.node_id)?;
+	let scene_id = node.scene_id?;
+	let scene = state.scenes.get(&scene_id)?;
+	if !scene.physics_on {
+		return None;
+	}
+	// Nodes are in world space during the step, only the scale comes from the last update
+	let (scale, _, _) = node.global_transform.to_scale_rotation_translation();
+	let (rotation, origin) = (node.rotation, node.translation);
+	let transform = glam::Mat4::from_scale_rotation_translation(scale, rotation, origin);
+
+	let mut pushes = Vec::new();
+	for body_id in step_overlap(state, scene_id, &field.shape, transform, &field.filter) {
+		let body = match state.nodes.get(&body_id) {
+			Some(body) if body_id != field.node_id && body.physics.typ == PhycisObjectType::Dynamic => body,
+			_ => continue,
+		};
+		let center = body.step_center_of_mass();
+		let offset = center - origin;
+		let strength = field.falloff.factor(offset.length());
+		if strength <= 0.0 {
+			continue;
+		}
+
+		match &field.kind {
+			ForceFieldKind::Wind { velocity, drag, turbulence } => {
+				let wind = rotation * *velocity;
+				let gusts = Vec3::new(gust(time, center, 0.0), gust(time, center, 1.0), gust(time, center, 2.0));
+				let wind = wind + gusts * *turbulence * wind.length();
+				pushes.push(Push::force(body_id, center, (wind - body.physics.velocity) * *drag * strength));
+			}
+			ForceFieldKind::Explosion { impulse } => {
+				pushes.push(Push {
+					impulse: true,
+					..Push::force(body_id, center, offset.normalize_or(Vec3::Y) * *impulse * strength)
+				});
+			}
+			ForceFieldKind::Buoyancy { surface, density, drag, angular_drag } => {
+				let shape = match &body.collision_shape {
+					Some(shape) => shape,
+					None => continue,
+				};
+				let level = origin.y + surface;
+				let bounds = shape.bounds(body.translation, body.rotation);
+				let height = bounds.max.y - bounds.min.y;
+				let submerged = match height > 0.0 {
+					true => ((level - bounds.min.y) / height).clamp(0.0, 1.0) * strength,
+					false => 0.0,
+				};
+				if submerged <= 0.0 {
+					continue;
+				}
+				// Lift at the middle of the part under water
+				let lift = -scene.gravity * *density * shape.volume() * submerged;
+				let under_water = Vec3::new(center.x, (bounds.min.y + level.min(bounds.max.y)) * 0.5, center.z);
+				pushes.push(Push::force(body_id, under_water, lift));
+				pushes.push(Push {
+					torque: -body.physics.angular_velocity * *angular_drag * submerged,
+					..Push::force(body_id, center, -body.physics.velocity * *drag * submerged)
+				});
+			}
+			ForceFieldKind::Attractor { strength: attraction } => {
+				let force = -offset.normalize_or_zero() * *attraction * body.physics.mass * strength;
+				pushes.push(Push::force(body_id, center, force));
+			}
+		}
+	}
+	Some(pushes)
+}
+
+/// Applies the forces of every force field for one physics step and removes
+/// the explosions that went off.
+pub fn apply_force_fields(state: &mut State, dt: f32) {
+	let time = state.physics_step as f32 * dt;
+	let mut pushes = Vec::new();
+	let mut fired = Vec::new();
+	for (field_id, field) in &state.force_fields {
+		let field_pushes = match field_pushes(field, state, time) {
+			Some(field_pushes) => field_pushes,
+			None => continue,
+		};
+		pushes.extend(field_pushes);
+		if let ForceFieldKind::Explosion { .. } = field.kind {
+			fired.push(field_id);
+		}
+	}
+
+	for push in pushes {
+		let node = match state.nodes.get_mut(&push.node_id) {
+			Some(node) => node,
+			None => continue,
+		};
+		// Nodes are in world space during the step, so their pose gives the center
+		let r = push.point - node.step_center_of_mass();
+		if push.impulse {
+			let rotation = node.rotation;
+			node.apply_impulse_offset(r, rotation, push.force);
+			continue;
+		}
+		node.apply_force_offset(r, push.force);
+		if push.torque != Vec3::ZERO {
+			node.apply_torque(push.torque);
+		}
+	}
+	for field_id in fired {
+		state.force_fields.remove(&field_id);
+	}
+}
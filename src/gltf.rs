@@ -128,7 +128,7 @@ pub fn load_node(n: &gltf::Node, buffers: &[Data], state: &mut State, parser_sta
 		}
 	}
 	
-	let node_id = state.nodes.insert(node);
+	let node_id = state.insert_node(node);
 	parser_state.node_map.insert(n.index(), node_id); // Store the mapping
 
 	for child in n.children() {
@@ -1,3 +1,5 @@
+use serde::Deserialize;
+use serde::Serialize;
 use crate::ArenaId;
 use crate::Camera;
 use crate::FontHandle;
@@ -92,7 +94,7 @@ pub fn window() -> Window {
 	Window::new()
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Flex {
 	Horizontal,
 	Vertical,
@@ -105,7 +107,8 @@ impl Default for Flex {
 	}
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GUIElement {
 	pub grow: u32,
 	pub children: Vec<GUIElement>,
@@ -122,7 +125,10 @@ pub struct GUIElement {
 	pub background_color: Option<[f32; 3]>,
 	pub font_size: u32,
 	pub font_color: [f32; 4],
+	#[serde(skip)]
 	pub camera_id: Option<ArenaId<Camera>>,
+	/// Fonts are loaded by the renderer and not saved with scene files.
+	#[serde(skip)]
 	pub font: Option<FontHandle>,
 	pub height: Option<f32>,
 	pub width: Option<f32>,
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use crate::Arena;
+use crate::ArenaId;
+use crate::Node;
+use crate::NodeParent;
+use crate::Scene;
+
+/// Children of every node and scene, so walking the hierarchy doesn't scan
+/// every node. `State` updates it when it adds or moves nodes itself. Nodes
+/// inserted, removed or given another parent directly on the arena are
+/// picked up by `sync`, which the engine runs every frame.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Hierarchy {
+	/// Parent each node is indexed under.
+	parents: HashMap<ArenaId<Node>, NodeParent>,
+	/// Children in the order they were indexed. Orphans have no entry, there
+	/// is nothing to walk down from them.
+	children: HashMap<NodeParent, Vec<ArenaId<Node>>>,
+	/// Changes whenever a node is indexed under another parent, so orders
+	/// built from the index know when to be rebuilt.
+	version: u64,
+}
+
+impl Hierarchy {
+	/// Whether every node is indexed under the parent it has. Walks every
+	/// node like `sync`, but leaves the index alone.
+	pub fn is_current(&self, nodes: &Arena<Node>) -> bool {
+		self.parents.len() == nodes.len() &&
+			nodes.iter().all(|(node_id, node)| self.parents.get(&node_id) == Some(&node.parent))
+	}
+
+	pub fn version(&self) -> u64 {
+		self.version
+	}
+
+	/// Inserts the node and indexes it under its parent.
+	pub fn insert(&mut self, nodes: &mut Arena<Node>, node: Node) -> ArenaId<Node> {
+		let parent = node.parent;
+		let node_id = nodes.insert(node);
+		self.set_parent(node_id, parent);
+		node_id
+	}
+
+	pub fn children(&self, parent: NodeParent) -> &[ArenaId<Node>] {
+		match self.children.get(&parent) {
+			Some(children) => children,
+			None => &[],
+		}
+	}
+
+	pub fn set_parent(&mut self, node_id: ArenaId<Node>, parent: NodeParent) {
+		match self.parents.insert(node_id, parent) {
+			Some(previous) if previous == parent => return,
+			Some(previous) => self.unlink(node_id, previous),
+			None => {}
+		}
+		self.version += 1;
+		if parent != NodeParent::Orphan {
+			self.children.entry(parent).or_default().push(node_id);
+		}
+	}
+
+	pub fn remove(&mut self, node_id: ArenaId<Node>) {
+		if let Some(previous) = self.parents.remove(&node_id) {
+			self.unlink(node_id, previous);
+			self.version += 1;
+		}
+	}
+
+	fn unlink(&mut self, node_id: ArenaId<Node>, parent: NodeParent) {
+		if let Some(children) = self.children.get_mut(&parent) {
+			if let Some(index) = children.iter().position(|child| *child == node_id) {
+				children.remove(index);
+			}
+			if children.is_empty() {
+				self.children.remove(&parent);
+			}
+		}
+	}
+
+	/// Catches up with nodes that were added, removed or given another parent
+	/// directly. Walks every node, nodes that didn't change cost a lookup each.
+	pub fn sync(&mut self, nodes: &Arena<Node>) {
+		let mut count = 0;
+		for (node_id, node) in nodes {
+			count += 1;
+			if self.parents.get(&node_id) != Some(&node.parent) {
+				self.set_parent(node_id, node.parent);
+			}
+		}
+		if self.parents.len() > count {
+			let removed: Vec<_> = self.parents.keys().filter(|node_id| !nodes.contains(node_id)).copied().collect();
+			for node_id in removed {
+				self.remove(node_id);
+			}
+		}
+	}
+
+	/// The node followed by its descendants, parents before their children.
+	pub fn subtree(&self, node_id: ArenaId<Node>) -> Vec<ArenaId<Node>> {
+		let mut nodes = Vec::new();
+		let mut stack = vec![node_id];
+		while let Some(node_id) = stack.pop() {
+			nodes.push(node_id);
+			stack.extend(self.children(NodeParent::Node(node_id)).iter().rev());
+		}
+		nodes
+	}
+
+	/// Nodes of every scene with parents before their children.
+	pub fn sorted(&self, scenes: &Arena<Scene>, sorted_nodes: &mut Vec<ArenaId<Node>>) {
+		let mut stack = Vec::new();
+		for (scene_id, _) in scenes {
+			stack.extend(self.children(NodeParent::Scene(scene_id)).iter().rev());
+			while let Some(node_id) = stack.pop() {
+				sorted_nodes.push(node_id);
+				stack.extend(self.children(NodeParent::Node(node_id)).iter().rev());
+			}
+		}
+	}
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Disjoint set over body indices.
+struct UnionFind {
+	parents: Vec<usize>,
+}
+
+impl UnionFind {
+	fn new(len: usize) -> Self {
+		Self {
+			parents: (0..len).collect(),
+		}
+	}
+
+	fn find(&mut self, mut i: usize) -> usize {
+		while self.parents[i] != i {
+			self.parents[i] = self.parents[self.parents[i]];
+			i = self.parents[i];
+		}
+		i
+	}
+
+	fn union(&mut self, a: usize, b: usize) {
+		let a = self.find(a);
+		let b = self.find(b);
+		if a != b {
+			self.parents[b] = a;
+		}
+	}
+}
+
+/// Groups `bodies` into simulation islands. Two bodies end up in the same
+/// island when they are connected by a chain of `links` (contacts or joints).
+/// Links to nodes that are not in `bodies`, such as static geometry, do not
+/// merge islands. Islands are ordered by their first body in `bodies`.
+pub fn build_islands<T: Copy + Eq + Hash>(bodies: &[T], links: &[(T, T)]) -> Vec<Vec<T>> {
+	let indices: HashMap<T, usize> = bodies
+		.iter()
+		.enumerate()
+		.map(|(i, id)| (*id, i))
+		.collect();
+	let mut sets = UnionFind::new(bodies.len());
+
+	for (a, b) in links {
+		if let (Some(a), Some(b)) = (indices.get(a), indices.get(b)) {
+			sets.union(*a, *b);
+		}
+	}
+
+	let mut islands: Vec<Vec<T>> = Vec::new();
+	let mut island_of_root: HashMap<usize, usize> = HashMap::new();
+	for (i, id) in bodies.iter().enumerate() {
+		let root = sets.find(i);
+		let island = *island_of_root.entry(root).or_insert_with(|| {
+			islands.push(Vec::new());
+			islands.len() - 1
+		});
+		islands[island].push(*id);
+	}
+
+	islands
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Arena;
+	use crate::Node;
+
+	#[test]
+	fn connected_bodies_share_an_island() {
+		let mut arena = Arena::new();
+		let a = arena.insert(Node::new());
+		let b = arena.insert(Node::new());
+		let c = arena.insert(Node::new());
+		let d = arena.insert(Node::new());
+		let floor = arena.insert(Node::new());
+
+		let islands = build_islands(&[a, b, c, d], &[(a, b), (b, c), (d, floor), (c, floor)]);
+
+		assert_eq!(islands, vec![vec![a, b, c], vec![d]]);
+	}
+}
@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+
+use glam::Mat3;
+use glam::Quat;
+use glam::Vec3;
+use crate::state::State;
+use crate::ArenaId;
+use crate::Joint;
+use crate::JointKind;
+use crate::Node;
+use crate::PhycisObjectType;
+use crate::Scene;
+
+const JOINT_ITERATIONS: usize = 8;
+
+/// Copy of the node state the solver works on.
+#[derive(Debug, Clone)]
+struct Body {
+	translation: Vec3,
+	rotation: Quat,
+	start_translation: Vec3,
+	start_rotation: Quat,
+	velocity: Vec3,
+	angular_velocity: Vec3,
+	inv_mass: f32,
+	inv_inertia: Mat3,
+}
+
+impl Body {
+	fn new(node: &Node) -> Self {
+		let dynamic = node.physics.typ == PhycisObjectType::Dynamic && node.physics.mass > 0.0;
+		let inv_mass = if dynamic { 1.0 / node.physics.mass } else { 0.0 };
+		let inertia = node.inertia_tensor();
+		let inv_inertia = if !dynamic || node.lock_rotation || inertia.determinant().abs() <= 1e-6 {
+			Mat3::ZERO
+		} else {
+			inertia.inverse()
+		};
+
+		Self {
+			translation: node.translation,
+			rotation: node.rotation,
+			start_translation: node.translation,
+			start_rotation: node.rotation,
+			velocity: node.physics.velocity,
+			angular_velocity: node.physics.angular_velocity,
+			inv_mass,
+			inv_inertia,
+		}
+	}
+
+	/// Inverse inertia tensor in world space.
+	fn world_inv_inertia(&self) -> Mat3 {
+		let r = Mat3::from_quat(self.rotation);
+		r * self.inv_inertia * r.transpose()
+	}
+
+	fn rotate(&mut self, omega: Vec3) {
+		if omega == Vec3::ZERO {
+			return;
+		}
+		let dq = Quat::from_xyzw(omega.x, omega.y, omega.z, 0.0) * self.rotation;
+		self.rotation = Quat::from_xyzw(
+			self.rotation.x + 0.5 * dq.x,
+			self.rotation.y + 0.5 * dq.y,
+			self.rotation.z + 0.5 * dq.z,
+			self.rotation.w + 0.5 * dq.w,
+		).normalize();
+	}
+
+	fn world_anchor(&self, anchor: Vec3) -> Vec3 {
+		self.translation + self.rotation * anchor
+	}
+
+	/// Generalized inverse mass along `n` at offset `r` from the center.
+	fn inv_mass_at(&self, r: Vec3, n: Vec3) -> f32 {
+		let rn = r.cross(n);
+		self.inv_mass + rn.dot(self.world_inv_inertia() * rn)
+	}
+}
+
+/// Moves both bodies so that anchor1 moves by `correction` towards anchor2.
+fn apply_positional(b1: &mut Body, b2: &mut Body, r1: Vec3, r2: Vec3, correction: Vec3) {
+	let c = correction.length();
+	if c < 1e-6 {
+		return;
+	}
+	let n = correction / c;
+	let w = b1.inv_mass_at(r1, n) + b2.inv_mass_at(r2, n);
+	if w == 0.0 {
+		return;
+	}
+	let p = n * (c / w);
+
+	b1.translation += p * b1.inv_mass;
+	b1.rotate(b1.world_inv_inertia() * r1.cross(p));
+	b2.translation -= p * b2.inv_mass;
+	b2.rotate(-(b2.world_inv_inertia() * r2.cross(p)));
+}
+
+/// Cross product matrix, `skew(r) * v == r.cross(v)`.
+fn skew(r: Vec3) -> Mat3 {
+	Mat3::from_cols(
+		Vec3::new(0.0, r.z, -r.y),
+		Vec3::new(-r.z, 0.0, r.x),
+		Vec3::new(r.y, -r.x, 0.0),
+	)
+}
+
+/// Moves both bodies so that the anchors meet, solving all three axes at once.
+fn apply_point(b1: &mut Body, b2: &mut Body, r1: Vec3, r2: Vec3, correction: Vec3) {
+	if correction.length_squared() < 1e-12 {
+		return;
+	}
+	let s1 = skew(r1);
+	let s2 = skew(r2);
+	let k = Mat3::from_diagonal(Vec3::splat(b1.inv_mass + b2.inv_mass))
+		+ s1.transpose() * b1.world_inv_inertia() * s1
+		+ s2.transpose() * b2.world_inv_inertia() * s2;
+	if k.determinant().abs() < 1e-9 {
+		return;
+	}
+	let p = k.inverse() * correction;
+
+	b1.translation += p * b1.inv_mass;
+	b1.rotate(b1.world_inv_inertia() * r1.cross(p));
+	b2.translation -= p * b2.inv_mass;
+	b2.rotate(-(b2.world_inv_inertia() * r2.cross(p)));
+}
+
+/// Rotates both bodies so that body1 turns by `omega` towards body2.
+fn apply_angular(b1: &mut Body, b2: &mut Body, omega: Vec3) {
+	let angle = omega.length();
+	if angle < 1e-6 {
+		return;
+	}
+	let n = omega / angle;
+	let i1 = b1.world_inv_inertia();
+	let i2 = b2.world_inv_inertia();
+	let w = n.dot(i1 * n) + n.dot(i2 * n);
+	if w == 0.0 {
+		return;
+	}
+	let p = n * (angle / w);
+
+	b1.rotate(i1 * p);
+	b2.rotate(-(i2 * p));
+}
+
+/// Rotation (axis times angle) that turns `from` into `to`.
+pub(crate) fn rotation_vector(from: Quat, to: Quat) -> Vec3 {
+	let mut dq = to * from.inverse();
+	if dq.w < 0.0 {
+		dq = -dq;
+	}
+	2.0 * Vec3::new(dq.x, dq.y, dq.z)
+}
+
+fn any_perpendicular(v: Vec3) -> Vec3 {
+	if v.x.abs() < 0.9 {
+		v.cross(Vec3::X).normalize()
+	} else {
+		v.cross(Vec3::Y).normalize()
+	}
+}
+
+/// Keeps the relative rotation of the bodies at `rest`.
+fn solve_rotation_lock(b1: &mut Body, b2: &mut Body, rest: Quat) {
+	let target = b1.rotation * rest;
+	let omega = rotation_vector(target, b2.rotation);
+	apply_angular(b1, b2, omega);
+}
+
+fn solve_anchors(b1: &mut Body, b2: &mut Body, joint: &Joint) {
+	let r1 = b1.rotation * joint.anchor1;
+	let r2 = b2.rotation * joint.anchor2;
+	let correction = b2.world_anchor(joint.anchor2) - b1.world_anchor(joint.anchor1);
+	apply_point(b1, b2, r1, r2, correction);
+}
+
+fn solve_joint(b1: &mut Body, b2: &mut Body, joint: &Joint, rest: Quat) {
+	match &joint.kind {
+		JointKind::Fixed => {
+			solve_rotation_lock(b1, b2, rest);
+			solve_anchors(b1, b2, joint);
+		}
+		JointKind::BallSocket => {
+			solve_anchors(b1, b2, joint);
+		}
+		JointKind::Hinge { limits, .. } => {
+			let a1 = b1.rotation * joint.axis;
+			let a2 = b2.rotation * (rest.inverse() * joint.axis);
+			apply_angular(b1, b2, a1.cross(a2));
+
+			if let Some((min, max)) = limits {
+				let a1 = b1.rotation * joint.axis;
+				let reference = any_perpendicular(joint.axis);
+				let ref1 = b1.rotation * reference;
+				let ref2 = b2.rotation * (rest.inverse() * reference);
+				let angle = ref1.cross(ref2).dot(a1).atan2(ref1.dot(ref2));
+				if angle < *min {
+					apply_angular(b1, b2, a1 * (angle - min));
+				} else if angle > *max {
+					apply_angular(b1, b2, a1 * (angle - max));
+				}
+			}
+
+			solve_anchors(b1, b2, joint);
+		}
+		JointKind::Slider { limits } => {
+			solve_rotation_lock(b1, b2, rest);
+
+			let axis = b1.rotation * joint.axis;
+			let r1 = b1.rotation * joint.anchor1;
+			let r2 = b2.rotation * joint.anchor2;
+			let d = b2.world_anchor(joint.anchor2) - b1.world_anchor(joint.anchor1);
+			let along = d.dot(axis);
+			let mut correction = d - axis * along;
+			if let Some((min, max)) = limits {
+				if along < *min {
+					correction += axis * (along - min);
+				} else if along > *max {
+					correction += axis * (along - max);
+				}
+			}
+			apply_positional(b1, b2, r1, r2, correction);
+		}
+		JointKind::Distance { rest_length, stiffness, .. } => {
+			if *stiffness > 0.0 {
+				return;
+			}
+			let r1 = b1.rotation * joint.anchor1;
+			let r2 = b2.rotation * joint.anchor2;
+			let d = b2.world_anchor(joint.anchor2) - b1.world_anchor(joint.anchor1);
+			let len = d.length();
+			if len < 1e-6 {
+				return;
+			}
+			apply_positional(b1, b2, r1, r2, d / len * (len - rest_length));
+		}
+	}
+}
+
+/// Velocity level parts of joints: hinge motors and distance springs.
+fn solve_joint_velocity(b1: &mut Body, b2: &mut Body, joint: &Joint, dt: f32) {
+	match &joint.kind {
+		JointKind::Hinge { motor: Some(motor), .. } => {
+			let axis = b1.rotation * joint.axis;
+			let i1 = b1.world_inv_inertia();
+			let i2 = b2.world_inv_inertia();
+			let k = axis.dot(i1 * axis) + axis.dot(i2 * axis);
+			if k == 0.0 {
+				return;
+			}
+			let relative = (b2.angular_velocity - b1.angular_velocity).dot(axis);
+			let max_impulse = motor.max_torque * dt;
+			let impulse = ((motor.target_velocity - relative) / k).clamp(-max_impulse, max_impulse);
+			b1.angular_velocity -= i1 * axis * impulse;
+			b2.angular_velocity += i2 * axis * impulse;
+		}
+		JointKind::Distance { rest_length, stiffness, damping } if *stiffness > 0.0 => {
+			let r1 = b1.rotation * joint.anchor1;
+			let r2 = b2.rotation * joint.anchor2;
+			let d = b2.world_anchor(joint.anchor2) - b1.world_anchor(joint.anchor1);
+			let len = d.length();
+			if len < 1e-6 {
+				return;
+			}
+			let n = d / len;
+			let v1 = b1.velocity + b1.angular_velocity.cross(r1);
+			let v2 = b2.velocity + b2.angular_velocity.cross(r2);
+			let force = stiffness * (len - rest_length) + damping * (v2 - v1).dot(n);
+			let impulse = n * force * dt;
+
+			b1.velocity += impulse * b1.inv_mass;
+			b1.angular_velocity += b1.world_inv_inertia() * r1.cross(impulse);
+			b2.velocity -= impulse * b2.inv_mass;
+			b2.angular_velocity -= b2.world_inv_inertia() * r2.cross(impulse);
+		}
+		_ => {}
+	}
+}
+
+/// Solves the joints touching `scene_id`, or every joint when it is `None`. Joints are projected on positions and the
+/// resulting displacement is fed back into the velocities of the bodies.
+pub fn solve_joints(state: &mut State, scene_id: Option<ArenaId<Scene>>, dt: f32) {
+	if state.joints.len() == 0 || dt <= 0.0 {
+		return;
+	}
+
+	let mut bodies: HashMap<ArenaId<Node>, Body> = HashMap::new();
+	let mut joints = Vec::new();
+	for (joint_id, joint) in state.joints.iter_mut() {
+		let (node1, node2) = match (state.nodes.get(&joint.node1), state.nodes.get(&joint.node2)) {
+			(Some(n1), Some(n2)) => (n1, n2),
+			_ => continue,
+		};
+		if scene_id.is_some() && node1.scene_id != scene_id && node2.scene_id != scene_id {
+			continue;
+		}
+		if joint.rest_rotation.is_none() {
+			joint.rest_rotation = Some(node1.rotation.inverse() * node2.rotation);
+		}
+		bodies.entry(joint.node1).or_insert_with(|| Body::new(node1));
+		bodies.entry(joint.node2).or_insert_with(|| Body::new(node2));
+		joints.push(joint_id);
+	}
+
+	for joint_id in &joints {
+		let joint = state.joints.get(joint_id).unwrap();
+		if joint.node1 == joint.node2 {
+			continue;
+		}
+		let mut b1 = bodies.remove(&joint.node1).unwrap();
+		let mut b2 = bodies.remove(&joint.node2).unwrap();
+		solve_joint_velocity(&mut b1, &mut b2, joint, dt);
+		bodies.insert(joint.node1, b1);
+		bodies.insert(joint.node2, b2);
+	}
+
+	for _ in 0..JOINT_ITERATIONS {
+		for joint_id in &joints {
+			let joint = state.joints.get(joint_id).unwrap();
+			if joint.node1 == joint.node2 {
+				continue;
+			}
+			let rest = joint.rest_rotation.unwrap_or(Quat::IDENTITY);
+			let mut b1 = bodies.remove(&joint.node1).unwrap();
+			let mut b2 = bodies.remove(&joint.node2).unwrap();
+			solve_joint(&mut b1, &mut b2, joint, rest);
+			bodies.insert(joint.node1, b1);
+			bodies.insert(joint.node2, b2);
+		}
+	}
+
+	for (node_id, body) in bodies {
+		let node = match state.nodes.get_mut(&node_id) {
+			Some(node) => node,
+			None => continue,
+		};
+		if body.inv_mass == 0.0 && body.inv_inertia == Mat3::ZERO {
+			continue;
+		}
+		node.translation = body.translation;
+		node.rotation = body.rotation;
+		node.physics.velocity = body.velocity + (body.translation - body.start_translation) / dt;
+		node.physics.angular_velocity = body.angular_velocity + rotation_vector(body.start_rotation, body.rotation) / dt;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::JointMotor;
+	use crate::PhysicsProps;
+
+	fn dynamic_node(translation: Vec3) -> Node {
+		Node {
+			translation,
+			physics: PhysicsProps {
+				typ: PhycisObjectType::Dynamic,
+				mass: 1.0,
+				..Default::default()
+			},
+			collision_shape: Some(crate::CollisionShape::Box { size: Vec3::splat(0.5) }),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn ball_socket_pulls_anchors_together() {
+		let mut state = State::default();
+		let anchor = state.nodes.insert(Node {
+			physics: PhysicsProps { typ: PhycisObjectType::Static, ..Default::default() },
+			..Default::default()
+		});
+		let bob = state.nodes.insert(dynamic_node(Vec3::new(0.05, -2.05, 0.0)));
+		state.joints.insert(Joint::new(JointKind::BallSocket, anchor, bob).anchors(Vec3::ZERO, Vec3::new(0.0, 2.0, 0.0)));
+
+		solve_joints(&mut state, None, 0.016);
+
+		let node = state.nodes.get(&bob).unwrap();
+		let world_anchor = node.translation + node.rotation * Vec3::new(0.0, 2.0, 0.0);
+		assert!(world_anchor.length() < 0.01, "anchor error {:?}", world_anchor);
+		assert_eq!(state.nodes.get(&anchor).unwrap().translation, Vec3::ZERO);
+	}
+
+	#[test]
+	fn rigid_distance_joint_keeps_length() {
+		let mut state = State::default();
+		let a = state.nodes.insert(dynamic_node(Vec3::ZERO));
+		let b = state.nodes.insert(dynamic_node(Vec3::new(5.0, 0.0, 0.0)));
+		state.joints.insert(Joint::new(JointKind::Distance { rest_length: 3.0, stiffness: 0.0, damping: 0.0 }, a, b));
+
+		solve_joints(&mut state, None, 0.016);
+
+		let pa = state.nodes.get(&a).unwrap().translation;
+		let pb = state.nodes.get(&b).unwrap().translation;
+		assert!((pa.distance(pb) - 3.0).abs() < 0.01);
+		// Equal masses share the correction
+		assert!((pa.x - 1.0).abs() < 0.01);
+	}
+
+	#[test]
+	fn hinge_keeps_axis_aligned() {
+		let mut state = State::default();
+		let frame = state.nodes.insert(Node {
+			physics: PhysicsProps { typ: PhycisObjectType::Static, ..Default::default() },
+			..Default::default()
+		});
+		let door = state.nodes.insert(dynamic_node(Vec3::ZERO));
+		let hinge = Joint::new(JointKind::Hinge { limits: None, motor: None }, frame, door).axis(Vec3::Y);
+		state.joints.insert(hinge);
+		solve_joints(&mut state, None, 0.016);
+
+		let node = state.nodes.get_mut(&door).unwrap();
+		node.rotation = Quat::from_rotation_x(0.3) * Quat::from_rotation_y(0.5);
+		for _ in 0..10 {
+			solve_joints(&mut state, None, 0.016);
+		}
+
+		let node = state.nodes.get(&door).unwrap();
+		let axis = node.rotation * Vec3::Y;
+		assert!(axis.dot(Vec3::Y) > 0.999, "axis drifted to {:?}", axis);
+	}
+
+	fn static_node() -> Node {
+		Node {
+			physics: PhysicsProps { typ: PhycisObjectType::Static, ..Default::default() },
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn hinge_motor_spins_towards_target_velocity() {
+		let mut state = State::default();
+		let frame = state.nodes.insert(static_node());
+		let wheel = state.nodes.insert(dynamic_node(Vec3::ZERO));
+		let motor = JointMotor { target_velocity: 2.0, max_torque: 1000.0 };
+		state.joints.insert(Joint::new(JointKind::Hinge { limits: None, motor: Some(motor) }, frame, wheel).axis(Vec3::Y));
+
+		solve_joints(&mut state, None, 0.016);
+
+		let spin = state.nodes.get(&wheel).unwrap().physics.angular_velocity;
+		assert!((spin.y - 2.0).abs() < 0.01, "spin {:?}", spin);
+		assert!(spin.x.abs() < 0.01 && spin.z.abs() < 0.01);
+
+		// A weak motor only gets part of the way in one step
+		let wheel = state.nodes.insert(dynamic_node(Vec3::ZERO));
+		let motor = JointMotor { target_velocity: 2.0, max_torque: 0.1 };
+		state.joints.insert(Joint::new(JointKind::Hinge { limits: None, motor: Some(motor) }, frame, wheel).axis(Vec3::Y));
+		solve_joints(&mut state, None, 0.016);
+		let spin = state.nodes.get(&wheel).unwrap().physics.angular_velocity;
+		assert!(spin.y > 0.0 && spin.y < 1.0, "spin {:?}", spin);
+	}
+
+	#[test]
+	fn hinge_limits_clamp_the_angle() {
+		let mut state = State::default();
+		let frame = state.nodes.insert(static_node());
+		let door = state.nodes.insert(dynamic_node(Vec3::ZERO));
+		let hinge = Joint::new(JointKind::Hinge { limits: Some((-0.2, 0.2)), motor: None }, frame, door).axis(Vec3::Y);
+		state.joints.insert(hinge);
+		solve_joints(&mut state, None, 0.016);
+
+		for (angle, expected) in [(1.0, 0.2), (-1.0, -0.2), (0.1, 0.1)] {
+			state.nodes.get_mut(&door).unwrap().rotation = Quat::from_rotation_y(angle);
+			for _ in 0..10 {
+				solve_joints(&mut state, None, 0.016);
+			}
+			let (axis, angle) = state.nodes.get(&door).unwrap().rotation.to_axis_angle();
+			let angle = angle * axis.y.signum();
+			assert!((angle - expected).abs() < 0.01, "angle {} expected {}", angle, expected);
+		}
+	}
+
+	#[test]
+	fn slider_moves_along_axis_within_limits() {
+		let mut state = State::default();
+		let rail = state.nodes.insert(static_node());
+		let cart = state.nodes.insert(dynamic_node(Vec3::ZERO));
+		state.joints.insert(Joint::new(JointKind::Slider { limits: Some((0.0, 1.0)) }, rail, cart).axis(Vec3::X));
+		solve_joints(&mut state, None, 0.016);
+
+		let node = state.nodes.get_mut(&cart).unwrap();
+		node.translation = Vec3::new(0.5, 0.3, -0.2);
+		node.rotation = Quat::from_rotation_z(0.3);
+		for _ in 0..10 {
+			solve_joints(&mut state, None, 0.016);
+		}
+		let node = state.nodes.get(&cart).unwrap();
+		assert!(node.translation.distance(Vec3::new(0.5, 0.0, 0.0)) < 0.01, "cart at {:?}", node.translation);
+		assert!(node.rotation.angle_between(Quat::IDENTITY) < 0.01, "cart rotated {:?}", node.rotation);
+
+		state.nodes.get_mut(&cart).unwrap().translation = Vec3::new(3.0, 0.0, 0.0);
+		solve_joints(&mut state, None, 0.016);
+		let node = state.nodes.get(&cart).unwrap();
+		assert!(node.translation.distance(Vec3::new(1.0, 0.0, 0.0)) < 0.01, "cart at {:?}", node.translation);
+	}
+
+	#[test]
+	fn fixed_joint_locks_position_and_rotation() {
+		let mut state = State::default();
+		let base = state.nodes.insert(static_node());
+		let arm = state.nodes.insert(dynamic_node(Vec3::new(0.0, 1.0, 0.0)));
+		let rest = Quat::from_rotation_x(0.4);
+		state.nodes.get_mut(&arm).unwrap().rotation = rest;
+		state.joints.insert(Joint::new(JointKind::Fixed, base, arm).anchors(Vec3::new(0.0, 1.0, 0.0), Vec3::ZERO));
+		solve_joints(&mut state, None, 0.016);
+
+		let node = state.nodes.get_mut(&arm).unwrap();
+		node.translation = Vec3::new(0.3, 1.2, 0.1);
+		node.rotation = Quat::from_rotation_y(0.5) * rest;
+		for _ in 0..10 {
+			solve_joints(&mut state, None, 0.016);
+		}
+
+		let node = state.nodes.get(&arm).unwrap();
+		assert!(node.translation.distance(Vec3::new(0.0, 1.0, 0.0)) < 0.01, "arm at {:?}", node.translation);
+		assert!(node.rotation.angle_between(rest) < 0.01, "arm rotated {:?}", node.rotation);
+	}
+}
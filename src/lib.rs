@@ -20,6 +20,19 @@ mod state;
 mod wgpu;
 mod mock_hardware;
 mod collision_detection;
+mod joints;
+mod islands;
+mod character;
+mod vehicle;
+mod cloth;
+mod force_fields;
+mod query;
+mod bvh;
+mod broad_phase;
+mod parallel;
+mod hierarchy;
+mod prefab;
+mod scene_file;
 pub mod utility;
 pub mod text;
 pub use types::*;
@@ -30,6 +43,8 @@ pub use glam::*;
 pub use log::*;
 pub use state::*;
 pub use gltf::load_gltf;
+pub use scene_file::SceneFile;
+pub use prefab::*;
 
 #[cfg(not(feature = "wgpu_winit"))]
 pub fn run<T>(app: T) -> anyhow::Result<()>
@@ -0,0 +1,50 @@
+/// Maps `items` with `f`, spread over up to `threads` scoped threads. Each
+/// thread gets a run of at least `min_per_thread` consecutive items, and the
+/// results keep the order of the items.
+pub fn par_map<T: Send, R: Send>(items: Vec<T>, threads: usize, min_per_thread: usize, f: impl Fn(T) -> R + Sync) -> Vec<R> {
+	let threads = threads.min(items.len() / min_per_thread.max(1));
+	if threads <= 1 {
+		return items.into_iter().map(f).collect();
+	}
+
+	let chunk_len = items.len().div_ceil(threads);
+	let mut chunks = Vec::new();
+	let mut items = items.into_iter();
+	loop {
+		let chunk: Vec<T> = items.by_ref().take(chunk_len).collect();
+		if chunk.is_empty() {
+			break;
+		}
+		chunks.push(chunk);
+	}
+
+	let f = &f;
+	std::thread::scope(|scope| {
+		let mut chunks = chunks.into_iter();
+		// The calling thread takes the first run itself
+		let first = chunks.next().unwrap();
+		let handles: Vec<_> = chunks
+			.map(|chunk| scope.spawn(move || chunk.into_iter().map(f).collect::<Vec<R>>()))
+			.collect();
+		let mut results: Vec<R> = first.into_iter().map(f).collect();
+		for handle in handles {
+			results.extend(handle.join().unwrap());
+		}
+		results
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn keeps_item_order() {
+		let items: Vec<usize> = (0..103).collect();
+		let expected: Vec<usize> = items.iter().map(|i| i * 2).collect();
+		assert_eq!(par_map(items.clone(), 4, 1, |i| i * 2), expected);
+		assert_eq!(par_map(items.clone(), 1, 1, |i| i * 2), expected);
+		// Too few items for more than one thread
+		assert_eq!(par_map(items, 8, 60, |i| i * 2), expected);
+	}
+}
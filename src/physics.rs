@@ -1,17 +1,28 @@
+use std::collections::HashMap;
 use std::collections::HashSet;
-use std::time::Duration;
 use std::time::Instant;
 
 use glam::Vec3;
+use crate::broad_phase::BroadPhase;
 use crate::collision_detection::obb_collide;
 use crate::collision_detection::CollisionInfo;
-use crate::spatial_grid::SpatialGrid;
+use crate::islands::build_islands;
+use crate::parallel::par_map;
+use crate::joints::rotation_vector;
+use crate::joints::solve_joints;
+use crate::query::time_of_impact;
 use crate::state::State;
+use crate::Arena;
 use crate::ArenaId;
 use crate::CollisionShape;
 use crate::ContactInfo;
 use crate::Node;
+use crate::NodeParent;
+use crate::PhycicsEvent;
 use crate::PhycisObjectType;
+use crate::PhysicsSettings;
+use crate::PhysicsStats;
+use crate::Scene;
 use crate::AABB;
 
 #[derive(Debug, Clone)]
@@ -53,15 +64,17 @@ struct Impulse {
 /// - `restitution`: The coefficient of restitution, representing the elasticity of the collision,
 ///   with values from 0.0 (perfectly inelastic) to 1.0 (perfectly elastic).
 /// - `coeff_of_friction`: The coefficient of friction, representing the resistance to sliding.
+/// - `support`: Impulse the contact exerts to hold up resting weight during the step. It is
+///   not part of the normal impulse but still lets friction act on resting bodies.
 ///
 /// # Returns
 ///
 /// An `Impulse` struct containing the normal and tangential impulses as well as the vectors `r1`
 /// and `r2` from each body's center of mass to the collision point.
 ///
-fn calculate_impulse(node1: &Node, node2: &Node, collision: &Collision, restitution: f32, coeff_of_friction: f32) -> Impulse {
-	let r1 = collision.point - node1.center_of_mass();
-	let r2 = collision.point - node2.center_of_mass();
+fn calculate_impulse(node1: &Node, node2: &Node, collision: &Collision, restitution: f32, coeff_of_friction: f32, support: f32) -> Impulse {
+	let r1 = collision.point - node1.step_center_of_mass();
+	let r2 = collision.point - node2.step_center_of_mass();
 
 	let node1_velocity = node1.physics.velocity + node1.physics.angular_velocity.cross(r1);
 	let node2_velocity = node2.physics.velocity + node2.physics.angular_velocity.cross(r2);
@@ -72,16 +85,16 @@ fn calculate_impulse(node1: &Node, node2: &Node, collision: &Collision, restitut
 		return Impulse::default();
 	}
 
-	let node1_inv_mass = if node1.physics.mass == 0.0 { 0.0 } else { 1.0 / node1.physics.mass };
-	let node2_inv_mass = if node2.physics.mass == 0.0 { 0.0 } else { 1.0 / node2.physics.mass };
+	let node1_inv_mass = node1.physics.inv_mass();
+	let node2_inv_mass = node2.physics.inv_mass();
 	let inv_mass_sum = node1_inv_mass + node2_inv_mass;
 
 	if inv_mass_sum == 0.0 {
 		return Impulse::default();
 	}
 
-	let node1_inertia_tensor = if node1.inertia_tensor() == glam::Mat3::ZERO { glam::Mat3::ZERO } else { node1.inertia_tensor().inverse() };
-	let node2_inertia_tensor = if node2.inertia_tensor() == glam::Mat3::ZERO { glam::Mat3::ZERO } else { node2.inertia_tensor().inverse() };
+	let node1_inertia_tensor = if node1_inv_mass == 0.0 || node1.inertia_tensor() == glam::Mat3::ZERO { glam::Mat3::ZERO } else { node1.inertia_tensor().inverse() };
+	let node2_inertia_tensor = if node2_inv_mass == 0.0 || node2.inertia_tensor() == glam::Mat3::ZERO { glam::Mat3::ZERO } else { node2.inertia_tensor().inverse() };
 	let term_a = collision.normal.dot(node1_inertia_tensor * (r1.cross(collision.normal)).cross(r1));
 	let term_b = collision.normal.dot(node2_inertia_tensor * (r2.cross(collision.normal)).cross(r2));
 	let j = -(1.0 + restitution) * vel_along_normal / (inv_mass_sum + term_a + term_b);
@@ -91,7 +104,7 @@ fn calculate_impulse(node1: &Node, node2: &Node, collision: &Collision, restitut
 	let vel_along_tangent = rel_velocity.dot(tangent);
 	let jt = -vel_along_tangent / (inv_mass_sum + term_a + term_b);
 
-	let max_friction = coeff_of_friction * j.abs();
+	let max_friction = coeff_of_friction * (j.abs() + support);
 	let tangent_impulse = if jt.abs() < max_friction { jt * tangent } else { max_friction * tangent * jt.signum() };
 
 	return Impulse {
@@ -121,6 +134,59 @@ fn calculate_collision_point(a: &AABB, b: &AABB) -> [f32; 3] {
 	collision_point
 }
 
+/// Bounds of the node's shape at its current position. The broad phase rect of a
+/// fast body covers its whole motion for the step, so it is only a fallback.
+fn body_aabb(node: &Node, rect: &AABB) -> AABB {
+	match &node.collision_shape {
+		Some(shape) => shape.bounds(node.translation, node.rotation),
+		None => rect.clone(),
+	}
+}
+
+/// Boxes of the collider parts of the node.
+fn part_aabbs(node: &Node, rect: &AABB) -> Vec<AABB> {
+	match &node.collision_shape {
+		Some(shape) => shape.part_aabbs(node.translation, node.rotation),
+		None => vec![rect.clone()],
+	}
+}
+
+/// Collision between the overlapping parts of two bodies. The deepest pair
+/// gives the normal and correction, the contact point is averaged over all
+/// pairs so a body resting on several parts is not tipped over.
+fn part_collision(node1_id: ArenaId<Node>, parts1: &[AABB], node2_id: ArenaId<Node>, parts2: &[AABB]) -> Option<Collision> {
+	let mut deepest: Option<(f32, Collision)> = None;
+	let mut point_sum = Vec3::ZERO;
+	let mut count = 0;
+	for a in parts1 {
+		for b in parts2 {
+			if !a.intersects(b) {
+				continue;
+			}
+			let collision = make_collision(node1_id, a, node2_id, b);
+			let depth = (a.max.min(b.max) - a.min.max(b.min)).min_element();
+			point_sum += collision.point;
+			count += 1;
+			if deepest.as_ref().is_none_or(|(d, _)| depth > *d) {
+				deepest = Some((depth, collision));
+			}
+		}
+	}
+	let (_, mut collision) = deepest?;
+	collision.point = point_sum / count as f32;
+	Some(collision)
+}
+
+fn make_collision(node1_id: ArenaId<Node>, node1_aabb: &AABB, node2_id: ArenaId<Node>, node2_aabb: &AABB) -> Collision {
+	Collision {
+		node1: node1_id,
+		node2: node2_id,
+		normal: calculate_collision_normal(node1_aabb, node2_aabb).into(),
+		point: calculate_collision_point(node1_aabb, node2_aabb).into(),
+		correction: node1_aabb.get_correction(node2_aabb),
+	}
+}
+
 fn calculate_collision_normal(a: &AABB, b: &AABB) -> [f32; 3] {
 	let center_a = [(a.min[0] + a.max[0]) / 2.0, (a.min[1] + a.max[1]) / 2.0, (a.min[2] + a.max[2]) / 2.0];
 	let center_b = [(b.min[0] + b.max[0]) / 2.0, (b.min[1] + b.max[1]) / 2.0, (b.min[2] + b.max[2]) / 2.0];
@@ -231,25 +297,89 @@ fn apply_impulse(impulse: &Impulse, node: &mut Node, r: glam::Vec3) {
 	}
 }
 
-fn resolve_collision(collision: &Collision, state: &mut State) {
-	let node1 = state.nodes.get(&collision.node1).unwrap();
-	let node2 = state.nodes.get(&collision.node2).unwrap();
+/// Node storage collisions are resolved against.
+trait NodeAccess {
+	fn node(&self, node_id: &ArenaId<Node>) -> Option<&Node>;
+	fn node_mut(&mut self, node_id: &ArenaId<Node>) -> Option<&mut Node>;
+}
+
+impl NodeAccess for Arena<Node> {
+	fn node(&self, node_id: &ArenaId<Node>) -> Option<&Node> {
+		self.get(node_id)
+	}
+
+	fn node_mut(&mut self, node_id: &ArenaId<Node>) -> Option<&mut Node> {
+		self.get_mut(node_id)
+	}
+}
+
+/// Nodes one island of collisions works on. The dynamic bodies of the island
+/// are borrowed mutably, the static and kinematic nodes they touch are shared
+/// with the other islands.
+struct IslandNodes<'a, 'b> {
+	bodies: HashMap<ArenaId<Node>, &'a mut Node>,
+	fixed: &'b HashMap<ArenaId<Node>, &'a Node>,
+}
 
-	let node1_inv_mass = if node1.physics.mass == 0.0 { 0.0 } else { 1.0 / node1.physics.mass };
-	let node2_inv_mass = if node2.physics.mass == 0.0 { 0.0 } else { 1.0 / node2.physics.mass };
+impl NodeAccess for IslandNodes<'_, '_> {
+	fn node(&self, node_id: &ArenaId<Node>) -> Option<&Node> {
+		match self.bodies.get(node_id) {
+			Some(node) => Some(&**node),
+			None => self.fixed.get(node_id).copied(),
+		}
+	}
+
+	fn node_mut(&mut self, node_id: &ArenaId<Node>) -> Option<&mut Node> {
+		self.bodies.get_mut(node_id).map(|node| &mut **node)
+	}
+}
+
+/// What processing one island of collisions changed in the system. Applied
+/// once all islands are done so they can run in parallel.
+#[derive(Default)]
+struct IslandOutcome {
+	triggers: Vec<(ArenaId<Node>, ArenaId<Node>)>,
+	woken: Vec<ArenaId<Node>>,
+	resolved: Vec<(Collision, f32)>,
+	/// Earliest time of impact of a fast pair and the index of its collision.
+	earliest: Option<(f32, usize)>,
+	fast: bool,
+}
+
+/// Resolves the collision and returns the magnitude of the applied normal impulse.
+fn resolve_collision(collision: &Collision, nodes: &mut impl NodeAccess, gravity: glam::Vec3, dt: f32) -> f32 {
+	let node1 = nodes.node(&collision.node1).unwrap();
+	let node2 = nodes.node(&collision.node2).unwrap();
+
+	let node1_inv_mass = node1.physics.inv_mass();
+	let node2_inv_mass = node2.physics.inv_mass();
 	let inv_mass_sum = node1_inv_mass + node2_inv_mass;
 
 	if inv_mass_sum == 0.0 {
-		return; // Both objects are static, no correction needed
+		return 0.0; // Both objects are static, no correction needed
 	}
 
-	let impluse = calculate_impulse(node1, node2, &collision, 0.3, 0.2);
+	// Gravity along the contact normal is cancelled during integration, so the
+	// weight resting on the contact is added to bound the friction
+	let support: f32 = [node1, node2]
+		.iter()
+		.filter(|node| node.physics.typ == PhycisObjectType::Dynamic)
+		.map(|node| node.physics.mass * (gravity * node.physics.gravity_scale).dot(collision.normal).abs() * dt)
+		.sum();
+	let impluse = calculate_impulse(node1, node2, &collision, 0.3, 0.2, support);
 	let node1_typ = node1.physics.typ.clone();
 	let node2_typ = node2.physics.typ.clone();
 
 	if node1_typ == PhycisObjectType::Dynamic {
-		let node1 = state.nodes.get_mut(&collision.node1).unwrap();
-		apply_impulse(&impluse, node1, impluse.r1);
+		let node1 = nodes.node_mut(&collision.node1).unwrap();
+		// The impulse is computed for node2, node1 receives the opposite one
+		let impulse = Impulse {
+			normal_impulse: -impluse.normal_impulse,
+			tangent_impulse: -impluse.tangent_impulse,
+			r1: impluse.r1,
+			r2: impluse.r2,
+		};
+		apply_impulse(&impulse, node1, impulse.r1);
 		let node1_correciton_ratio = node1_inv_mass / inv_mass_sum;
 		let correction = collision.correction * node1_correciton_ratio;
 		node1.translation += correction;
@@ -261,13 +391,7 @@ fn resolve_collision(collision: &Collision, state: &mut State) {
 	}
 
 	if node2_typ == PhycisObjectType::Dynamic {
-		let node2 = state.nodes.get_mut(&collision.node2).unwrap();
-		/*let impulse = Impulse {
-			normal_impulse: -impluse.normal_impulse,
-			tangent_impulse: -impluse.tangent_impulse,
-			r1: impluse.r2,
-			r2: impluse.r1,
-		};*/
+		let node2 = nodes.node_mut(&collision.node2).unwrap();
 		apply_impulse(&impluse, node2, impluse.r2);
 		let node2_correciton_ratio = node2_inv_mass / inv_mass_sum;
 		let correction = collision.correction * node2_correciton_ratio;
@@ -278,6 +402,121 @@ fn resolve_collision(collision: &Collision, state: &mut State) {
 			node_id: collision.node1,
 		});
 	}
+
+	impluse.normal_impulse.length()
+}
+
+/// Derives the velocity of kinematic nodes from how far they moved since
+/// `poses` was last updated and stores their current pose.
+pub fn update_kinematic_velocities(state: &mut State, poses: &mut HashMap<ArenaId<Node>, (Vec3, glam::Quat)>, dt: f32) {
+	for (node_id, node) in &mut state.nodes {
+		if node.physics.typ != PhycisObjectType::Kinematic {
+			continue;
+		}
+		let (translation, rotation) = poses.get(&node_id).copied().unwrap_or((node.translation, node.rotation));
+		node.physics.velocity = (node.translation - translation) / dt;
+		node.physics.angular_velocity = rotation_vector(rotation, node.rotation) / dt;
+		poses.insert(node_id, (node.translation, node.rotation));
+	}
+	poses.retain(|node_id, _| match state.nodes.get(node_id) {
+		Some(node) => node.physics.typ == PhycisObjectType::Kinematic,
+		None => false,
+	});
+}
+
+/// Where a node sits in its scene while a physics step runs in world space.
+pub(crate) struct WorldFrame {
+	/// Scale of the scene, applied after the node's own matrix.
+	scene_scale: glam::Mat4,
+	translation: Vec3,
+	rotation: glam::Quat,
+	collision_shape: Option<CollisionShape>,
+	world_translation: Vec3,
+	world_rotation: glam::Quat,
+}
+
+/// Moves every node in a simulated scene to its world pose and scales its
+/// collision shape, so a step can simulate parented and scaled nodes
+/// together. `sorted_nodes` has parents before their children. `frames` is
+/// filled in the same order and handed back to `leave_world_space` after
+/// the step.
+pub(crate) fn enter_world_space(state: &mut State, sorted_nodes: &[ArenaId<Node>], frames: &mut Vec<(ArenaId<Node>, WorldFrame)>) {
+	frames.clear();
+	for node_id in sorted_nodes {
+		let node = match state.nodes.get(node_id) {
+			Some(node) => node,
+			None => continue,
+		};
+		match node.scene_id.and_then(|scene_id| state.scenes.get(&scene_id)) {
+			Some(scene) if scene.physics_on => {}
+			_ => continue,
+		}
+		// Nodes directly in a scene keep their pose, the scene only scales them
+		let (scene_scale, (scale, world_rotation, world_translation)) = match node.parent {
+			NodeParent::Scene(scene_id) => match state.scenes.get(&scene_id) {
+				Some(scene) => (scene.scale, (node.scale * scene.scale, node.rotation, node.translation)),
+				None => continue,
+			},
+			NodeParent::Node(parent_id) => match state.nodes.get(&parent_id) {
+				Some(parent) => (Vec3::ONE, (parent.global_transform * node.matrix()).to_scale_rotation_translation()),
+				None => continue,
+			},
+			NodeParent::Orphan => continue,
+		};
+		let frame = WorldFrame {
+			scene_scale: glam::Mat4::from_scale(scene_scale),
+			translation: node.translation,
+			rotation: node.rotation,
+			collision_shape: node.collision_shape.clone(),
+			world_translation,
+			world_rotation,
+		};
+
+		let node = state.nodes.get_mut(node_id).unwrap();
+		node.translation = world_translation;
+		node.rotation = world_rotation;
+		if scale != Vec3::ONE {
+			node.collision_shape = frame.collision_shape.as_ref().map(|shape| shape.scaled(scale));
+		}
+		frames.push((*node_id, frame));
+	}
+}
+
+/// Writes the world poses produced by a step back into local space and
+/// refreshes the global transforms. Parents come first, so children are
+/// placed relative to where their parent ended up.
+pub(crate) fn leave_world_space(state: &mut State, frames: &mut Vec<(ArenaId<Node>, WorldFrame)>) {
+	for (node_id, frame) in frames.drain(..) {
+		let parent = match state.nodes.get(&node_id).map(|node| node.parent) {
+			Some(NodeParent::Node(parent_id)) => match state.nodes.get(&parent_id) {
+				Some(parent) => Some(parent.global_transform),
+				None => continue,
+			},
+			Some(_) => None,
+			None => continue,
+		};
+		let node = state.nodes.get_mut(&node_id).unwrap();
+		node.collision_shape = frame.collision_shape;
+		if node.translation == frame.world_translation && node.rotation == frame.world_rotation {
+			// Untouched nodes get their exact local pose back
+			node.translation = frame.translation;
+			node.rotation = frame.rotation;
+		} else if let Some(parent) = parent {
+			let world = glam::Mat4::from_rotation_translation(node.rotation, node.translation);
+			let (_, rotation, translation) = (parent.inverse() * world).to_scale_rotation_translation();
+			node.translation = translation;
+			node.rotation = rotation.normalize();
+		}
+		node.global_transform = parent.unwrap_or(glam::Mat4::IDENTITY) * node.matrix() * frame.scene_scale;
+	}
+}
+
+/// Checks groups, masks and ignore lists of both nodes.
+pub(crate) fn collision_allowed(node1_id: ArenaId<Node>, node1: &Node, node2_id: ArenaId<Node>, node2: &Node) -> bool {
+	if !node1.physics.collides_with(&node2.physics) {
+		return false;
+	}
+	!node1.physics.ignored_nodes.contains(&node2_id) && !node2.physics.ignored_nodes.contains(&node1_id)
 }
 
 fn get_collision(node1: &Node, node2: &Node) -> Option<CollisionInfo> {
@@ -296,43 +535,466 @@ fn get_collision(node1: &Node, node2: &Node) -> Option<CollisionInfo> {
     let transform1 = node1.global_transform;
     let transform2 = node2.global_transform;
 
-    match (shape1, shape2) {
-        (CollisionShape::Box { size: s1 }, CollisionShape::Box { size: s2 }) => {
-			obb_collide(transform1, *s1, transform2, *s2)
+    obb_collide(transform1, shape1.half_extents(), transform2, shape2.half_extents())
+}
+
+/// Contact between two nodes recorded during a physics step.
+#[derive(Debug, Clone)]
+struct ContactRecord {
+	node1: ArenaId<Node>,
+	node2: ArenaId<Node>,
+	point: glam::Vec3,
+	normal: glam::Vec3,
+	impulse: f32,
+}
+
+/// Relative speed above which pairs are resolved at their time of impact.
+const FAST_BODY_SPEED: f32 = 50.0;
+/// Distance within which a resting pair is still considered to be in contact.
+/// Keeps contacts from flickering between begin and end while bodies settle.
+const CONTACT_MARGIN: f32 = 0.05;
+
+/// Bodies slower than this are candidates for sleeping.
+const SLEEP_LINEAR_VELOCITY: f32 = 0.3;
+const SLEEP_ANGULAR_VELOCITY: f32 = 0.3;
+/// How long a whole island has to stay slow before it is put to sleep.
+const TIME_TO_SLEEP: f32 = 0.5;
+/// Applied forces that change less than this fraction between steps are a
+/// steady load, like buoyancy holding up a floating body, and allow sleep.
+const STEADY_LOAD_TOLERANCE: f32 = 0.05;
+
+/// Less work than this per thread is not worth spawning threads for.
+const MIN_ISLANDS_PER_THREAD: usize = 8;
+const MIN_BODIES_PER_THREAD: usize = 256;
+
+/// Orders events by the nodes they involve.
+fn event_key(event: &PhycicsEvent) -> (usize, usize) {
+	match event {
+		PhycicsEvent::ContactBegin { node1, node2, .. } |
+		PhycicsEvent::ContactPersist { node1, node2, .. } |
+		PhycicsEvent::ContactEnd { node1, node2 } => (node1.index(), node2.index()),
+		PhycicsEvent::TriggerEnter { sensor, node } |
+		PhycicsEvent::TriggerExit { sensor, node } => (sensor.index(), node.index()),
+	}
+}
+
+/// Steps the physics of the scenes in the given order and returns their
+/// events in the same order. With more than one thread, scenes are split into
+/// groups that share no node or joint, and each group is stepped on its own
+/// part of the state.
+pub(crate) fn step_scenes(state: &mut State, scenes: Vec<(ArenaId<Scene>, &mut PhysicsSystem, &mut BroadPhase)>, dt: f32) -> Vec<PhycicsEvent> {
+	let threads = state.physics_settings.threads;
+	if threads <= 1 || scenes.len() < 2 {
+		let mut events = Vec::new();
+		state.physics_stats.clear();
+		for (scene_id, system, broad_phase) in scenes {
+			if let Some(stats) = system.physics_update(state, broad_phase, dt) {
+				state.physics_stats.insert(scene_id, stats);
+			}
+			events.extend(system.drain_events());
 		}
-    }
+		return events;
+	}
+
+	// Scenes joined by a joint, directly or through a node outside of any
+	// scene, are stepped together
+	let scene_ids: Vec<_> = scenes.iter().map(|(scene_id, _, _)| *scene_id).collect();
+	let mut links = Vec::new();
+	let mut outside: HashMap<ArenaId<Node>, ArenaId<Scene>> = HashMap::new();
+	for (_, joint) in &state.joints {
+		let scene1 = state.nodes.get(&joint.node1).and_then(|node| node.scene_id);
+		let scene2 = state.nodes.get(&joint.node2).and_then(|node| node.scene_id);
+		let (scene, node_id) = match (scene1, scene2) {
+			(Some(a), Some(b)) => {
+				links.push((a, b));
+				continue;
+			}
+			(Some(scene), None) => (scene, joint.node2),
+			(None, Some(scene)) => (scene, joint.node1),
+			(None, None) => continue,
+		};
+		if let Some(other) = outside.insert(node_id, scene) {
+			links.push((scene, other));
+		}
+	}
+	let groups = build_islands(&scene_ids, &links);
+	let group_of: HashMap<ArenaId<Scene>, usize> = groups
+		.iter()
+		.enumerate()
+		.flat_map(|(i, group)| group.iter().map(move |scene_id| (*scene_id, i)))
+		.collect();
+	let node_group: HashMap<ArenaId<Node>, usize> = state.nodes
+		.iter()
+		.filter_map(|(node_id, node)| {
+			let scene_id = node.scene_id.or_else(|| outside.get(&node_id).copied())?;
+			Some((node_id, *group_of.get(&scene_id)?))
+		})
+		.collect();
+
+	let nodes = state.nodes.split(groups.len(), |node_id, _| node_group.get(&node_id).copied());
+	let scene_arenas = state.scenes.split(groups.len(), |scene_id, _| group_of.get(&scene_id).copied());
+	let joints = state.joints.split(groups.len(), |_, joint| {
+		node_group.get(&joint.node1).or_else(|| node_group.get(&joint.node2)).copied()
+	});
+	let mut work: Vec<_> = nodes
+		.into_iter()
+		.zip(scene_arenas)
+		.zip(joints)
+		.map(|((nodes, scenes), joints)| {
+			let part = State {
+				nodes,
+				scenes,
+				joints,
+				// Groups already use every thread
+				physics_settings: PhysicsSettings { threads: 1, ..state.physics_settings.clone() },
+				..Default::default()
+			};
+			(part, Vec::new())
+		})
+		.collect();
+	for scene in scenes {
+		work[group_of[&scene.0]].1.push(scene);
+	}
+
+	let results = par_map(work, threads, 1, |(mut part, scenes)| {
+		let mut events = Vec::new();
+		for (scene_id, system, broad_phase) in scenes {
+			if let Some(stats) = system.physics_update(&mut part, broad_phase, dt) {
+				part.physics_stats.insert(scene_id, stats);
+			}
+			events.push((scene_id, system.drain_events()));
+		}
+		(part, events)
+	});
+
+	let mut scene_events = HashMap::new();
+	state.physics_stats.clear();
+	for (part, events) in results {
+		state.nodes.restore(part.nodes);
+		state.scenes.restore(part.scenes);
+		state.joints.restore(part.joints);
+		state.physics_stats.extend(part.physics_stats);
+		scene_events.extend(events);
+	}
+	scene_ids.iter().flat_map(|scene_id| scene_events.remove(scene_id).unwrap_or_default()).collect()
 }
 
-#[derive(Debug, Default, Clone)]
+/// Hash of the physics state of every node, to compare simulations step by
+/// step. Uses FNV-1a over the exact bits so it is the same on every platform.
+pub fn physics_hash(state: &State) -> u64 {
+	let mut hash: u64 = 0xcbf29ce484222325;
+	let mut write = |bytes: &[u8]| {
+		for byte in bytes {
+			hash ^= *byte as u64;
+			hash = hash.wrapping_mul(0x100000001b3);
+		}
+	};
+	for (node_id, node) in &state.nodes {
+		write(&(node_id.index() as u64).to_le_bytes());
+		let values = [
+			node.translation.to_array(),
+			node.physics.velocity.to_array(),
+			node.physics.angular_velocity.to_array(),
+			node.physics.force.to_array(),
+			node.physics.torque.to_array(),
+			node.physics.applied_force.to_array(),
+			node.physics.applied_torque.to_array(),
+		];
+		for value in values.iter().flatten().chain(node.rotation.to_array().iter()) {
+			write(&value.to_bits().to_le_bytes());
+		}
+		write(&[node.physics.sleeping as u8]);
+	}
+	hash
+}
+
+/// Returns the pair in a stable order so (a, b) and (b, a) map to the same key.
+fn pair_key(node1: ArenaId<Node>, node2: ArenaId<Node>) -> (ArenaId<Node>, ArenaId<Node>) {
+	if node1.index() <= node2.index() {
+		(node1, node2)
+	} else {
+		(node2, node1)
+	}
+}
+
+#[derive(Debug, Clone)]
 pub struct PhysicsSystem {
+	/// Scene whose nodes this system simulates.
+	scene_id: ArenaId<Scene>,
 	gravity: glam::Vec3,
 	collision_cache: HashSet<(ArenaId<Node>, ArenaId<Node>)>,
 	broad_phase_collisions: Vec<Collision>,
-	broad_phase_collision_count: usize,
+	contacts: HashMap<(ArenaId<Node>, ArenaId<Node>), ContactRecord>,
+	prev_contacts: HashSet<(ArenaId<Node>, ArenaId<Node>)>,
+	triggers: HashSet<(ArenaId<Node>, ArenaId<Node>)>,
+	prev_triggers: HashSet<(ArenaId<Node>, ArenaId<Node>)>,
+	/// Pairs connected by a joint that must not collide with each other.
+	jointed_pairs: HashSet<(ArenaId<Node>, ArenaId<Node>)>,
+	/// How long each dynamic body has been below the sleep thresholds.
+	sleep_timers: HashMap<ArenaId<Node>, f32>,
+	/// Applied force and torque of each dynamic body in the last step, or
+	/// when it fell asleep.
+	applied_loads: HashMap<ArenaId<Node>, (glam::Vec3, glam::Vec3)>,
+	islands: Vec<Vec<ArenaId<Node>>>,
+	events: Vec<PhycicsEvent>,
 }
 
 impl PhysicsSystem {
-	pub fn new() -> Self {
+	pub fn new(scene_id: ArenaId<Scene>) -> Self {
 		Self {
+			scene_id,
 			gravity: glam::Vec3::new(0.0, -10.0, 0.0),
 			collision_cache: HashSet::new(),
 			broad_phase_collisions: Vec::new(),
-			broad_phase_collision_count: 0,
+			contacts: HashMap::new(),
+			prev_contacts: HashSet::new(),
+			triggers: HashSet::new(),
+			prev_triggers: HashSet::new(),
+			jointed_pairs: HashSet::new(),
+			sleep_timers: HashMap::new(),
+			applied_loads: HashMap::new(),
+			islands: Vec::new(),
+			events: Vec::new(),
+		}
+	}
+
+	/// Takes the contact events produced since the last call.
+	pub fn drain_events(&mut self) -> Vec<PhycicsEvent> {
+		std::mem::take(&mut self.events)
+	}
+
+	fn record_contact(&mut self, collision: &Collision, impulse: f32) {
+		let record = self.contacts
+			.entry(pair_key(collision.node1, collision.node2))
+			.or_insert(ContactRecord {
+				node1: collision.node1,
+				node2: collision.node2,
+				point: collision.point,
+				normal: collision.normal,
+				impulse: 0.0,
+			});
+		record.point = collision.point;
+		record.normal = collision.normal;
+		record.impulse += impulse;
+	}
+
+	/// Earliest impact within `dt` of the bodies with continuous collision
+	/// detection, as a collision to resolve at that time.
+	fn sweep_ccd_bodies(&self, state: &State, broad_phase: &BroadPhase, dt: f32) -> Option<(f32, Collision)> {
+		let mut earliest: Option<(f32, Collision)> = None;
+		for (node_id, node) in &state.nodes {
+			if !node.physics.ccd || !node.physics.is_awake() || node.physics.sensor || node.scene_id != Some(self.scene_id) {
+				continue;
+			}
+			let impact = time_of_impact(state, broad_phase, node_id, dt, |other_id, other| {
+				!other.physics.sensor &&
+					collision_allowed(node_id, node, other_id, other) &&
+					!self.jointed_pairs.contains(&pair_key(node_id, other_id))
+			});
+			let (toi, hit) = match impact {
+				Some(impact) => impact,
+				None => continue,
+			};
+			if earliest.as_ref().is_none_or(|(earliest_toi, _)| toi < *earliest_toi) {
+				earliest = Some((toi, Collision {
+					node1: node_id,
+					node2: hit.node_id,
+					normal: hit.normal,
+					point: hit.point,
+					correction: Vec3::ZERO,
+				}));
+			}
 		}
+		earliest
+	}
+
+	fn emit_trigger_events(&mut self) {
+		for (sensor, node) in &self.triggers {
+			if !self.prev_triggers.contains(&(*sensor, *node)) {
+				self.events.push(PhycicsEvent::TriggerEnter { sensor: *sensor, node: *node });
+			}
+		}
+
+		for (sensor, node) in &self.prev_triggers {
+			if !self.triggers.contains(&(*sensor, *node)) {
+				self.events.push(PhycicsEvent::TriggerExit { sensor: *sensor, node: *node });
+			}
+		}
+
+		self.prev_triggers = std::mem::take(&mut self.triggers);
+	}
+
+	/// Whether the applied force and torque of the body are about the same
+	/// as in the last step.
+	fn steady_load(&self, node_id: ArenaId<Node>, node: &Node) -> bool {
+		let close = |a: glam::Vec3, b: glam::Vec3| a.distance(b) <= STEADY_LOAD_TOLERANCE * a.length().max(b.length());
+		let (force, torque) = self.applied_loads.get(&node_id).copied().unwrap_or_default();
+		close(force, node.physics.applied_force) && close(torque, node.physics.applied_torque)
+	}
+
+	/// Wakes bodies that had a force, torque or velocity applied while asleep.
+	/// Applied forces only wake bodies when they differ from the load the body
+	/// fell asleep under.
+	fn wake_disturbed_bodies(&mut self, state: &mut State) {
+		for (node_id, node) in &mut state.nodes {
+			if !node.physics.sleeping || node.scene_id != Some(self.scene_id) {
+				continue;
+			}
+			if node.physics.force != glam::Vec3::ZERO ||
+				node.physics.torque != glam::Vec3::ZERO ||
+				!self.steady_load(node_id, node) ||
+				node.physics.velocity.length() > SLEEP_LINEAR_VELOCITY ||
+				node.physics.angular_velocity.length() > SLEEP_ANGULAR_VELOCITY {
+				node.physics.wake_up();
+				self.sleep_timers.remove(&node_id);
+			}
+		}
+	}
+
+	/// Builds islands from contacts and joints and puts islands to sleep once
+	/// all of their bodies have been resting long enough. Islands with a single
+	/// awake body are woken up as a whole.
+	fn update_sleeping(&mut self, state: &mut State, dt: f32) {
+		let mut bodies = Vec::new();
+		for (node_id, node) in &state.nodes {
+			if node.physics.typ != PhycisObjectType::Dynamic || node.physics.stationary || node.scene_id != Some(self.scene_id) {
+				continue;
+			}
+			bodies.push(node_id);
+			if node.physics.sleeping {
+				continue;
+			}
+			// Bodies under changing applied forces are being pushed around,
+			// a steady load that holds them still lets them rest
+			let resting = node.physics.velocity.length() < SLEEP_LINEAR_VELOCITY &&
+				node.physics.angular_velocity.length() < SLEEP_ANGULAR_VELOCITY &&
+				self.steady_load(node_id, node);
+			self.applied_loads.insert(node_id, (node.physics.applied_force, node.physics.applied_torque));
+			let timer = self.sleep_timers.entry(node_id).or_insert(0.0);
+			if resting {
+				*timer += dt;
+			} else {
+				*timer = 0.0;
+			}
+		}
+		self.sleep_timers.retain(|node_id, _| state.nodes.contains(node_id));
+		self.applied_loads.retain(|node_id, _| state.nodes.contains(node_id));
+
+		// Bodies carried or pushed by a moving kinematic body stay awake
+		for collision in &self.broad_phase_collisions {
+			let (node1, node2) = match (state.nodes.get(&collision.node1), state.nodes.get(&collision.node2)) {
+				(Some(node1), Some(node2)) => (node1, node2),
+				_ => continue,
+			};
+			if node2.physics.typ == PhycisObjectType::Kinematic && node2.physics.is_active() {
+				self.sleep_timers.insert(collision.node1, 0.0);
+			}
+			if node1.physics.typ == PhycisObjectType::Kinematic && node1.physics.is_active() {
+				self.sleep_timers.insert(collision.node2, 0.0);
+			}
+		}
+
+		let mut links: Vec<_> = self.broad_phase_collisions
+			.iter()
+			.map(|c| (c.node1, c.node2))
+			.collect();
+		for (_, joint) in &state.joints {
+			links.push((joint.node1, joint.node2));
+		}
+
+		self.islands = build_islands(&bodies, &links);
+		for island in &self.islands {
+			let resting = island.iter().all(|node_id| {
+				let node = state.nodes.get(node_id).unwrap();
+				node.physics.can_sleep && (node.physics.sleeping ||
+					self.sleep_timers.get(node_id).copied().unwrap_or(0.0) >= TIME_TO_SLEEP)
+			});
+
+			for node_id in island {
+				let node = state.nodes.get_mut(node_id).unwrap();
+				if resting {
+					node.physics.sleeping = true;
+					node.physics.velocity = glam::Vec3::ZERO;
+					node.physics.angular_velocity = glam::Vec3::ZERO;
+				} else if node.physics.sleeping {
+					node.physics.wake_up();
+					self.sleep_timers.insert(*node_id, 0.0);
+				}
+			}
+		}
+	}
+
+	/// Compares the contacts of this step with the previous one and queues
+	/// begin, persist and end events.
+	fn emit_contact_events(&mut self, broad_phase: &BroadPhase) {
+		for key in &self.prev_contacts {
+			if self.contacts.contains_key(key) {
+				continue;
+			}
+			let (a, b) = match (broad_phase.get_node_rect(key.0), broad_phase.get_node_rect(key.1)) {
+				(Some(a), Some(b)) => (a, b),
+				_ => continue,
+			};
+			let margin = glam::Vec3::splat(CONTACT_MARGIN);
+			let expanded = AABB::new(a.min - margin, a.max + margin);
+			if expanded.intersects(b) {
+				self.contacts.insert(*key, ContactRecord {
+					node1: key.0,
+					node2: key.1,
+					point: calculate_collision_point(a, b).into(),
+					normal: calculate_collision_normal(a, b).into(),
+					impulse: 0.0,
+				});
+			}
+		}
+
+		for (key, c) in &self.contacts {
+			let event = if self.prev_contacts.contains(key) {
+				PhycicsEvent::ContactPersist {
+					node1: c.node1,
+					node2: c.node2,
+					point: c.point,
+					normal: c.normal,
+					impulse: c.impulse,
+				}
+			} else {
+				PhycicsEvent::ContactBegin {
+					node1: c.node1,
+					node2: c.node2,
+					point: c.point,
+					normal: c.normal,
+					impulse: c.impulse,
+				}
+			};
+			self.events.push(event);
+		}
+
+		for key in &self.prev_contacts {
+			if !self.contacts.contains_key(key) {
+				self.events.push(PhycicsEvent::ContactEnd {
+					node1: key.0,
+					node2: key.1,
+				});
+			}
+		}
+
+		self.prev_contacts = self.contacts.keys().copied().collect();
+		self.contacts.clear();
 	}
 	
-	pub fn node_physics_update(&mut self, node: &mut Node, dt: f32) {
+	pub fn node_physics_update(&self, node: &mut Node, dt: f32) {
 		// Linear dynamics
 		let mass = node.physics.mass;
-		let gravity_force = if mass > 0.0 { self.gravity * mass } else { glam::Vec3::ZERO };
-		let mut total_force = node.physics.force + gravity_force;
+		let gravity = self.gravity * node.physics.gravity_scale;
+		let gravity_force = if mass > 0.0 { gravity * mass } else { glam::Vec3::ZERO };
+		let mut total_force = node.physics.force + node.physics.applied_force + gravity_force;
 		if !node.contacts.is_empty() {
 			let mut net_contact_normal = glam::Vec3::ZERO;
 			for contact in &node.contacts {
 				net_contact_normal += contact.normal;
 			}
 			net_contact_normal = net_contact_normal.normalize_or_zero();
-			let gravity_along_normal = self.gravity.project_onto(net_contact_normal);
+			let gravity_along_normal = gravity.project_onto(net_contact_normal);
 			total_force -= gravity_along_normal * mass;
 		}
 		let acceleration = if mass > 0.0 { total_force / mass } else { glam::Vec3::ZERO };
@@ -345,7 +1007,7 @@ impl PhysicsSystem {
 		}
 	
 		// Angular dynamics
-		let torque = node.physics.torque;
+		let torque = node.physics.torque + node.physics.applied_torque;
 		let inertia_tensor = node.inertia_tensor();
 	
 		// Invert inertia tensor if determinant is large enough to avoid numerical instability
@@ -365,89 +1027,258 @@ impl PhysicsSystem {
 				node.physics.angular_velocity.normalize(),
 				node.physics.angular_velocity.length() * dt,
 			);
+			// Bodies turn around their center of mass, which may be off the node origin
+			let center = node.step_center_of_mass();
+			node.translation = center + rotation_delta * (node.translation - center);
 			node.rotation = (rotation_delta * node.rotation).normalize();
 		}
 		node.physics.angular_acceleration = angular_acceleration;
 	}
 	
 	
-	fn update_nodes(&mut self, state: &mut State, dt: f32) {
-		for (_, node) in &mut state.nodes {
-			if node.physics.typ == crate::PhycisObjectType::Dynamic && !node.physics.stationary {
-				self.node_physics_update(node, dt);
+	fn integrate(&self, state: &mut State, dt: f32, stats: &mut PhysicsStats) {
+		let timer = Instant::now();
+		self.update_nodes(state, dt);
+		stats.integration_time += timer.elapsed();
+	}
+
+	fn update_nodes(&self, state: &mut State, dt: f32) {
+		let threads = state.physics_settings.threads;
+		let bodies: Vec<&mut Node> = state.nodes
+			.iter_mut()
+			.map(|(_, node)| node)
+			.filter(|node| node.scene_id == Some(self.scene_id) &&
+				node.physics.typ == crate::PhycisObjectType::Dynamic && !node.physics.stationary && !node.physics.sleeping)
+			.collect();
+		par_map(bodies, threads, MIN_BODIES_PER_THREAD, |node| self.node_physics_update(node, dt));
+	}
+
+	/// Splits the collisions into islands of dynamic bodies touching each
+	/// other and processes the islands on the configured threads. Islands
+	/// share no dynamic body, so the result doesn't depend on how they run.
+	fn process_islands(&self, state: &mut State, collisions: &[Collision], broad_phase: &BroadPhase, step_dt: f32, dt: f32) -> Vec<IslandOutcome> {
+		let mut bodies = Vec::new();
+		let mut involved = HashSet::new();
+		for collision in collisions {
+			for node_id in [collision.node1, collision.node2] {
+				let dynamic = state.nodes.get(&node_id).is_some_and(|node| node.physics.typ == PhycisObjectType::Dynamic);
+				if involved.insert(node_id) && dynamic {
+					bodies.push(node_id);
+				}
+			}
+		}
+		let links: Vec<_> = collisions.iter().map(|c| (c.node1, c.node2)).collect();
+		let islands = build_islands(&bodies, &links);
+		let island_of: HashMap<ArenaId<Node>, usize> = islands
+			.iter()
+			.enumerate()
+			.flat_map(|(i, island)| island.iter().map(move |node_id| (*node_id, i)))
+			.collect();
+
+		// Pairs without a dynamic body have nothing to resolve
+		let mut island_collisions = vec![Vec::new(); islands.len()];
+		for (index, collision) in collisions.iter().enumerate() {
+			if let Some(island) = island_of.get(&collision.node1).or_else(|| island_of.get(&collision.node2)) {
+				island_collisions[*island].push((index, collision));
 			}
 		}
+
+		let mut island_bodies: Vec<HashMap<_, _>> = islands.iter().map(|_| HashMap::new()).collect();
+		let mut fixed = HashMap::new();
+		for (node_id, node) in &mut state.nodes {
+			match island_of.get(&node_id) {
+				Some(island) => {
+					island_bodies[*island].insert(node_id, node);
+				}
+				None if involved.contains(&node_id) => {
+					fixed.insert(node_id, &*node);
+				}
+				None => {}
+			}
+		}
+
+		let fixed = &fixed;
+		let work: Vec<_> = island_bodies.into_iter().zip(island_collisions).collect();
+		par_map(work, state.physics_settings.threads, MIN_ISLANDS_PER_THREAD, |(bodies, collisions)| {
+			let mut nodes = IslandNodes { bodies, fixed };
+			self.process_island(&collisions, &mut nodes, broad_phase, step_dt, dt)
+		})
+	}
+
+	/// Resolves the slow collisions of one island in order and finds the
+	/// earliest impact among its fast pairs.
+	fn process_island(&self, collisions: &[(usize, &Collision)], nodes: &mut IslandNodes, broad_phase: &BroadPhase, step_dt: f32, dt: f32) -> IslandOutcome {
+		let mut outcome = IslandOutcome::default();
+		let mut earliest_toi = dt;
+		for (index, collision) in collisions {
+			let node1 = nodes.node(&collision.node1).unwrap();
+			let node2 = nodes.node(&collision.node2).unwrap();
+
+			// Overlaps involving sensors are only recorded
+			if node1.physics.sensor {
+				outcome.triggers.push((collision.node1, collision.node2));
+			}
+			if node2.physics.sensor {
+				outcome.triggers.push((collision.node2, collision.node1));
+			}
+			if node1.physics.sensor || node2.physics.sensor {
+				continue;
+			}
+
+			// A moving body wakes the sleeping body it runs into
+			let wake = if node1.physics.sleeping && node2.physics.is_active() {
+				Some(collision.node1)
+			} else if node2.physics.sleeping && node1.physics.is_active() {
+				Some(collision.node2)
+			} else {
+				None
+			};
+			if let Some(node_id) = wake {
+				if let Some(node) = nodes.node_mut(&node_id) {
+					node.physics.wake_up();
+					outcome.woken.push(node_id);
+				}
+			}
+			let node1 = nodes.node(&collision.node1).unwrap();
+			let node2 = nodes.node(&collision.node2).unwrap();
+
+			let rel_velocity = node2.physics.velocity - node1.physics.velocity;
+
+			if self.collision_cache.contains(&(collision.node1, collision.node2)) || rel_velocity.length() < FAST_BODY_SPEED {
+				let impulse = resolve_collision(collision, nodes, self.gravity, step_dt);
+				outcome.resolved.push(((*collision).clone(), impulse));
+				continue;
+			}
+			outcome.fast = true;
+
+			let node1_aabb = body_aabb(node1, broad_phase.get_node_rect(collision.node1).unwrap());
+			let node2_aabb = body_aabb(node2, broad_phase.get_node_rect(collision.node2).unwrap());
+
+			if let Some(toi) = calculate_toi(&node1_aabb, &node2_aabb, rel_velocity, dt) {
+				if toi < earliest_toi {
+					earliest_toi = toi;
+					outcome.earliest = Some((toi, *index));
+				}
+			}
+		}
+		outcome
 	}	
 	
-	fn detect_collisions(&mut self, state: &mut State, grid: &SpatialGrid) {
+	fn detect_collisions(&mut self, state: &mut State, broad_phase: &BroadPhase, stats: &mut PhysicsStats) {
 		self.broad_phase_collisions.clear();
-		for cell in grid.cells.values() {
-			if cell.len() < 2 {
-				continue;
+		let timer = Instant::now();
+		let mut pairs = broad_phase.pairs();
+		// Pairs come in the order the broad phase stores nodes, which depends
+		// on how often it was refreshed between steps
+		if state.physics_settings.deterministic {
+			for pair in &mut pairs {
+				*pair = pair_key(pair.0, pair.1);
 			}
+			pairs.sort_by_key(|(a, b)| (a.index(), b.index()));
+		}
+		stats.broad_phase_pairs += pairs.len();
+		stats.broad_phase_time += timer.elapsed();
 
-			for i in 0..cell.len() {
-				let node1_id = cell[i];
-				let node1_aabb = match grid.get_node_rect(node1_id) {
-					Some(a) => a,
-					None => continue
-				};
-				for j in i+1..cell.len() {
-					let node2_id = cell[j];
-					if self.broad_phase_collisions.iter().any(|c: &Collision| 
-						(c.node1 == node1_id && c.node2 == node2_id) || 
-						(c.node1 == node2_id && c.node2 == node1_id)) {
-						continue;
-					}
-					let node2_aabb = match grid.get_node_rect(node2_id) {
-						Some(a) => a,
-						None => continue
-					};
-					if !node1_aabb.intersects(&node2_aabb) {
-						continue;
-					}
-					log::info!("node1: {:?}, node2: {:?} aabb intersect", node1_id, node2_id);
-					let correction = node1_aabb.get_correction(&node2_aabb) * 1.0;
-					self.broad_phase_collisions.push(Collision {
-						node1: node1_id,
-						node2: node2_id,
-						normal: calculate_collision_normal(&node1_aabb, &node2_aabb).into(),
-						point: calculate_collision_point(&node1_aabb, &node2_aabb).into(),
-						correction,
-					});
-
-					/*let node1 = state.nodes.get(&node1_id).unwrap();
-					let node2 = state.nodes.get(&node2_id).unwrap();
-					if node1.collision_shape.is_none() || node2.collision_shape.is_none() {
+		let timer = Instant::now();
+		for (node1_id, node2_id) in pairs {
+			let (node1_aabb, node2_aabb) = match (broad_phase.get_node_rect(node1_id), broad_phase.get_node_rect(node2_id)) {
+				(Some(a), Some(b)) => (a, b),
+				_ => continue,
+			};
+			if !node1_aabb.intersects(node2_aabb) {
+				continue;
+			}
+			if self.jointed_pairs.contains(&pair_key(node1_id, node2_id)) {
+				continue;
+			}
+			let (node1, node2) = match (state.nodes.get(&node1_id), state.nodes.get(&node2_id)) {
+				(Some(node1), Some(node2)) => (node1, node2),
+				_ => continue,
+			};
+			if !collision_allowed(node1_id, node1, node2_id, node2) {
+				continue;
+			}
+			// Resting pairs are skipped, sensors keep reporting overlaps
+			if !node1.physics.is_active() && !node2.physics.is_active() &&
+				!node1.physics.sensor && !node2.physics.sensor {
+				continue;
+			}
+			let parts1 = part_aabbs(node1, node1_aabb);
+			let parts2 = part_aabbs(node2, node2_aabb);
+			let collision = match part_collision(node1_id, &parts1, node2_id, &parts2) {
+				Some(collision) => {
+					stats.contacts += 1;
+					collision
+				}
+				None => {
+					// Swept rects of fast bodies may overlap before the bodies do.
+					// Those pairs are kept only for the time of impact search.
+					let rel_velocity = node2.physics.velocity - node1.physics.velocity;
+					if rel_velocity.length() < FAST_BODY_SPEED {
 						continue;
 					}
-					log::info!("node1.translation: {:?}", node1.translation);
-					log::info!("node2.translation: {:?}", node2.translation);
-					let collision_info = match get_collision(node1, node2) {
-						Some(info) => info,
-						None => continue,
-					};
-
-					log::info!("collision_info: {:?}", collision_info);
-
-					self.broad_phase_collisions.push(Collision {
-						node1: node1_id,
-						node2: node2_id,
-						normal: collision_info.normal,
-						point: collision_info.contact_point,
-						correction: collision_info.correction,
-					});*/
+					make_collision(node1_id, &body_aabb(node1, node1_aabb), node2_id, &body_aabb(node2, node2_aabb))
 				}
+			};
+			self.broad_phase_collisions.push(collision);
+		}
+		stats.narrow_phase_time += timer.elapsed();
+	}
+
+	/// Several steps can run per frame, so bodies moved by the previous step
+	/// have to be refreshed in the broad phase before detecting collisions.
+	/// Fast bodies cover their whole motion so they can't skip past anything.
+	fn refresh_rects(&self, state: &State, broad_phase: &mut BroadPhase, dt: f32) {
+		for (node_id, node) in &state.nodes {
+			if node.physics.sleeping {
+				continue;
+			}
+			let (shape, rect) = match (&node.collision_shape, broad_phase.get_node_rect(node_id)) {
+				(Some(shape), Some(rect)) => (shape, rect),
+				_ => continue,
+			};
+			let mut aabb = shape.bounds(node.translation, node.rotation);
+			if node.physics.is_awake() && node.physics.velocity.length() >= FAST_BODY_SPEED {
+				aabb = aabb.merge(&shape.bounds(node.translation + node.physics.velocity * dt, node.rotation));
+			}
+			if aabb.min != rect.min || aabb.max != rect.max {
+				broad_phase.set_node(node_id, aabb);
 			}
 		}
 	}
 
-	pub fn physics_update(&mut self, state: &mut State, grid: &mut SpatialGrid, mut dt: f32) {
-		let timer = Instant::now();
+	/// Steps the scene by `dt`. Returns what the step did, or `None` when the
+	/// scene is gone or its physics is off.
+	pub fn physics_update(&mut self, state: &mut State, broad_phase: &mut BroadPhase, mut dt: f32) -> Option<PhysicsStats> {
+		let total = Instant::now();
+		let mut stats = PhysicsStats::default();
+
+		match state.scenes.get(&self.scene_id) {
+			Some(scene) if scene.physics_on => self.gravity = scene.gravity,
+			_ => return None,
+		}
 
 		for (_, node) in &mut state.nodes {
-			node.contacts.clear();
+			if node.scene_id == Some(self.scene_id) {
+				node.contacts.clear();
+			}
+		}
+
+		let timer = Instant::now();
+		self.refresh_rects(state, broad_phase, dt);
+		stats.broad_phase_time += timer.elapsed();
+
+		let timer = Instant::now();
+		self.wake_disturbed_bodies(state);
+		stats.sleeping_time += timer.elapsed();
+
+		self.jointed_pairs.clear();
+		for (_, joint) in &state.joints {
+			if !joint.collide_connected {
+				self.jointed_pairs.insert(pair_key(joint.node1, joint.node2));
+			}
 		}
+		let step_dt = dt;
 
 	    let min_dt = 0.0001; // Minimum time increment to prevent infinite loops
 		let max_iterations = 10; // Maximum iterations to prevent infinite loops
@@ -458,54 +1289,56 @@ impl PhysicsSystem {
 
 			let mut earliest_toi = dt;
 			let mut earliest_collision = None;
+			let mut earliest_is_swept = false;
 
 			// Detect potential collisions without moving the nodes
-			self.detect_collisions(state, grid);
-
-			if self.broad_phase_collisions.len() != self.broad_phase_collision_count {
-				self.broad_phase_collision_count = self.broad_phase_collisions.len();
-				log::info!("collision count: {}", self.broad_phase_collision_count);
-			}
+			self.detect_collisions(state, broad_phase, &mut stats);
+			let timer = Instant::now();
+			let swept_impact = self.sweep_ccd_bodies(state, broad_phase, dt);
+			stats.narrow_phase_time += timer.elapsed();
 
-			if self.broad_phase_collisions.is_empty() {
+			if self.broad_phase_collisions.is_empty() && swept_impact.is_none() {
 				// No collisions, update nodes for remaining dt and exit
-				self.update_nodes(state, dt);
+				self.integrate(state, dt, &mut stats);
 				break;
 			}
 
 			let mut there_is_fast_boy = false;
-			// Find the earliest collision
-			for collision in &self.broad_phase_collisions {
-				let node1 = state.nodes.get(&collision.node1).unwrap();
-				let node2 = state.nodes.get(&collision.node2).unwrap();
-
-				if node1.physics.typ == PhycisObjectType::Static && node2.physics.typ == PhycisObjectType::Static {
-					continue;
+			let collisions = std::mem::take(&mut self.broad_phase_collisions);
+			let timer = Instant::now();
+			let outcomes = self.process_islands(state, &collisions, broad_phase, step_dt, dt);
+			stats.solver_passes += 1;
+			stats.solver_time += timer.elapsed();
+			// Merged in island order, ties go to the collision found first
+			let mut earliest_index = None;
+			for outcome in outcomes {
+				self.triggers.extend(outcome.triggers);
+				for node_id in outcome.woken {
+					self.sleep_timers.insert(node_id, 0.0);
 				}
-
-				log::info!("collision: {:?}", collision);
-
-				if self.collision_cache.contains(&(collision.node1, collision.node2)) {
-					resolve_collision(&collision, state);
-					continue;
+				for (collision, impulse) in &outcome.resolved {
+					self.record_contact(collision, *impulse);
 				}
-
-				let rel_velocity = node2.physics.velocity - node1.physics.velocity;
-
-				if rel_velocity.length() < 50.0 {
-					resolve_collision(&collision, state);
-					continue;
+				there_is_fast_boy |= outcome.fast;
+				if let Some((toi, index)) = outcome.earliest {
+					if toi < earliest_toi || (toi == earliest_toi && earliest_index.is_some_and(|earliest| index < earliest)) {
+						earliest_toi = toi;
+						earliest_index = Some(index);
+					}
 				}
-				there_is_fast_boy = true;
+			}
+			if let Some(index) = earliest_index {
+				earliest_collision = Some(collisions[index].clone());
+			}
 
-				let node1_aabb = grid.get_node_rect(collision.node1).unwrap();
-				let node2_aabb = grid.get_node_rect(collision.node2).unwrap();
+			self.broad_phase_collisions = collisions;
 
-				if let Some(toi) = calculate_toi(&node1_aabb, &node2_aabb, rel_velocity, dt) {
-					if toi < earliest_toi {
-						earliest_toi = toi;
-						earliest_collision = Some(collision.clone());
-					}
+			if let Some((toi, collision)) = swept_impact {
+				there_is_fast_boy = true;
+				if toi < earliest_toi {
+					earliest_toi = toi;
+					earliest_collision = Some(collision);
+					earliest_is_swept = true;
 				}
 			}
 
@@ -516,31 +1349,145 @@ impl PhysicsSystem {
 			});
 
 			if !there_is_fast_boy {
-				self.update_nodes(state, dt);
+				self.integrate(state, dt, &mut stats);
 				break;
 			}
-			log::info!("There is a fast boy, need to do toi");
 
 			if let Some(collision) = earliest_collision {
 				// Avoid zero TOI causing infinite loops
 				let time_step = if earliest_toi < min_dt { min_dt } else { earliest_toi };
 				
 				// Update nodes to the time just before collision
-				self.update_nodes(state, time_step);
+				self.integrate(state, time_step, &mut stats);
 				dt -= time_step;
-
-				// Resolve collision
-				resolve_collision(&collision, state);
+				stats.toi_substeps += 1;
+
+				let timer = Instant::now();
+
+				// Resolve collision with the contact at the time of impact. Swept
+				// impacts already carry the contact of the touching shapes.
+				let collision = if earliest_is_swept {
+					collision
+				} else {
+					let node1 = state.nodes.get(&collision.node1).unwrap();
+					let node2 = state.nodes.get(&collision.node2).unwrap();
+					let node1_rect = broad_phase.get_node_rect(collision.node1).unwrap();
+					let node2_rect = broad_phase.get_node_rect(collision.node2).unwrap();
+					let parts1 = part_aabbs(node1, node1_rect);
+					let parts2 = part_aabbs(node2, node2_rect);
+					match part_collision(collision.node1, &parts1, collision.node2, &parts2) {
+						Some(collision) => collision,
+						None => make_collision(collision.node1, &body_aabb(node1, node1_rect), collision.node2, &body_aabb(node2, node2_rect)),
+					}
+				};
+				let impulse = resolve_collision(&collision, &mut state.nodes, self.gravity, step_dt);
+				self.record_contact(&collision, impulse);
 				self.collision_cache.insert((collision.node1, collision.node2));
+				stats.solver_time += timer.elapsed();
 			} else {
 				// No collisions within remaining dt, update nodes and exit
-				self.update_nodes(state, dt);
+				self.integrate(state, dt, &mut stats);
 				break;
 			}
 		}
-		let elapsed = timer.elapsed();
-		if elapsed > Duration::from_millis(10) {
-			log::info!("Physics update took {:?}", elapsed);
+		let timer = Instant::now();
+		solve_joints(state, Some(self.scene_id), step_dt);
+		stats.solver_time += timer.elapsed();
+
+		let timer = Instant::now();
+		self.update_sleeping(state, step_dt);
+		for (_, node) in &state.nodes {
+			if node.scene_id != Some(self.scene_id) || node.physics.typ != PhycisObjectType::Dynamic {
+				continue;
+			}
+			match node.physics.sleeping {
+				true => stats.sleeping_bodies += 1,
+				false => stats.awake_bodies += 1,
+			}
+		}
+		stats.sleeping_time += timer.elapsed();
+
+		self.emit_contact_events(broad_phase);
+		self.emit_trigger_events();
+		if state.physics_settings.deterministic {
+			self.events.sort_by_key(event_key);
 		}
+		// Queries run between steps and look nodes up by their rects
+		let timer = Instant::now();
+		self.refresh_rects(state, broad_phase, 0.0);
+		stats.broad_phase_time += timer.elapsed();
+
+		stats.total_time = total.elapsed();
+		Some(stats)
+	}
+}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::PhysicsProps;
+
+	fn moving_box(nodes: &mut Arena<Node>, typ: PhycisObjectType, x: f32, velocity: f32) -> (ArenaId<Node>, AABB) {
+		let node_id = nodes.insert(Node {
+			translation: Vec3::new(x, 0.0, 0.0),
+			physics: PhysicsProps {
+				typ,
+				mass: 1.0,
+				velocity: Vec3::new(velocity, 0.0, 0.0),
+				..Default::default()
+			},
+			collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.5) }),
+			..Default::default()
+		});
+		(node_id, AABB::new(Vec3::new(x - 0.5, -0.5, -0.5), Vec3::new(x + 0.5, 0.5, 0.5)))
+	}
+
+	#[test]
+	fn head_on_impulse_pushes_bodies_apart() {
+		let mut nodes = Arena::new();
+		let (node1, aabb1) = moving_box(&mut nodes, PhycisObjectType::Dynamic, 0.0, 5.0);
+		let (node2, aabb2) = moving_box(&mut nodes, PhycisObjectType::Dynamic, 0.9, 0.0);
+		resolve_collision(&make_collision(node1, &aabb1, node2, &aabb2), &mut nodes, Vec3::ZERO, 1.0 / 60.0);
+
+		let v1 = nodes.get(&node1).unwrap().physics.velocity;
+		let v2 = nodes.get(&node2).unwrap().physics.velocity;
+		assert!(v1.x < v2.x, "Bodies should separate {:?} {:?}", v1, v2);
+		assert!(v2.x > 0.0, "{:?}", v2);
+		assert!((v1.x + v2.x - 5.0).abs() < 1e-4, "Momentum should be kept {:?} {:?}", v1, v2);
+	}
+
+	#[test]
+	fn static_body_mass_does_not_soften_contacts() {
+		let mut nodes = Arena::new();
+		let (node1, aabb1) = moving_box(&mut nodes, PhycisObjectType::Dynamic, 0.0, 5.0);
+		// Static bodies push like infinite mass whatever their mass is set to
+		let (wall, aabb2) = moving_box(&mut nodes, PhycisObjectType::Static, 0.9, 0.0);
+		resolve_collision(&make_collision(node1, &aabb1, wall, &aabb2), &mut nodes, Vec3::ZERO, 1.0 / 60.0);
+
+		let node = nodes.get(&node1).unwrap();
+		assert!((node.physics.velocity.x + 1.5).abs() < 1e-4, "Should bounce with restitution 0.3 {:?}", node.physics.velocity);
+		assert!((node.translation.x + 0.1).abs() < 1e-4, "Should get the whole correction {:?}", node.translation);
+		let wall = nodes.get(&wall).unwrap();
+		assert_eq!(wall.translation, Vec3::new(0.9, 0.0, 0.0));
+		assert_eq!(wall.physics.velocity, Vec3::ZERO);
+	}
+
+	#[test]
+	fn friction_holds_resting_bodies() {
+		let mut nodes = Arena::new();
+		let (node1, _) = moving_box(&mut nodes, PhycisObjectType::Dynamic, 0.0, 1.0);
+		let (floor, _) = moving_box(&mut nodes, PhycisObjectType::Static, 0.0, 0.0);
+		nodes.get_mut(&node1).unwrap().translation.y = 0.95;
+		let aabb1 = AABB::new(Vec3::new(-0.5, 0.45, -0.5), Vec3::new(0.5, 1.45, 0.5));
+		let aabb2 = AABB::new(Vec3::new(-5.0, -0.5, -5.0), Vec3::new(5.0, 0.5, 5.0));
+		let collision = make_collision(node1, &aabb1, floor, &aabb2);
+
+		// Sliding without falling, the normal impulse alone allows no friction
+		let dt = 1.0 / 60.0;
+		resolve_collision(&collision, &mut nodes, Vec3::new(0.0, -10.0, 0.0), dt);
+		let velocity = nodes.get(&node1).unwrap().physics.velocity;
+		assert!((velocity.x - (1.0 - 0.2 * 10.0 * dt)).abs() < 1e-4, "{:?}", velocity);
+
+		resolve_collision(&collision, &mut nodes, Vec3::ZERO, dt);
+		assert_eq!(nodes.get(&node1).unwrap().physics.velocity, velocity, "No weight, no friction");
 	}
-}
\ No newline at end of file
+}
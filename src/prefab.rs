@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use glam::Quat;
+use glam::Vec3;
+use crate::state::State;
+use crate::Animation;
+use crate::ArenaId;
+use crate::Camera;
+use crate::Node;
+use crate::NodeParent;
+use crate::PointLight;
+use crate::RayCast;
+
+/// Template of a node subtree that can be spawned many times. Instances share
+/// the meshes of the template and get their own copies of the lights,
+/// cameras, ray casts and animations attached to its nodes.
+#[derive(Debug, Clone)]
+pub struct Prefab {
+	/// Root first and parents before their children. Ids of nodes inside the
+	/// template are positions in this list.
+	nodes: Vec<Node>,
+	point_lights: Vec<PointLight>,
+	cameras: Vec<Camera>,
+	raycasts: Vec<RayCast>,
+	animations: Vec<Animation>,
+}
+
+/// Change made to the nodes of an instance with a given name.
+type NodeOverride = (String, Box<dyn Fn(&mut Node)>);
+
+/// Changes applied to a single instance of a prefab.
+#[derive(Default)]
+pub struct PrefabOverrides {
+	pub translation: Option<Vec3>,
+	pub rotation: Option<Quat>,
+	pub scale: Option<Vec3>,
+	nodes: Vec<NodeOverride>,
+}
+
+impl PrefabOverrides {
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	pub fn translation(mut self, translation: Vec3) -> Self {
+		self.translation = Some(translation);
+		self
+	}
+
+	pub fn rotation(mut self, rotation: Quat) -> Self {
+		self.rotation = Some(rotation);
+		self
+	}
+
+	pub fn scale(mut self, scale: Vec3) -> Self {
+		self.scale = Some(scale);
+		self
+	}
+
+	/// Changes the nodes named `name` once they are spawned.
+	pub fn node<F: Fn(&mut Node) + 'static>(mut self, name: &str, change: F) -> Self {
+		self.nodes.push((name.to_string(), Box::new(change)));
+		self
+	}
+}
+
+/// What spawning a prefab added to the state.
+#[derive(Debug, Clone)]
+pub struct PrefabInstance {
+	pub root: ArenaId<Node>,
+	/// In the order of the prefab, root first.
+	pub nodes: Vec<ArenaId<Node>>,
+	pub point_lights: Vec<ArenaId<PointLight>>,
+	pub cameras: Vec<ArenaId<Camera>>,
+	pub raycasts: Vec<ArenaId<RayCast>>,
+	pub animations: Vec<ArenaId<Animation>>,
+}
+
+impl Prefab {
+	/// Template of the node and its descendants. References to nodes outside
+	/// of the subtree, like ignored nodes or animation channels, are dropped.
+	pub fn from_node(state: &State, node_id: ArenaId<Node>) -> Option<Self> {
+		if !state.nodes.contains(&node_id) {
+			return None;
+		}
+		let order = state.current_hierarchy().subtree(node_id);
+		let local: HashMap<ArenaId<Node>, ArenaId<Node>> = order
+			.iter()
+			.enumerate()
+			.map(|(index, node_id)| (*node_id, ArenaId::new(index)))
+			.collect();
+		let to_local = |node_id: Option<ArenaId<Node>>| node_id.and_then(|node_id| local.get(&node_id).copied());
+
+		let nodes = order
+			.iter()
+			.map(|node_id| {
+				let mut node = state.nodes.get(node_id).unwrap().clone();
+				node.parent = match node.parent {
+					NodeParent::Node(parent) if *node_id != order[0] => NodeParent::Node(local[&parent]),
+					_ => NodeParent::Orphan,
+				};
+				node.physics.ignored_nodes = node.physics.ignored_nodes.iter().filter_map(|id| to_local(Some(*id))).collect();
+				node.contacts.clear();
+				node
+			})
+			.collect();
+		let point_lights = state.point_lights
+			.iter()
+			.filter_map(|(_, light)| Some(PointLight { node_id: Some(to_local(light.node_id)?), ..light.clone() }))
+			.collect();
+		let cameras = state.cameras
+			.iter()
+			.filter_map(|(_, camera)| Some(Camera { node_id: Some(to_local(camera.node_id)?), ..camera.clone() }))
+			.collect();
+		let raycasts = state.raycasts
+			.iter()
+			.filter_map(|(_, raycast)| {
+				let mut raycast = raycast.clone();
+				raycast.node_id = to_local(Some(raycast.node_id))?;
+				raycast.filter.exclude = raycast.filter.exclude.iter().filter_map(|id| to_local(Some(*id))).collect();
+				raycast.intersects.clear();
+				raycast.hits.clear();
+				Some(raycast)
+			})
+			.collect();
+		let animations = state.animations
+			.iter()
+			.filter_map(|(_, animation)| {
+				let mut animation = animation.clone();
+				animation.channels.retain_mut(|channel| match to_local(Some(channel.target.node_id)) {
+					Some(node_id) => {
+						channel.target.node_id = node_id;
+						true
+					}
+					None => false,
+				});
+				(!animation.channels.is_empty()).then_some(animation)
+			})
+			.collect();
+
+		Some(Self {
+			nodes,
+			point_lights,
+			cameras,
+			raycasts,
+			animations,
+		})
+	}
+
+	/// Position of the first node named `name` in `PrefabInstance::nodes`.
+	pub fn find(&self, name: &str) -> Option<usize> {
+		self.nodes.iter().position(|node| node.name.as_deref() == Some(name))
+	}
+
+	/// Adds a copy of the template under `parent`. Only touches the nodes of
+	/// the template, so the cost doesn't grow with the size of the state.
+	pub fn instantiate(&self, state: &mut State, parent: NodeParent, overrides: &PrefabOverrides) -> PrefabInstance {
+		let mut nodes = Vec::with_capacity(self.nodes.len());
+		for node in &self.nodes {
+			let mut node = node.clone();
+			// Parents come first so their ids are known already
+			node.parent = match node.parent {
+				NodeParent::Node(local) => NodeParent::Node(nodes[local.index()]),
+				_ => parent,
+			};
+			nodes.push(state.insert_node(node));
+		}
+		let root = nodes[0];
+		for node_id in &nodes {
+			let node = state.nodes.get_mut(node_id).unwrap();
+			for ignored in &mut node.physics.ignored_nodes {
+				*ignored = nodes[ignored.index()];
+			}
+		}
+
+		let node = state.nodes.get_mut(&root).unwrap();
+		node.translation = overrides.translation.unwrap_or(node.translation);
+		node.rotation = overrides.rotation.unwrap_or(node.rotation);
+		node.scale = overrides.scale.unwrap_or(node.scale);
+		for (name, change) in &overrides.nodes {
+			for (template, node_id) in self.nodes.iter().zip(&nodes) {
+				if template.name.as_deref() == Some(name.as_str()) {
+					let node = state.nodes.get_mut(node_id).unwrap();
+					change(node);
+					state.hierarchy.set_parent(*node_id, node.parent);
+				}
+			}
+		}
+
+		let point_lights = self.point_lights
+			.iter()
+			.map(|light| state.point_lights.insert(PointLight {
+				node_id: light.node_id.map(|local| nodes[local.index()]),
+				..light.clone()
+			}))
+			.collect();
+		let cameras = self.cameras
+			.iter()
+			.map(|camera| state.cameras.insert(Camera {
+				node_id: camera.node_id.map(|local| nodes[local.index()]),
+				..camera.clone()
+			}))
+			.collect();
+		let raycasts = self.raycasts
+			.iter()
+			.map(|raycast| {
+				let mut raycast = raycast.clone();
+				raycast.node_id = nodes[raycast.node_id.index()];
+				for excluded in &mut raycast.filter.exclude {
+					*excluded = nodes[excluded.index()];
+				}
+				state.raycasts.insert(raycast)
+			})
+			.collect();
+		let animations = self.animations
+			.iter()
+			.map(|animation| {
+				let mut animation = animation.clone();
+				for channel in &mut animation.channels {
+					channel.target.node_id = nodes[channel.target.node_id.index()];
+				}
+				state.animations.insert(animation)
+			})
+			.collect();
+
+		PrefabInstance {
+			root,
+			nodes,
+			point_lights,
+			cameras,
+			raycasts,
+			animations,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::AnimationChannel;
+	use crate::AnimationTarget;
+	use crate::AnimationTargetPath;
+	use crate::Mesh;
+	use crate::Scene;
+	use super::*;
+
+	fn enemy(state: &mut State) -> ArenaId<Node> {
+		let mesh = state.meshes.insert(Mesh::new());
+		let body = state.nodes.insert(Node {
+			name: Some("body".to_string()),
+			mesh: Some(mesh),
+			..Default::default()
+		});
+		let gun = state.nodes.insert(Node {
+			name: Some("gun".to_string()),
+			parent: NodeParent::Node(body),
+			translation: Vec3::new(0.5, 1.0, 0.0),
+			..Default::default()
+		});
+		state.nodes.get_mut(&gun).unwrap().physics.ignored_nodes = vec![body];
+		state.point_lights.insert(PointLight {
+			node_id: Some(gun),
+			..PointLight::new()
+		});
+		state.raycasts.insert(RayCast::new(gun, 20.0));
+		let mut animation = Animation::new();
+		animation.channels.push(AnimationChannel {
+			sampler: 0,
+			target: AnimationTarget { node_id: gun, path: AnimationTargetPath::Rotation },
+		});
+		state.animations.insert(animation);
+		body
+	}
+
+	#[test]
+	fn instances_get_their_own_attachments() {
+		let mut state = State::default();
+		let body = enemy(&mut state);
+		let scene_id = state.scenes.insert(Scene::new());
+		let prefab = Prefab::from_node(&state, body).unwrap();
+		let gun = prefab.find("gun").unwrap();
+
+		let overrides = PrefabOverrides::new()
+			.translation(Vec3::new(3.0, 0.0, 0.0))
+			.node("gun", |node| node.physics.mass = 2.0);
+		let first = prefab.instantiate(&mut state, NodeParent::Scene(scene_id), &overrides);
+		let second = prefab.instantiate(&mut state, NodeParent::Scene(scene_id), &PrefabOverrides::new());
+
+		for instance in [&first, &second] {
+			let root = state.nodes.get(&instance.root).unwrap();
+			assert_eq!(root.parent, NodeParent::Scene(scene_id));
+			assert_eq!(root.mesh, state.nodes.get(&body).unwrap().mesh, "Instances share meshes");
+			let gun_id = instance.nodes[gun];
+			let gun = state.nodes.get(&gun_id).unwrap();
+			assert_eq!(gun.parent, NodeParent::Node(instance.root));
+			assert_eq!(gun.physics.ignored_nodes, vec![instance.root]);
+			assert_eq!(state.point_lights.get(&instance.point_lights[0]).unwrap().node_id, Some(gun_id));
+			assert_eq!(state.raycasts.get(&instance.raycasts[0]).unwrap().node_id, gun_id);
+			assert_eq!(state.animations.get(&instance.animations[0]).unwrap().channels[0].target.node_id, gun_id);
+		}
+		assert_eq!(state.nodes.get(&first.root).unwrap().translation, Vec3::new(3.0, 0.0, 0.0));
+		assert_eq!(state.nodes.get(&first.nodes[gun]).unwrap().physics.mass, 2.0);
+		assert_eq!(state.nodes.get(&second.root).unwrap().translation, Vec3::ZERO);
+		assert_eq!(state.nodes.get(&second.nodes[gun]).unwrap().physics.mass, 0.0);
+		assert_eq!(state.point_lights.iter().count(), 3);
+		assert_eq!(state.raycasts.iter().count(), 3);
+		assert_eq!(state.animations.iter().count(), 3);
+	}
+
+	#[test]
+	fn spawning_many_instances_only_adds_their_nodes() {
+		let mut state = State::default();
+		let body = enemy(&mut state);
+		let prefab = Prefab::from_node(&state, body).unwrap();
+		for _ in 0..200 {
+			prefab.instantiate(&mut state, NodeParent::Orphan, &PrefabOverrides::new());
+		}
+		assert_eq!(state.nodes.iter().count(), 2 * 201);
+		assert!(Prefab::from_node(&state, ArenaId::new(10_000)).is_none());
+	}
+}
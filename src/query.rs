@@ -0,0 +1,624 @@
+use std::collections::HashSet;
+
+use glam::Quat;
+use glam::Vec3;
+use crate::broad_phase::BroadPhase;
+use crate::state::State;
+use crate::ArenaId;
+use crate::CollisionShape;
+use crate::Node;
+use crate::QueryFilter;
+use crate::RayHit;
+use crate::Scene;
+use crate::AABB;
+
+const PROJECTION_ITERATIONS: usize = 32;
+const ADVANCEMENT_ITERATIONS: usize = 64;
+/// Distance at which shapes are considered touching.
+const CONTACT_TOLERANCE: f32 = 1e-4;
+
+pub(crate) fn closest_on_segment(a: Vec3, b: Vec3, point: Vec3) -> Vec3 {
+	let ab = b - a;
+	let len = ab.length_squared();
+	if len <= 1e-8 {
+		return a;
+	}
+	let t = ((point - a).dot(ab) / len).clamp(0.0, 1.0);
+	a + ab * t
+}
+
+/// Collision shape placed in the world.
+#[derive(Debug, Clone)]
+enum Convex {
+	Box { center: Vec3, rotation: Quat, half: Vec3 },
+	Capsule { a: Vec3, b: Vec3, radius: f32 },
+	Sphere { center: Vec3, radius: f32 },
+}
+
+impl Convex {
+	fn new(shape: &CollisionShape, translation: Vec3, rotation: Quat) -> Self {
+		match shape {
+			CollisionShape::Box { size } => Convex::Box {
+				center: translation,
+				rotation,
+				half: *size,
+			},
+			CollisionShape::Capsule { radius, half_height } => {
+				let offset = rotation * Vec3::new(0.0, *half_height, 0.0);
+				Convex::Capsule {
+					a: translation - offset,
+					b: translation + offset,
+					radius: *radius,
+				}
+			}
+			CollisionShape::Sphere { radius } => Convex::Sphere {
+				center: translation,
+				radius: *radius,
+			},
+			// Compound shapes are split into their parts before this, a whole
+			// one is only approximated by its bounds
+			CollisionShape::Compound { .. } => Convex::Box {
+				center: translation + rotation * shape.center_of_mass(),
+				rotation,
+				half: shape.half_extents(),
+			},
+		}
+	}
+
+	fn center(&self) -> Vec3 {
+		match self {
+			Convex::Box { center, .. } => *center,
+			Convex::Capsule { a, b, .. } => (*a + *b) * 0.5,
+			Convex::Sphere { center, .. } => *center,
+		}
+	}
+
+	fn translated(&self, offset: Vec3) -> Self {
+		match self.clone() {
+			Convex::Box { center, rotation, half } => Convex::Box { center: center + offset, rotation, half },
+			Convex::Capsule { a, b, radius } => Convex::Capsule { a: a + offset, b: b + offset, radius },
+			Convex::Sphere { center, radius } => Convex::Sphere { center: center + offset, radius },
+		}
+	}
+
+	/// Closest point of the shape to `point`, or `point` itself when inside.
+	fn project(&self, point: Vec3) -> Vec3 {
+		match self {
+			Convex::Box { center, rotation, half } => {
+				let local = rotation.inverse() * (point - *center);
+				*center + *rotation * local.clamp(-*half, *half)
+			}
+			Convex::Capsule { a, b, radius } => project_sphere(closest_on_segment(*a, *b, point), *radius, point),
+			Convex::Sphere { center, radius } => project_sphere(*center, *radius, point),
+		}
+	}
+
+	/// Outward surface normal closest to `point`, used when `point` is inside.
+	fn inner_normal(&self, point: Vec3) -> Vec3 {
+		match self {
+			Convex::Box { center, rotation, half } => {
+				let local = rotation.inverse() * (point - *center);
+				let depth = *half - local.abs();
+				let axis = if depth.x < depth.y && depth.x < depth.z {
+					0
+				} else if depth.y < depth.z {
+					1
+				} else {
+					2
+				};
+				let mut normal = Vec3::ZERO;
+				normal[axis] = if local[axis] < 0.0 { -1.0 } else { 1.0 };
+				*rotation * normal
+			}
+			Convex::Capsule { a, b, .. } => (point - closest_on_segment(*a, *b, point)).normalize_or_zero(),
+			Convex::Sphere { center, .. } => (point - *center).normalize_or_zero(),
+		}
+	}
+}
+
+fn project_sphere(center: Vec3, radius: f32, point: Vec3) -> Vec3 {
+	let offset = point - center;
+	if offset.length() <= radius {
+		point
+	} else {
+		center + offset.normalize() * radius
+	}
+}
+
+/// Closest points between two convex shapes by alternating projections.
+/// Returns the point on `a` and the point on `b`, which are equal when the
+/// shapes overlap.
+fn closest_points(a: &Convex, b: &Convex) -> (Vec3, Vec3) {
+	let mut on_a = a.project(b.center());
+	let mut on_b = b.project(on_a);
+	for _ in 0..PROJECTION_ITERATIONS {
+		let next = a.project(on_b);
+		let done = next.distance_squared(on_a) < 1e-12;
+		on_a = next;
+		on_b = b.project(on_a);
+		if done {
+			break;
+		}
+	}
+	(on_a, on_b)
+}
+
+fn ray_box(origin: Vec3, dir: Vec3, center: Vec3, rotation: Quat, half: Vec3) -> Option<(f32, Vec3)> {
+	let inv = rotation.inverse();
+	let origin = inv * (origin - center);
+	let dir = inv * dir;
+
+	let mut t_enter = f32::NEG_INFINITY;
+	let mut t_exit = f32::INFINITY;
+	let mut normal = Vec3::ZERO;
+	for i in 0..3 {
+		if dir[i].abs() < 1e-8 {
+			if origin[i].abs() > half[i] {
+				return None;
+			}
+			continue;
+		}
+		let t1 = (-half[i] - origin[i]) / dir[i];
+		let t2 = (half[i] - origin[i]) / dir[i];
+		let (near, far) = if t1 < t2 { (t1, t2) } else { (t2, t1) };
+		if near > t_enter {
+			t_enter = near;
+			normal = Vec3::ZERO;
+			normal[i] = -dir[i].signum();
+		}
+		t_exit = t_exit.min(far);
+	}
+
+	if t_enter > t_exit || t_exit < 0.0 {
+		return None;
+	}
+	if t_enter < 0.0 {
+		// Starts inside the box
+		return Some((0.0, -(rotation * dir)));
+	}
+	Some((t_enter, rotation * normal))
+}
+
+fn ray_sphere(origin: Vec3, dir: Vec3, center: Vec3, radius: f32) -> Option<(f32, Vec3)> {
+	let offset = origin - center;
+	let c = offset.length_squared() - radius * radius;
+	if c <= 0.0 {
+		return Some((0.0, -dir));
+	}
+	let b = offset.dot(dir);
+	let discriminant = b * b - c;
+	if b > 0.0 || discriminant < 0.0 {
+		return None;
+	}
+	let t = -b - discriminant.sqrt();
+	Some((t, (origin + dir * t - center).normalize()))
+}
+
+/// Distance and normal where a ray with a unit `dir` enters the shape.
+fn ray_convex(origin: Vec3, dir: Vec3, max_dist: f32, shape: &Convex) -> Option<(f32, Vec3)> {
+	let hit = match shape {
+		Convex::Box { center, rotation, half } => ray_box(origin, dir, *center, *rotation, *half),
+		Convex::Sphere { center, radius } => ray_sphere(origin, dir, *center, *radius),
+		Convex::Capsule { .. } => {
+			let point = Convex::Sphere { center: origin, radius: 0.0 };
+			sweep_convex(&point, dir, max_dist, shape)
+		}
+	};
+	hit.filter(|(distance, _)| *distance <= max_dist)
+}
+
+/// Moves `moving` along the unit `dir` until it touches `target`. Each step
+/// advances by the current distance, which can never skip past the target.
+/// Grazing sweeps that run out of steps report the distance reached so far.
+fn sweep_convex(moving: &Convex, dir: Vec3, max_dist: f32, target: &Convex) -> Option<(f32, Vec3)> {
+	let mut travelled = 0.0;
+	let mut normal = -dir;
+	for _ in 0..ADVANCEMENT_ITERATIONS {
+		let shape = moving.translated(dir * travelled);
+		let (on_moving, on_target) = closest_points(&shape, target);
+		let distance = on_moving.distance(on_target);
+		if distance <= CONTACT_TOLERANCE {
+			if travelled == 0.0 {
+				normal = target.inner_normal(on_target);
+			}
+			return Some((travelled, normal));
+		}
+		normal = (on_moving - on_target) / distance;
+		if normal.dot(dir) >= 0.0 {
+			// Moving away from the target
+			return None;
+		}
+		let next = travelled + distance - CONTACT_TOLERANCE * 0.5;
+		if next > max_dist {
+			return None;
+		}
+		travelled = next;
+	}
+	Some((travelled, normal))
+}
+
+/// Where the node poses of a query come from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Poses {
+	/// `global_transform`, as of the last render update.
+	Global,
+	/// `translation` and `rotation`, which are world space during a physics step.
+	Step,
+}
+
+/// Shapes of the nodes in the scene the filter accepts. `candidates` narrows
+/// the search down to the nodes the broad phase found, `None` tests all of them.
+fn scene_shapes<'a>(state: &'a State, scene_id: ArenaId<Scene>, filter: &'a QueryFilter, candidates: Option<Vec<ArenaId<Node>>>, poses: Poses) -> impl Iterator<Item = (ArenaId<Node>, Convex)> + 'a {
+	let nodes: Box<dyn Iterator<Item = (ArenaId<Node>, &Node)>> = match candidates {
+		Some(ids) => Box::new(ids.into_iter().filter_map(|node_id| Some((node_id, state.nodes.get(&node_id)?)))),
+		None => Box::new(state.nodes.iter()),
+	};
+	nodes.filter_map(move |(node_id, node)| {
+		if node.scene_id != Some(scene_id) || !filter.accepts(node_id, node) {
+			return None;
+		}
+		let shape = node.collision_shape.as_ref()?;
+		let convexes = match poses {
+			Poses::Global => query_convexes(shape, node.global_transform),
+			Poses::Step => node_convexes(shape, node.translation, node.rotation),
+		};
+		Some(convexes.into_iter().map(move |convex| (node_id, convex)))
+	})
+	.flatten()
+}
+
+/// One convex per part of the shape placed at `transform`.
+fn query_convexes(shape: &CollisionShape, transform: glam::Mat4) -> Vec<Convex> {
+	let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+	node_convexes(&shape.scaled(scale), translation, rotation)
+}
+
+fn node_convexes(shape: &CollisionShape, translation: Vec3, rotation: Quat) -> Vec<Convex> {
+	shape.parts()
+		.iter()
+		.map(|part| Convex::new(&part.shape, translation + rotation * part.offset, rotation * part.rotation))
+		.collect()
+}
+
+/// Box that holds the shape in any rotation.
+fn rotated_bounds(shape: &CollisionShape, translation: Vec3) -> AABB {
+	let reach = Vec3::splat(shape.half_extents().length());
+	AABB::new(translation - reach, translation + reach)
+}
+
+/// Earliest impact of the shape of `node_id` within `dt`, with every body
+/// moving at its current velocity. Works on the current node poses instead of
+/// global transforms so it can run in the middle of a physics step. Other
+/// nodes come from the broad phase, whose rects of fast bodies already cover
+/// their motion. Returns the time of impact and the hit on the other node.
+pub(crate) fn time_of_impact(state: &State, broad_phase: &BroadPhase, node_id: ArenaId<Node>, dt: f32, accept: impl Fn(ArenaId<Node>, &Node) -> bool) -> Option<(f32, RayHit)> {
+	let node = state.nodes.get(&node_id)?;
+	let shape = node.collision_shape.as_ref()?;
+	let moving = node_convexes(shape, node.translation, node.rotation);
+	let bounds = rotated_bounds(shape, node.translation);
+	let reach = bounds.merge(&rotated_bounds(shape, node.translation + node.physics.velocity * dt));
+
+	let mut earliest: Option<(f32, RayHit)> = None;
+	for other_id in broad_phase.aabb_candidates(&reach) {
+		let other = match state.nodes.get(&other_id) {
+			Some(other) => other,
+			None => continue,
+		};
+		if other_id == node_id || other.scene_id != node.scene_id || !accept(other_id, other) {
+			continue;
+		}
+		let other_shape = match &other.collision_shape {
+			Some(shape) => shape,
+			None => continue,
+		};
+		let motion = (node.physics.velocity - other.physics.velocity) * dt;
+		let max_dist = motion.length();
+		if max_dist <= CONTACT_TOLERANCE {
+			continue;
+		}
+		let swept = bounds.merge(&rotated_bounds(shape, node.translation + motion));
+		if !swept.intersects(&rotated_bounds(other_shape, other.translation)) {
+			continue;
+		}
+		let dir = motion / max_dist;
+		for target in node_convexes(other_shape, other.translation, other.rotation) {
+			for part in &moving {
+				let (distance, normal) = match sweep_convex(part, dir, max_dist, &target) {
+					Some(hit) => hit,
+					None => continue,
+				};
+				// Touching shapes that slide along or apart don't impact
+				if normal.dot(dir) >= 0.0 {
+					continue;
+				}
+				let time = distance / max_dist * dt;
+				if earliest.as_ref().is_some_and(|(t, _)| *t <= time) {
+					continue;
+				}
+				let (_, point) = closest_points(&part.translated(dir * distance), &target);
+				earliest = Some((time, RayHit {
+					node_id: other_id,
+					point,
+					normal,
+					distance,
+				}));
+			}
+		}
+	}
+	earliest
+}
+
+fn ray_hits<'a>(state: &'a State, scene_id: ArenaId<Scene>, origin: Vec3, dir: Vec3, max_dist: f32, filter: &'a QueryFilter, candidates: Option<Vec<ArenaId<Node>>>) -> impl Iterator<Item = RayHit> + 'a {
+	scene_shapes(state, scene_id, filter, candidates, Poses::Global).filter_map(move |(node_id, shape)| {
+		let (distance, normal) = ray_convex(origin, dir, max_dist, &shape)?;
+		Some(RayHit {
+			node_id,
+			point: origin + dir * distance,
+			normal,
+			distance,
+		})
+	})
+}
+
+/// Every node in the scene the ray passes through, nearest first.
+pub fn raycast_all(state: &State, scene_id: ArenaId<Scene>, origin: Vec3, dir: Vec3, max_dist: f32, filter: &QueryFilter) -> Vec<RayHit> {
+	let dir = dir.normalize_or_zero();
+	if dir == Vec3::ZERO {
+		return vec![];
+	}
+	let candidates = state.broad_phases.get(&scene_id)
+		.map(|broad_phase| broad_phase.ray_candidates(origin, dir, max_dist));
+	let mut hits: Vec<RayHit> = ray_hits(state, scene_id, origin, dir, max_dist, filter, candidates).collect();
+	hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+	// Compound shapes report only their nearest part
+	let mut seen = HashSet::new();
+	hits.retain(|hit| seen.insert(hit.node_id));
+	hits
+}
+
+/// Nearest node the ray hits. Grid scenes walk the cells along the ray and
+/// stop once a hit lies within the cells already walked.
+pub fn raycast(state: &State, scene_id: ArenaId<Scene>, origin: Vec3, dir: Vec3, max_dist: f32, filter: &QueryFilter) -> Option<RayHit> {
+	let grid = match state.broad_phases.get(&scene_id) {
+		Some(BroadPhase::Grid(grid)) => grid,
+		_ => return raycast_all(state, scene_id, origin, dir, max_dist, filter).into_iter().next(),
+	};
+	let dir = dir.normalize_or_zero();
+	let mut tested = HashSet::new();
+	let mut nearest: Option<RayHit> = None;
+	grid.traverse_ray(origin, dir, max_dist, |nodes, t_exit| {
+		let untested: Vec<_> = nodes.iter().copied().filter(|node_id| tested.insert(*node_id)).collect();
+		for hit in ray_hits(state, scene_id, origin, dir, max_dist, filter, Some(untested)) {
+			if nearest.as_ref().is_none_or(|nearest| hit.distance < nearest.distance) {
+				nearest = Some(hit);
+			}
+		}
+		// Nodes in later cells can only be hit further away
+		nearest.as_ref().is_none_or(|hit| hit.distance > t_exit)
+	});
+	nearest
+}
+
+/// First node `shape` placed at `transform` touches when moved along `dir`.
+pub fn sweep(state: &State, scene_id: ArenaId<Scene>, shape: &CollisionShape, transform: glam::Mat4, dir: Vec3, max_dist: f32, filter: &QueryFilter) -> Option<RayHit> {
+	let dir = dir.normalize_or_zero();
+	if dir == Vec3::ZERO {
+		return None;
+	}
+	let moving = query_convexes(shape, transform);
+	let candidates = match state.broad_phases.get(&scene_id) {
+		Some(broad_phase) if max_dist.is_finite() => {
+			let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+			let shape = shape.scaled(scale);
+			let swept = shape.bounds(translation, rotation).merge(&shape.bounds(translation + dir * max_dist, rotation));
+			Some(broad_phase.aabb_candidates(&swept))
+		}
+		_ => None,
+	};
+	scene_shapes(state, scene_id, filter, candidates, Poses::Global)
+		.flat_map(|(node_id, target)| {
+			moving.iter().filter_map(move |moving| {
+				let (distance, normal) = sweep_convex(moving, dir, max_dist, &target)?;
+				let (_, point) = closest_points(&moving.translated(dir * distance), &target);
+				Some(RayHit {
+					node_id,
+					point,
+					normal,
+					distance,
+				})
+			})
+		})
+		.min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+/// Nodes whose shapes overlap `shape` placed at `transform`.
+pub fn overlap(state: &State, scene_id: ArenaId<Scene>, shape: &CollisionShape, transform: glam::Mat4, filter: &QueryFilter) -> Vec<ArenaId<Node>> {
+	overlap_poses(state, scene_id, shape, transform, filter, Poses::Global)
+}
+
+/// `overlap` against the current node poses, for use in the middle of a
+/// physics step. `transform` is in world space.
+pub(crate) fn step_overlap(state: &State, scene_id: ArenaId<Scene>, shape: &CollisionShape, transform: glam::Mat4, filter: &QueryFilter) -> Vec<ArenaId<Node>> {
+	overlap_poses(state, scene_id, shape, transform, filter, Poses::Step)
+}
+
+fn overlap_poses(state: &State, scene_id: ArenaId<Scene>, shape: &CollisionShape, transform: glam::Mat4, filter: &QueryFilter, poses: Poses) -> Vec<ArenaId<Node>> {
+	let query = query_convexes(shape, transform);
+	let candidates = state.broad_phases.get(&scene_id).map(|broad_phase| {
+		let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+		let bounds = shape.scaled(scale).bounds(translation, rotation);
+		let margin = Vec3::splat(CONTACT_TOLERANCE);
+		broad_phase.aabb_candidates(&AABB::new(bounds.min - margin, bounds.max + margin))
+	});
+	let mut found = Vec::new();
+	for (node_id, target) in scene_shapes(state, scene_id, filter, candidates, poses) {
+		if found.contains(&node_id) {
+			continue;
+		}
+		let touches = query.iter().any(|query| {
+			let (on_query, on_target) = closest_points(query, &target);
+			on_query.distance(on_target) <= CONTACT_TOLERANCE
+		});
+		if touches {
+			found.push(node_id);
+		}
+	}
+	found
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::spatial_grid::SpatialGrid;
+	use crate::Mat4;
+
+	fn scene_with_boxes() -> (State, ArenaId<Scene>, ArenaId<Node>, ArenaId<Node>) {
+		let mut state = State::default();
+		let scene_id = state.scenes.insert(Scene::new());
+		let wall = state.nodes.insert(Node {
+			translation: Vec3::new(0.0, 0.0, 5.0),
+			global_transform: Mat4::from_translation(Vec3::new(0.0, 0.0, 5.0)),
+			collision_shape: Some(CollisionShape::Box { size: Vec3::new(2.0, 2.0, 0.5) }),
+			scene_id: Some(scene_id),
+			..Default::default()
+		});
+		let ball = state.nodes.insert(Node {
+			translation: Vec3::new(0.0, 0.0, 10.0),
+			global_transform: Mat4::from_translation(Vec3::new(0.0, 0.0, 10.0)),
+			collision_shape: Some(CollisionShape::Sphere { radius: 1.0 }),
+			scene_id: Some(scene_id),
+			..Default::default()
+		});
+		(state, scene_id, wall, ball)
+	}
+
+	#[test]
+	fn raycast_reports_point_normal_and_distance() {
+		let (mut state, scene_id, wall, ball) = scene_with_boxes();
+
+		let hit = state.raycast(scene_id, Vec3::ZERO, Vec3::Z, 100.0, &QueryFilter::new()).unwrap();
+		assert_eq!(hit.node_id, wall);
+		assert!((hit.distance - 4.5).abs() < 1e-4);
+		assert!(hit.point.abs_diff_eq(Vec3::new(0.0, 0.0, 4.5), 1e-4));
+		assert!(hit.normal.abs_diff_eq(Vec3::NEG_Z, 1e-4));
+
+		let hits = state.raycast_all(scene_id, Vec3::ZERO, Vec3::Z, 100.0, &QueryFilter::new());
+		assert_eq!(hits.iter().map(|h| h.node_id).collect::<Vec<_>>(), vec![wall, ball]);
+		assert!((hits[1].distance - 9.0).abs() < 1e-4);
+
+		let mut filter = QueryFilter::new();
+		filter.exclude.push(wall);
+		assert_eq!(state.raycast(scene_id, Vec3::ZERO, Vec3::Z, 100.0, &filter).unwrap().node_id, ball);
+		assert!(state.raycast(scene_id, Vec3::ZERO, Vec3::Z, 4.0, &QueryFilter::new()).is_none());
+
+		// Rotated box reports the normal of the rotated face
+		let node = state.nodes.get_mut(&wall).unwrap();
+		node.rotation = Quat::from_rotation_y(std::f32::consts::FRAC_PI_4);
+		node.global_transform = node.matrix();
+		let hit = state.raycast(scene_id, Vec3::ZERO, Vec3::Z, 100.0, &QueryFilter::new()).unwrap();
+		let expected = Quat::from_rotation_y(std::f32::consts::FRAC_PI_4) * Vec3::NEG_Z;
+		assert!(hit.normal.abs_diff_eq(expected, 1e-4), "{:?}", hit.normal);
+	}
+
+	#[test]
+	fn grid_raycast_matches_testing_every_node() {
+		let (mut state, scene_id, _, _) = scene_with_boxes();
+		for (i, x) in [-3.0, -1.5, 1.5, 3.0].iter().enumerate() {
+			state.nodes.insert(Node {
+				global_transform: Mat4::from_translation(Vec3::new(*x, 0.5 * i as f32, 7.0 + i as f32)),
+				collision_shape: Some(CollisionShape::Sphere { radius: 0.4 }),
+				scene_id: Some(scene_id),
+				..Default::default()
+			});
+		}
+		let mut grid = SpatialGrid::new(1.0);
+		for (node_id, node) in &state.nodes {
+			let (_, rotation, translation) = node.global_transform.to_scale_rotation_translation();
+			grid.set_node(node_id, node.collision_shape.as_ref().unwrap().bounds(translation, rotation));
+		}
+		let mut with_grid = state.clone();
+		with_grid.broad_phases.insert(scene_id, BroadPhase::Grid(grid));
+
+		let filter = QueryFilter::new();
+		for i in 0..64 {
+			let angle = i as f32 / 64.0 * std::f32::consts::TAU;
+			let origin = Vec3::new(0.0, 0.3, -1.0);
+			let dir = Vec3::new(angle.cos() * 0.5, angle.sin() * 0.2, 1.0);
+			let expected = state.raycast(scene_id, origin, dir, f32::INFINITY, &filter).map(|hit| hit.node_id);
+			let found = with_grid.raycast(scene_id, origin, dir, f32::INFINITY, &filter).map(|hit| hit.node_id);
+			assert_eq!(found, expected, "ray {}", i);
+			let expected: Vec<_> = state.raycast_all(scene_id, origin, dir, 12.0, &filter).iter().map(|hit| hit.node_id).collect();
+			let found: Vec<_> = with_grid.raycast_all(scene_id, origin, dir, 12.0, &filter).iter().map(|hit| hit.node_id).collect();
+			assert_eq!(found, expected, "ray {}", i);
+		}
+	}
+
+	#[test]
+	fn sweep_stops_at_first_contact() {
+		let (state, scene_id, wall, _) = scene_with_boxes();
+
+		let sphere = CollisionShape::Sphere { radius: 0.5 };
+		let hit = state.sweep(scene_id, &sphere, Mat4::IDENTITY, Vec3::Z, 100.0, &QueryFilter::new()).unwrap();
+		assert_eq!(hit.node_id, wall);
+		assert!((hit.distance - 4.0).abs() < 1e-3, "{}", hit.distance);
+		assert!(hit.normal.abs_diff_eq(Vec3::NEG_Z, 1e-3));
+		assert!(hit.point.abs_diff_eq(Vec3::new(0.0, 0.0, 4.5), 1e-3));
+
+		let cube = CollisionShape::Box { size: Vec3::splat(1.0) };
+		let transform = Mat4::from_translation(Vec3::new(2.5, 0.0, 0.0));
+		let hit = state.sweep(scene_id, &cube, transform, Vec3::Z, 100.0, &QueryFilter::new()).unwrap();
+		assert_eq!(hit.node_id, wall);
+		assert!((hit.distance - 3.5).abs() < 1e-3, "{}", hit.distance);
+
+		// Passes beside the wall
+		let transform = Mat4::from_translation(Vec3::new(3.5, 0.0, 0.0));
+		assert!(state.sweep(scene_id, &cube, transform, Vec3::Z, 100.0, &QueryFilter::new()).is_none());
+	}
+
+	#[test]
+	fn time_of_impact_finds_bodies_through_the_broad_phase() {
+		let (mut state, _, wall, ball) = scene_with_boxes();
+		state.nodes.get_mut(&ball).unwrap().physics.velocity = Vec3::new(0.0, 0.0, -600.0);
+		let mut grid = SpatialGrid::new(1.0);
+		for (node_id, node) in &state.nodes {
+			grid.set_node(node_id, node.collision_shape.as_ref().unwrap().bounds(node.translation, node.rotation));
+		}
+		let broad_phase = BroadPhase::Grid(grid);
+
+		// The ball passes the whole wall within the step
+		let (time, hit) = time_of_impact(&state, &broad_phase, ball, 1.0 / 60.0, |_, _| true).unwrap();
+		assert_eq!(hit.node_id, wall);
+		assert!((hit.distance - 3.5).abs() < 1e-3, "{}", hit.distance);
+		assert!((time - 3.5 / 600.0).abs() < 1e-5, "{}", time);
+
+		// Nodes the broad phase doesn't know about are not tested
+		let empty = BroadPhase::Grid(SpatialGrid::new(1.0));
+		assert!(time_of_impact(&state, &empty, ball, 1.0 / 60.0, |_, _| true).is_none());
+	}
+
+	#[test]
+	fn grazing_sweep_reports_the_distance_it_reached() {
+		let sphere = Convex::Sphere { center: Vec3::ZERO, radius: 0.5 };
+		let floor = Convex::Box { center: Vec3::new(0.0, -1.5, 0.0), rotation: Quat::IDENTITY, half: Vec3::new(1000.0, 0.5, 1000.0) };
+		// Touches the floor after 50, but every step only closes a hundredth of the gap
+		let dir = Vec3::new(1.0, -0.01, 0.0).normalize();
+		let (distance, normal) = sweep_convex(&sphere, dir, 100.0, &floor).unwrap();
+		assert!(distance > 0.0 && distance <= 50.0, "{}", distance);
+		assert!(normal.abs_diff_eq(Vec3::Y, 1e-3), "{:?}", normal);
+
+		assert!(sweep_convex(&sphere, dir, 10.0, &floor).is_none());
+	}
+
+	#[test]
+	fn overlap_finds_touching_shapes() {
+		let (state, scene_id, wall, ball) = scene_with_boxes();
+
+		let sphere = CollisionShape::Sphere { radius: 2.0 };
+		let found = state.overlap(scene_id, &sphere, Mat4::from_translation(Vec3::new(0.0, 0.0, 7.5)), &QueryFilter::new());
+		assert_eq!(found.len(), 2);
+		assert!(found.contains(&wall) && found.contains(&ball));
+
+		let found = state.overlap(scene_id, &sphere, Mat4::from_translation(Vec3::new(0.0, 5.0, 7.5)), &QueryFilter::new());
+		assert!(found.is_empty());
+	}
+}
@@ -0,0 +1,456 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::Deserialize;
+use serde::Serialize;
+use crate::state::State;
+use crate::Arena;
+use crate::ArenaId;
+use crate::Camera;
+use crate::GUIElement;
+use crate::Material;
+use crate::Mesh;
+use crate::Node;
+use crate::NodeParent;
+use crate::PointLight;
+use crate::Scene;
+use crate::Texture;
+
+/// A scene or a node subtree with everything it uses, kept apart from any
+/// `State` so it can be written to disk. Ids of a state are never written,
+/// items refer to each other by their position in the lists of the file and
+/// get new ids when the file is inserted into a state.
+///
+/// Nodes are saved with their meshes, materials, texture references, cameras,
+/// lights and the GUI trees showing their cameras. Joints, character
+/// controllers, vehicles, cloths, force fields, ray casts and animations
+/// attached to them are not saved, and GUI elements are loaded without fonts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneFile {
+	/// Settings of the saved scene. `None` when a node subtree was saved.
+	pub scene: Option<Scene>,
+	/// Parents come before their children.
+	nodes: Vec<SavedNode>,
+	meshes: Vec<SavedMesh>,
+	materials: Vec<SavedMaterial>,
+	textures: Vec<Texture>,
+	cameras: Vec<Attached<Camera>>,
+	point_lights: Vec<Attached<PointLight>>,
+	/// GUI trees showing one of the saved cameras.
+	guis: Vec<SavedGui>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedNode {
+	node: Node,
+	/// Roots have no parent.
+	parent: Option<usize>,
+	mesh: Option<usize>,
+	ignored_nodes: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedMesh {
+	mesh: Mesh,
+	/// Material of each primitive.
+	materials: Vec<Option<usize>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedMaterial {
+	material: Material,
+	/// In the order of `material_textures`.
+	textures: [Option<usize>; 5],
+}
+
+/// Camera or light and the saved node it is attached to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Attached<T> {
+	item: T,
+	node: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedGui {
+	gui: GUIElement,
+	/// Camera shown by each element, depth first.
+	cameras: Vec<Option<usize>>,
+}
+
+/// Position of `id` in `items`, copied from `arena` the first time it is seen.
+fn index_of<T: Clone>(arena: &Arena<T>, indices: &mut HashMap<ArenaId<T>, usize>, items: &mut Vec<T>, id: ArenaId<T>) -> Option<usize> {
+	if let Some(index) = indices.get(&id) {
+		return Some(*index);
+	}
+	let item = arena.get(&id)?;
+	indices.insert(id, items.len());
+	items.push(item.clone());
+	Some(items.len() - 1)
+}
+
+/// Id given to the item at `index` of a file's list. Indices past the end of
+/// the list, which only a hand edited file has, are dropped.
+fn lookup<T>(ids: &[ArenaId<T>], index: Option<usize>) -> Option<ArenaId<T>> {
+	ids.get(index?).copied()
+}
+
+fn material_textures(material: &Material) -> [Option<ArenaId<Texture>>; 5] {
+	[
+		material.base_color_texture,
+		material.metallic_roughness_texture,
+		material.normal_texture,
+		material.occlusion_texture,
+		material.emissive_texture,
+	]
+}
+
+fn set_material_textures(material: &mut Material, [base_color, metallic_roughness, normal, occlusion, emissive]: [Option<ArenaId<Texture>>; 5]) {
+	material.base_color_texture = base_color;
+	material.metallic_roughness_texture = metallic_roughness;
+	material.normal_texture = normal;
+	material.occlusion_texture = occlusion;
+	material.emissive_texture = emissive;
+}
+
+fn gui_cameras(gui: &GUIElement, cameras: &mut Vec<Option<ArenaId<Camera>>>) {
+	cameras.push(gui.camera_id);
+	for child in &gui.children {
+		gui_cameras(child, cameras);
+	}
+}
+
+fn set_gui_cameras(gui: &mut GUIElement, cameras: &mut impl Iterator<Item = Option<ArenaId<Camera>>>) {
+	gui.camera_id = cameras.next().flatten();
+	for child in &mut gui.children {
+		set_gui_cameras(child, cameras);
+	}
+}
+
+impl SceneFile {
+	/// The scene with all of its nodes.
+	pub fn from_scene(state: &State, scene_id: ArenaId<Scene>) -> Self {
+		let hierarchy = state.current_hierarchy();
+		let order = hierarchy
+			.children(NodeParent::Scene(scene_id))
+			.iter()
+			.flat_map(|root| hierarchy.subtree(*root))
+			.collect();
+		Self::from_nodes(state, state.scenes.get(&scene_id).cloned(), order)
+	}
+
+	/// The node and its descendants. The node is saved without its parent.
+	pub fn from_node(state: &State, node_id: ArenaId<Node>) -> Self {
+		let order = match state.nodes.contains(&node_id) {
+			true => state.current_hierarchy().subtree(node_id),
+			false => vec![],
+		};
+		Self::from_nodes(state, None, order)
+	}
+
+	/// Saves the nodes in `order`, which has parents before their children.
+	fn from_nodes(state: &State, scene: Option<Scene>, order: Vec<ArenaId<Node>>) -> Self {
+		let node_indices: HashMap<ArenaId<Node>, usize> = order.iter().enumerate().map(|(index, node_id)| (*node_id, index)).collect();
+		let node_index = |node_id: ArenaId<Node>| node_indices.get(&node_id).copied();
+
+		let mut file = Self {
+			scene,
+			..Default::default()
+		};
+		let (mut meshes, mut mesh_indices) = (Vec::new(), HashMap::new());
+		for node_id in &order {
+			let node = state.nodes.get(node_id).unwrap();
+			file.nodes.push(SavedNode {
+				parent: match node.parent {
+					NodeParent::Node(parent) => node_index(parent),
+					_ => None,
+				},
+				mesh: node.mesh.and_then(|mesh_id| index_of(&state.meshes, &mut mesh_indices, &mut meshes, mesh_id)),
+				ignored_nodes: node.physics.ignored_nodes.iter().filter_map(|node_id| node_index(*node_id)).collect(),
+				node: node.clone(),
+			});
+		}
+		let (mut materials, mut material_indices) = (Vec::new(), HashMap::new());
+		for mesh in meshes {
+			let materials = mesh.primitives
+				.iter()
+				.map(|primitive| primitive.material.and_then(|material_id| index_of(&state.materials, &mut material_indices, &mut materials, material_id)))
+				.collect();
+			file.meshes.push(SavedMesh { mesh, materials });
+		}
+		let (mut textures, mut texture_indices) = (Vec::new(), HashMap::new());
+		for material in materials {
+			let textures = material_textures(&material)
+				.map(|texture_id| texture_id.and_then(|texture_id| index_of(&state.textures, &mut texture_indices, &mut textures, texture_id)));
+			file.materials.push(SavedMaterial { material, textures });
+		}
+		file.textures = textures;
+
+		let mut camera_indices = HashMap::new();
+		for (camera_id, camera) in &state.cameras {
+			if let Some(node) = camera.node_id.and_then(node_index) {
+				camera_indices.insert(camera_id, file.cameras.len());
+				file.cameras.push(Attached { item: camera.clone(), node });
+			}
+		}
+		for (_, light) in &state.point_lights {
+			if let Some(node) = light.node_id.and_then(node_index) {
+				file.point_lights.push(Attached { item: light.clone(), node });
+			}
+		}
+		for (_, gui) in &state.guis {
+			let mut cameras = Vec::new();
+			gui_cameras(gui, &mut cameras);
+			let cameras: Vec<_> = cameras
+				.into_iter()
+				.map(|camera_id| camera_id.and_then(|camera_id| camera_indices.get(&camera_id).copied()))
+				.collect();
+			if cameras.iter().any(Option::is_some) {
+				file.guis.push(SavedGui { gui: gui.clone(), cameras });
+			}
+		}
+		file
+	}
+
+	/// Adds a copy of the contents to the state with new ids, the root nodes
+	/// under `parent`. Returns the root nodes.
+	pub fn insert(&self, state: &mut State, parent: NodeParent) -> Vec<ArenaId<Node>> {
+		let textures: Vec<_> = self.textures.iter().map(|texture| state.textures.insert(texture.clone())).collect();
+		let mut materials = Vec::new();
+		for saved in &self.materials {
+			let mut material = saved.material.clone();
+			set_material_textures(&mut material, saved.textures.map(|index| lookup(&textures, index)));
+			materials.push(state.materials.insert(material));
+		}
+		let mut meshes = Vec::new();
+		for saved in &self.meshes {
+			let mut mesh = saved.mesh.clone();
+			for (primitive, index) in mesh.primitives.iter_mut().zip(&saved.materials) {
+				primitive.material = lookup(&materials, *index);
+			}
+			meshes.push(state.meshes.insert(mesh));
+		}
+
+		// Nodes point at each other, so all of them need ids before linking
+		let node_ids: Vec<_> = self.nodes.iter().map(|saved| state.insert_node(saved.node.clone())).collect();
+		let mut roots = Vec::new();
+		for (saved, node_id) in self.nodes.iter().zip(&node_ids) {
+			let node = state.nodes.get_mut(node_id).unwrap();
+			node.parent = match lookup(&node_ids, saved.parent) {
+				Some(parent) => NodeParent::Node(parent),
+				None => {
+					roots.push(*node_id);
+					parent
+				}
+			};
+			node.mesh = lookup(&meshes, saved.mesh);
+			node.physics.ignored_nodes = saved.ignored_nodes.iter().filter_map(|index| lookup(&node_ids, Some(*index))).collect();
+			state.hierarchy.set_parent(*node_id, node.parent);
+		}
+
+		let mut cameras = Vec::new();
+		for saved in &self.cameras {
+			let mut camera = saved.item.clone();
+			camera.node_id = lookup(&node_ids, Some(saved.node));
+			cameras.push(state.cameras.insert(camera));
+		}
+		for saved in &self.point_lights {
+			let mut light = saved.item.clone();
+			light.node_id = lookup(&node_ids, Some(saved.node));
+			state.point_lights.insert(light);
+		}
+		for saved in &self.guis {
+			let mut gui = saved.gui.clone();
+			set_gui_cameras(&mut gui, &mut saved.cameras.iter().map(|index| lookup(&cameras, *index)));
+			state.guis.insert(gui);
+		}
+		roots
+	}
+
+	/// Adds the contents to the state as a new scene.
+	pub fn insert_scene(&self, state: &mut State) -> ArenaId<Scene> {
+		let scene_id = state.scenes.insert(self.scene.clone().unwrap_or_default());
+		self.insert(state, NodeParent::Scene(scene_id));
+		scene_id
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+		std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+		Ok(())
+	}
+
+	pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+		Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use glam::Vec3;
+	use crate::CollisionShape;
+	use crate::PhycisObjectType;
+	use crate::PhysicsProps;
+	use crate::Primitive;
+	use crate::PrimitiveTopology;
+	use crate::TextureSource;
+	use super::*;
+
+	fn level(state: &mut State) -> (ArenaId<Scene>, ArenaId<Node>) {
+		let scene_id = state.scenes.insert(Scene {
+			gravity: Vec3::new(0.0, -3.0, 0.0),
+			..Scene::new()
+		});
+		let texture = state.textures.insert(Texture::new("assets/crate.png"));
+		let material = state.materials.insert(Material {
+			base_color_texture: Some(texture),
+			base_color_factor: [0.5, 0.25, 1.0, 1.0],
+			..Default::default()
+		});
+		let mut primitive = Primitive::new(PrimitiveTopology::TriangleList);
+		primitive.vertices = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+		primitive.indices = vec![0, 1, 2];
+		primitive.material = Some(material);
+		let mut mesh = Mesh::new().set_name("crate");
+		mesh.primitives.push(primitive);
+		let mesh = state.meshes.insert(mesh);
+
+		let floor = state.nodes.insert(Node {
+			name: Some("floor".to_string()),
+			physics: PhysicsProps {
+				typ: PhycisObjectType::Static,
+				..Default::default()
+			},
+			collision_shape: Some(CollisionShape::Box { size: Vec3::new(10.0, 1.0, 10.0) }),
+			parent: NodeParent::Scene(scene_id),
+			..Default::default()
+		});
+		let crate_node = state.nodes.insert(Node {
+			name: Some("crate".to_string()),
+			mesh: Some(mesh),
+			translation: Vec3::new(1.0, 2.0, 3.0),
+			physics: PhysicsProps {
+				typ: PhycisObjectType::Dynamic,
+				mass: 4.0,
+				ignored_nodes: vec![floor],
+				..Default::default()
+			},
+			collision_shape: Some(CollisionShape::Sphere { radius: 0.5 }),
+			parent: NodeParent::Node(floor),
+			..Default::default()
+		});
+		let camera_node = state.nodes.insert(Node {
+			parent: NodeParent::Node(crate_node),
+			..Default::default()
+		});
+		let camera = state.cameras.insert(Camera {
+			node_id: Some(camera_node),
+			..Camera::new()
+		});
+		state.point_lights.insert(PointLight {
+			node_id: Some(crate_node),
+			intensity: 2.0,
+			..PointLight::new()
+		});
+		state.guis.insert(GUIElement::new().add(GUIElement::new().camera(camera)));
+		(scene_id, crate_node)
+	}
+
+	fn node_named<'a>(state: &'a State, name: &str) -> (ArenaId<Node>, &'a Node) {
+		state.nodes.iter().find(|(_, node)| node.name.as_deref() == Some(name)).unwrap()
+	}
+
+	#[test]
+	fn saved_scene_loads_with_remapped_ids() {
+		let mut state = State::default();
+		let (scene_id, _) = level(&mut state);
+		// Unrelated items the saved scene must leave out
+		state.nodes.insert(Node::default());
+		state.point_lights.insert(PointLight::new());
+
+		let path = std::env::temp_dir().join(format!("pge_scene_file_{}.json", std::process::id()));
+		state.save_scene(scene_id, &path).unwrap();
+
+		let mut loaded = State::default();
+		loaded.scenes.insert(Scene::new());
+		loaded.textures.insert(Texture::default());
+		loaded.nodes.insert(Node::default());
+		let scene_id = loaded.load_scene(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(loaded.scenes.get(&scene_id).unwrap().gravity, Vec3::new(0.0, -3.0, 0.0));
+		assert_eq!(loaded.nodes.iter().count(), 4);
+		let (floor_id, floor) = node_named(&loaded, "floor");
+		assert_eq!(floor.parent, NodeParent::Scene(scene_id));
+		let (crate_id, crate_node) = node_named(&loaded, "crate");
+		assert_eq!(crate_node.parent, NodeParent::Node(floor_id));
+		assert_eq!(crate_node.translation, Vec3::new(1.0, 2.0, 3.0));
+		assert_eq!(crate_node.physics.mass, 4.0);
+		assert_eq!(crate_node.physics.ignored_nodes, vec![floor_id]);
+		assert!(matches!(crate_node.collision_shape, Some(CollisionShape::Sphere { radius }) if radius == 0.5));
+
+		let mesh = loaded.meshes.get(&crate_node.mesh.unwrap()).unwrap();
+		assert_eq!(mesh.name.as_deref(), Some("crate"));
+		let material = loaded.materials.get(&mesh.primitives[0].material.unwrap()).unwrap();
+		assert_eq!(material.base_color_factor, [0.5, 0.25, 1.0, 1.0]);
+		let texture = loaded.textures.get(&material.base_color_texture.unwrap()).unwrap();
+		assert!(matches!(&texture.source, TextureSource::File(path) if path == "assets/crate.png"));
+
+		let lights: Vec<_> = loaded.point_lights.iter().map(|(_, light)| light).collect();
+		assert_eq!(lights.len(), 1);
+		assert_eq!(lights[0].node_id, Some(crate_id));
+		let (camera_id, camera) = loaded.cameras.iter().next().unwrap();
+		assert_eq!(loaded.nodes.get(&camera.node_id.unwrap()).unwrap().parent, NodeParent::Node(crate_id));
+		let (_, gui) = loaded.guis.iter().next().unwrap();
+		assert_eq!(gui.children[0].camera_id, Some(camera_id));
+	}
+
+	#[test]
+	fn saved_node_loads_under_new_parent() {
+		let mut state = State::default();
+		let (_, crate_id) = level(&mut state);
+		let file = SceneFile::from_node(&state, crate_id);
+		assert!(file.scene.is_none());
+		assert_eq!(file.nodes.len(), 2);
+		// The floor it ignores is not part of the subtree
+		assert!(file.nodes[0].ignored_nodes.is_empty());
+		assert!(file.guis.len() == 1 && file.cameras.len() == 1);
+
+		let json = serde_json::to_string(&file).unwrap();
+		let file: SceneFile = serde_json::from_str(&json).unwrap();
+		let scene_id = state.scenes.insert(Scene::new());
+		let roots = file.insert(&mut state, NodeParent::Scene(scene_id));
+		assert_eq!(roots.len(), 1);
+		let copy = state.nodes.get(&roots[0]).unwrap();
+		assert_eq!(copy.parent, NodeParent::Scene(scene_id));
+		assert_ne!(roots[0], crate_id);
+		assert_eq!(state.meshes.iter().count(), 2);
+		assert_ne!(copy.mesh, state.nodes.get(&crate_id).unwrap().mesh);
+		assert!(state.meshes.contains(&copy.mesh.unwrap()));
+	}
+
+	#[test]
+	fn file_refers_to_items_by_position_only() {
+		let mut state = State::default();
+		let (_, crate_id) = level(&mut state);
+		let camera_id = state.cameras.iter().next().unwrap().0;
+		state.guis.insert(GUIElement::new().camera(camera_id).font(crate::FontHandle::new(3)));
+		let file = SceneFile::from_node(&state, crate_id);
+
+		let mut json = serde_json::to_value(&file).unwrap();
+		let saved = &json["nodes"][1];
+		assert!(saved["node"].get("parent").is_none() && saved["node"].get("mesh").is_none());
+		assert_eq!(saved["parent"], 0);
+		assert!(json["guis"][1]["gui"].get("font").is_none());
+		// Positions past the end of a list point at nothing
+		json["nodes"][1]["parent"] = 7.into();
+		json["nodes"][0]["mesh"] = 7.into();
+		let file: SceneFile = serde_json::from_value(json).unwrap();
+
+		let scene_id = state.scenes.insert(Scene::new());
+		let roots = file.insert(&mut state, NodeParent::Scene(scene_id));
+		assert_eq!(roots.len(), 2);
+		assert!(roots.iter().all(|root| state.nodes.get(root).unwrap().parent == NodeParent::Scene(scene_id)));
+		assert!(state.nodes.get(&roots[0]).unwrap().mesh.is_none());
+		let (_, gui) = state.guis.iter().last().unwrap();
+		assert!(gui.font.is_none() && gui.camera_id.is_some() && gui.camera_id != Some(camera_id));
+	}
+}
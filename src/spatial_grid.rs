@@ -23,6 +23,8 @@ pub struct SpatialGrid {
 	cell_size: f32,
 	pub cells: HashMap<CellCoord, Vec<ArenaId<Node>>>,
 	nodes: HashMap<ArenaId<Node>, NodeMetadata>,
+	/// Box around every rect ever added. Ray traversal stops at its border.
+	bounds: Option<AABB>,
 }
 
 impl SpatialGrid {
@@ -31,9 +33,14 @@ impl SpatialGrid {
 			cell_size,
 			cells: HashMap::new(),
 			nodes: HashMap::new(),
+			bounds: None,
 		}
 	}
 
+	pub fn cell_size(&self) -> f32 {
+		self.cell_size
+	}
+
 	pub fn get_node_rect(&self, node: ArenaId<Node>) -> Option<&AABB> {
 		match self.nodes.get(&node) {
 			Some(n) => Some(&n.rect),
@@ -84,6 +91,10 @@ impl SpatialGrid {
 			}
 		}
 
+		self.bounds = Some(match &self.bounds {
+			Some(bounds) => bounds.merge(&rect),
+			None => rect.clone(),
+		});
 		self.nodes.insert(node, NodeMetadata {
 			rect,
 			cells: node_cells,
@@ -127,36 +138,100 @@ impl SpatialGrid {
 		}
 	}
 
-	pub fn get_line_ray_nodes(&self, start: glam::Vec3, end: glam::Vec3) -> HashSet<ArenaId<Node>> {
-		let mut nodes = HashSet::new();
-		let mut current = start;
-		let direction = (end - start).normalize();
-		let distance = (end - start).length();
-		let mut t = 0.0;
-		let mut last_cell = CellCoord { x: 0, y: 0, z: 0 };
-
-		while t < distance {
-			let next = current + direction * t;
-			let cell = CellCoord {
-				x: (next.x / self.cell_size).floor() as i32,
-				y: (next.y / self.cell_size).floor() as i32,
-				z: (next.z / self.cell_size).floor() as i32,
-			};
+	/// Nodes in the cells `rect` covers.
+	pub fn get_aabb_nodes(&self, rect: &AABB) -> Vec<ArenaId<Node>> {
+		let min = (rect.min / self.cell_size).floor();
+		let max = (rect.max / self.cell_size).ceil().max(min + 1.0);
+		let in_range = |coord: &CellCoord| {
+			(min.x as i32..max.x as i32).contains(&coord.x)
+				&& (min.y as i32..max.y as i32).contains(&coord.y)
+				&& (min.z as i32..max.z as i32).contains(&coord.z)
+		};
 
-			if cell != last_cell {
-				last_cell = cell;
-				if let Some(cell_nodes) = self.cells.get(&cell) {
-					for node in cell_nodes {
-						nodes.insert(*node);
-					}
+		let mut seen = HashSet::new();
+		let mut nodes = Vec::new();
+		let covered = (max - min).x * (max - min).y * (max - min).z;
+		if covered > self.cells.len() as f32 {
+			// Large boxes visit the occupied cells instead of every covered one
+			for (coord, cell) in &self.cells {
+				if in_range(coord) {
+					nodes.extend(cell.iter().filter(|node_id| seen.insert(**node_id)));
+				}
+			}
+			return nodes;
+		}
+		for x in min.x as i32..max.x as i32 {
+			for y in min.y as i32..max.y as i32 {
+				for z in min.z as i32..max.z as i32 {
+					nodes.extend(self.get_cell(x, y, z).iter().filter(|node_id| seen.insert(**node_id)));
 				}
 			}
-
-			t += self.cell_size;
 		}
+		nodes
+	}
 
+	/// Nodes in the cells the ray passes through within `max_dist`, in the
+	/// order the ray reaches them.
+	pub fn get_ray_nodes(&self, origin: glam::Vec3, dir: glam::Vec3, max_dist: f32) -> Vec<ArenaId<Node>> {
+		let mut seen = HashSet::new();
+		let mut nodes = Vec::new();
+		self.traverse_ray(origin, dir, max_dist, |cell_nodes, _| {
+			nodes.extend(cell_nodes.iter().filter(|node_id| seen.insert(**node_id)));
+			true
+		});
 		nodes
 	}
+
+	/// Visits every cell the ray passes through within `max_dist`, nearest
+	/// first, using Amanatides-Woo voxel traversal. `visit` gets the nodes of
+	/// the cell and the distance at which the ray leaves it, and returns false
+	/// to stop. `dir` has to be normalized.
+	pub fn traverse_ray(&self, origin: glam::Vec3, dir: glam::Vec3, max_dist: f32, mut visit: impl FnMut(&[ArenaId<Node>], f32) -> bool) {
+		if dir == glam::Vec3::ZERO {
+			return;
+		}
+		// Past the bounds there are only empty cells, which also ends infinite rays
+		let (t_start, t_end) = match self.bounds.as_ref().and_then(|bounds| bounds.ray_range(origin, dir, max_dist)) {
+			Some(range) => range,
+			None => return,
+		};
+
+		let start = (origin + dir * t_start) / self.cell_size;
+		let mut cell = start.floor().as_ivec3();
+		let step = glam::IVec3::new(dir.x.signum() as i32, dir.y.signum() as i32, dir.z.signum() as i32);
+		let mut t_max = glam::Vec3::splat(f32::INFINITY);
+		let mut t_delta = glam::Vec3::splat(f32::INFINITY);
+		for axis in 0..3 {
+			if dir[axis] == 0.0 {
+				continue;
+			}
+			let boundary = if dir[axis] > 0.0 { cell[axis] + 1 } else { cell[axis] } as f32 * self.cell_size;
+			t_max[axis] = (boundary - origin[axis]) / dir[axis];
+			t_delta[axis] = self.cell_size / dir[axis].abs();
+		}
+
+		loop {
+			let t_exit = t_max.min_element().min(t_end);
+			let coord = CellCoord { x: cell.x, y: cell.y, z: cell.z };
+			if let Some(nodes) = self.cells.get(&coord) {
+				if !nodes.is_empty() && !visit(nodes, t_exit) {
+					return;
+				}
+			}
+			if t_exit >= t_end {
+				return;
+			}
+			let axis = if t_max.x <= t_max.y && t_max.x <= t_max.z {
+				0
+			} else if t_max.y <= t_max.z {
+				1
+			} else {
+				2
+			};
+			cell[axis] += step[axis];
+			t_max[axis] += t_delta[axis];
+		}
+	}
 }
 
 #[cfg(test)]
@@ -201,4 +276,35 @@ mod tests {
 		let cell = grid.get_cell(-1, -1, -1);
 		assert_eq!(cell.contains(&id), true);
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn ray_visits_every_cell_it_crosses() {
+		let mut arena = Arena::new();
+		let mut grid = SpatialGrid::new(1.0);
+		let mut add = |x: f32, y: f32| {
+			let id = arena.insert(Node::new());
+			let min = glam::Vec3::new(x + 0.05, y + 0.05, 0.05);
+			grid.add_node(id, AABB::new(min, min + glam::Vec3::splat(0.9)));
+			id
+		};
+		let first = add(1.0, 0.0);
+		// Only the tip of this cell is clipped by the line
+		let corner = add(1.0, 1.0);
+		let last = add(2.0, 1.0);
+		let beyond = add(3.0, 1.0);
+		let aside = add(0.0, 1.0);
+
+		let (start, end) = (glam::Vec3::new(0.5, 0.2, 0.5), glam::Vec3::new(2.5, 1.4, 0.5));
+		let nodes = grid.get_ray_nodes(start, (end - start).normalize(), start.distance(end));
+		assert_eq!(nodes, vec![first, corner, last]);
+		assert!(!nodes.contains(&beyond) && !nodes.contains(&aside));
+
+		let mut visited = Vec::new();
+		grid.traverse_ray(glam::Vec3::new(0.5, 0.2, 0.5), glam::Vec3::X, f32::INFINITY, |nodes, t_exit| {
+			visited.extend_from_slice(nodes);
+			assert!(t_exit.is_finite());
+			true
+		});
+		assert_eq!(visited, vec![first]);
+	}
+}
@@ -1,8 +1,16 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::path::Path;
 use crate::load_gltf;
 use crate::arena::*;
 use crate::types::*;
+use crate::broad_phase::BroadPhase;
+use crate::hierarchy::Hierarchy;
+use crate::physics;
+use crate::prefab::Prefab;
+use crate::prefab::PrefabOverrides;
+use crate::query;
+use crate::scene_file::SceneFile;
 use crate::utility::get_scene_bounding_box;
 use crate::GUIElement;
 use crate::Window;
@@ -18,10 +26,28 @@ pub struct State {
     pub point_lights: Arena<PointLight>,
     pub textures: Arena<Texture>,
     pub raycasts: Arena<RayCast>,
+    pub joints: Arena<Joint>,
+    pub characters: Arena<CharacterController>,
+    pub vehicles: Arena<Vehicle>,
+    pub cloths: Arena<Cloth>,
+    pub force_fields: Arena<ForceField>,
     pub models: Arena<Model3D>,
     pub animations: Arena<Animation>,
     pub materials: Arena<Material>,
     pub keyboard: Option<Keyboard>,
+    pub physics_settings: PhysicsSettings,
+    /// Number of physics steps simulated so far. During `on_phycis_update`
+    /// it is the index of the step about to run.
+    pub physics_step: u64,
+    /// Statistics of the last physics step of every simulated scene. When a
+    /// frame runs several fixed steps only the last one is kept here; read it
+    /// in `on_phycis_update` to see the step before each one.
+    pub physics_stats: HashMap<ArenaId<Scene>, PhysicsStats>,
+    /// Collision broad phase of each scene, kept up to date by the engine.
+    pub(crate) broad_phases: HashMap<ArenaId<Scene>, BroadPhase>,
+    /// Children of every node and scene, kept up to date by the `State`
+    /// methods that add and move nodes, and by the engine.
+    pub(crate) hierarchy: Hierarchy,
 }
 
 impl State {
@@ -30,27 +56,149 @@ impl State {
         self.models.insert(model)
     }
 
-    /// Deep clones node and it's children
+    /// Deep clones node and it's children, with the lights, cameras, ray
+    /// casts and animations attached to them
     pub fn clone_node(&mut self, node_id: ArenaId<Node>) -> ArenaId<Node> {
-        let node = self.nodes.get(&node_id).expect("Node not found");
-        let mut new_node = node.clone();
-        new_node.parent = NodeParent::Orphan;
-        let new_node_id = self.nodes.insert(new_node);
-        let mut stack = vec![(node_id, new_node_id.clone())];
-        while let Some((orig_id, new_parent_id)) = stack.pop() {
-            let children: Vec<_> = self.nodes.iter()
-                .filter_map(|(id, n)| if n.parent == NodeParent::Node(orig_id) { Some(id) } else { None })
-                .collect();
-            for child_id in children {
-                let child = self.nodes.get(&child_id).expect("Child node not found");
-                let mut new_child = child.clone();
-                new_child.parent = NodeParent::Node(new_parent_id);
-                let new_child_id = self.nodes.insert(new_child);
-                stack.push((child_id, new_child_id));
+        let prefab = Prefab::from_node(self, node_id).expect("Node not found");
+        prefab.instantiate(self, NodeParent::Orphan, &PrefabOverrides::new()).root
+    }
+
+    /// Adds the node and indexes it under its parent right away, so
+    /// `children` and the other hierarchy queries see it before the next
+    /// frame.
+    pub fn insert_node(&mut self, node: Node) -> ArenaId<Node> {
+        self.hierarchy.insert(&mut self.nodes, node)
+    }
+
+    /// Direct children of the node.
+    ///
+    /// Stale until the next frame or `update_hierarchy` for nodes inserted
+    /// with `nodes.insert`, removed from `nodes`, or given another
+    /// `Node::parent` directly. Use `insert_node` and `reparent` to keep it
+    /// current right away. Prefabs, scene files and glTF models are indexed
+    /// when added.
+    pub fn children(&self, node_id: ArenaId<Node>) -> &[ArenaId<Node>] {
+        self.hierarchy.children(NodeParent::Node(node_id))
+    }
+
+    /// Nodes placed directly in the scene. Stale in the same cases as
+    /// `children`.
+    pub fn scene_children(&self, scene_id: ArenaId<Scene>) -> &[ArenaId<Node>] {
+        self.hierarchy.children(NodeParent::Scene(scene_id))
+    }
+
+    /// Children, their children and so on, parents before their children.
+    /// Stale in the same cases as `children`.
+    pub fn descendants(&self, node_id: ArenaId<Node>) -> Vec<ArenaId<Node>> {
+        let mut descendants = self.hierarchy.subtree(node_id);
+        descendants.remove(0);
+        descendants
+    }
+
+    /// Parent, its parent and so on up to the node at the top.
+    pub fn ancestors(&self, node_id: ArenaId<Node>) -> Vec<ArenaId<Node>> {
+        let mut ancestors = Vec::new();
+        let mut parent = self.nodes.get(&node_id).map(|node| node.parent);
+        while let Some(NodeParent::Node(parent_id)) = parent {
+            ancestors.push(parent_id);
+            parent = self.nodes.get(&parent_id).map(|node| node.parent);
+        }
+        ancestors
+    }
+
+    /// Moves the node with its descendants under `parent`. With
+    /// `keep_world_transform` the node stays where it is in the world,
+    /// otherwise it keeps its transform relative to the parent.
+    pub fn reparent(&mut self, node_id: ArenaId<Node>, parent: NodeParent, keep_world_transform: bool) {
+        if let NodeParent::Node(parent_id) = parent {
+            assert!(parent_id != node_id && !self.ancestors(parent_id).contains(&node_id), "Node can't be moved under itself");
+        }
+        let local = match keep_world_transform {
+            true => {
+                let (before, after) = self.parent_frame(parent);
+                before.inverse() * self.world_matrix(node_id) * after.inverse()
+            }
+            false => glam::Mat4::IDENTITY,
+        };
+        let node = self.nodes.get_mut(&node_id).expect("Node not found");
+        if keep_world_transform {
+            let (scale, rotation, translation) = local.to_scale_rotation_translation();
+            node.scale = scale;
+            node.rotation = rotation;
+            node.translation = translation;
+        }
+        node.parent = parent;
+        self.hierarchy.set_parent(node_id, parent);
+    }
+
+    /// Picks up nodes added, removed or given another parent directly on the
+    /// arena. Walks every node. The engine does it every frame.
+    pub fn update_hierarchy(&mut self) {
+        self.hierarchy.sync(&self.nodes);
+    }
+
+    /// The hierarchy index, synced on a copy when nodes were added, removed
+    /// or given another parent directly since it was last synced.
+    pub(crate) fn current_hierarchy(&self) -> Cow<'_, Hierarchy> {
+        match self.hierarchy.is_current(&self.nodes) {
+            true => Cow::Borrowed(&self.hierarchy),
+            false => {
+                let mut hierarchy = self.hierarchy.clone();
+                hierarchy.sync(&self.nodes);
+                Cow::Owned(hierarchy)
             }
         }
-    
-        new_node_id
+    }
+
+    /// Transform of the node in the world, from its current local transforms
+    /// rather than the ones of the last frame.
+    fn world_matrix(&self, node_id: ArenaId<Node>) -> glam::Mat4 {
+        match self.nodes.get(&node_id) {
+            Some(node) => {
+                let (before, after) = self.parent_frame(node.parent);
+                before * node.matrix() * after
+            }
+            None => glam::Mat4::IDENTITY,
+        }
+    }
+
+    /// Matrices a child of `parent` is placed between. Nodes of a scene are
+    /// scaled by the scene in their own frame, the same as the engine does.
+    fn parent_frame(&self, parent: NodeParent) -> (glam::Mat4, glam::Mat4) {
+        match parent {
+            NodeParent::Node(parent_id) => (self.world_matrix(parent_id), glam::Mat4::IDENTITY),
+            NodeParent::Scene(scene_id) => {
+                let scale = self.scenes.get(&scene_id).map_or(glam::Vec3::ONE, |scene| scene.scale);
+                (glam::Mat4::IDENTITY, glam::Mat4::from_scale(scale))
+            }
+            NodeParent::Orphan => (glam::Mat4::IDENTITY, glam::Mat4::IDENTITY),
+        }
+    }
+
+    /// Writes the scene with its nodes, meshes, materials, texture references,
+    /// cameras, lights and GUI to a JSON file. See `SceneFile` for what is
+    /// left out.
+    pub fn save_scene<P: AsRef<Path>>(&self, scene_id: ArenaId<Scene>, path: P) -> anyhow::Result<()> {
+        SceneFile::from_scene(self, scene_id).save(path)
+    }
+
+    /// Loads a file written by `save_scene` as a new scene.
+    pub fn load_scene<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<ArenaId<Scene>> {
+        Ok(SceneFile::load(path)?.insert_scene(self))
+    }
+
+    /// Writes the node and its descendants with what they use to a JSON file.
+    pub fn save_node<P: AsRef<Path>>(&self, node_id: ArenaId<Node>, path: P) -> anyhow::Result<()> {
+        SceneFile::from_node(self, node_id).save(path)
+    }
+
+    /// Loads a file written by `save_node` under `parent`. Returns the loaded node.
+    pub fn load_node<P: AsRef<Path>>(&mut self, path: P, parent: NodeParent) -> anyhow::Result<ArenaId<Node>> {
+        let roots = SceneFile::load(path)?.insert(self, parent);
+        match roots.as_slice() {
+            [node_id] => Ok(*node_id),
+            _ => anyhow::bail!("Expected one root node, found {}", roots.len()),
+        }
     }
 
     pub fn mem_size(&self) -> usize {
@@ -72,6 +220,32 @@ impl State {
 	pub fn get_scene_bounding_box(&self, scene_id: ArenaId<Scene>) -> AABB {
 		get_scene_bounding_box(scene_id, self)
 	}
+
+	/// Hash of the physics state of all nodes. Two deterministic simulations
+	/// fed the same inputs have the same hash after every step.
+	pub fn physics_hash(&self) -> u64 {
+		physics::physics_hash(self)
+	}
+
+	/// Nearest node in the scene hit by the ray.
+	pub fn raycast(&self, scene_id: ArenaId<Scene>, origin: glam::Vec3, dir: glam::Vec3, max_dist: f32, filter: &QueryFilter) -> Option<RayHit> {
+		query::raycast(self, scene_id, origin, dir, max_dist, filter)
+	}
+
+	/// All nodes in the scene hit by the ray, nearest first.
+	pub fn raycast_all(&self, scene_id: ArenaId<Scene>, origin: glam::Vec3, dir: glam::Vec3, max_dist: f32, filter: &QueryFilter) -> Vec<RayHit> {
+		query::raycast_all(self, scene_id, origin, dir, max_dist, filter)
+	}
+
+	/// First node hit when moving `shape` from `transform` along `dir`.
+	pub fn sweep(&self, scene_id: ArenaId<Scene>, shape: &CollisionShape, transform: glam::Mat4, dir: glam::Vec3, max_dist: f32, filter: &QueryFilter) -> Option<RayHit> {
+		query::sweep(self, scene_id, shape, transform, dir, max_dist, filter)
+	}
+
+	/// Nodes overlapping `shape` placed at `transform`.
+	pub fn overlap(&self, scene_id: ArenaId<Scene>, shape: &CollisionShape, transform: glam::Mat4, filter: &QueryFilter) -> Vec<ArenaId<Node>> {
+		query::overlap(self, scene_id, shape, transform, filter)
+	}
 }
 
 #[cfg(test)]
@@ -98,6 +272,86 @@ mod tests {
         assert!(state.nodes.contains(&cloned_id));
     }
 
+    #[test]
+    fn test_hierarchy_queries() {
+        let mut state = State::default();
+        let scene_id = state.scenes.insert(Scene::new());
+        let root = state.nodes.insert(Node { parent: NodeParent::Scene(scene_id), ..Default::default() });
+        let arm = state.nodes.insert(Node { parent: NodeParent::Node(root), ..Default::default() });
+        let hand = state.nodes.insert(Node { parent: NodeParent::Node(arm), ..Default::default() });
+        let leg = state.nodes.insert(Node { parent: NodeParent::Node(root), ..Default::default() });
+        assert!(state.children(root).is_empty(), "Direct inserts are indexed on update");
+
+        state.update_hierarchy();
+        assert_eq!(state.scene_children(scene_id), &[root]);
+        assert_eq!(state.children(root), &[arm, leg]);
+        assert_eq!(state.descendants(root), vec![arm, hand, leg]);
+        assert_eq!(state.ancestors(hand), vec![arm, root]);
+
+        state.nodes.get_mut(&hand).unwrap().parent = NodeParent::Node(leg);
+        state.nodes.remove(&arm);
+        state.update_hierarchy();
+        assert_eq!(state.children(root), &[leg]);
+        assert_eq!(state.children(leg), &[hand]);
+        assert!(state.children(arm).is_empty());
+
+        state.reparent(hand, NodeParent::Orphan, false);
+        assert!(state.children(leg).is_empty());
+        assert!(state.ancestors(hand).is_empty());
+
+        let foot = state.insert_node(Node { parent: NodeParent::Node(leg), ..Default::default() });
+        assert_eq!(state.children(leg), &[foot], "Nodes added through the state are indexed right away");
+        assert!(state.hierarchy.is_current(&state.nodes));
+    }
+
+    #[test]
+    fn test_clone_node_before_hierarchy_update() {
+        let mut state = State::default();
+        let root = state.nodes.insert(Node::new());
+        let child = state.nodes.insert(Node { parent: NodeParent::Node(root), ..Default::default() });
+        state.nodes.insert(Node { parent: NodeParent::Node(child), ..Default::default() });
+
+        // The directly inserted nodes are found without waiting for a frame
+        let copy = state.clone_node(root);
+        assert_eq!(state.descendants(copy).len(), 2);
+        assert_eq!(state.nodes.iter().count(), 6);
+
+        // So are parents assigned directly on nodes the index already has
+        let foot = state.insert_node(Node::new());
+        state.nodes.get_mut(&foot).unwrap().parent = NodeParent::Node(child);
+        let copy = state.clone_node(root);
+        assert_eq!(state.descendants(copy).len(), 3);
+    }
+
+    #[test]
+    fn test_reparent_keeps_world_transform() {
+        let mut state = State::default();
+        let scene_id = state.scenes.insert(Scene { scale: Vec3::splat(2.0), ..Scene::new() });
+        let turned = state.nodes.insert(Node {
+            parent: NodeParent::Scene(scene_id),
+            translation: Vec3::new(1.0, 0.0, 0.0),
+            rotation: glam::Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            ..Default::default()
+        });
+        let node = state.nodes.insert(Node {
+            parent: NodeParent::Scene(scene_id),
+            translation: Vec3::new(0.0, 2.0, 3.0),
+            ..Default::default()
+        });
+        state.update_hierarchy();
+        let world = state.world_matrix(node);
+
+        state.reparent(node, NodeParent::Node(turned), true);
+        assert_eq!(state.children(turned), &[node]);
+        assert!(state.world_matrix(node).abs_diff_eq(world, 1e-5));
+        state.reparent(node, NodeParent::Orphan, true);
+        assert!(state.world_matrix(node).abs_diff_eq(world, 1e-5));
+
+        state.reparent(node, NodeParent::Node(turned), false);
+        let local = state.nodes.get(&node).unwrap().matrix();
+        assert!(state.world_matrix(node).abs_diff_eq(state.world_matrix(turned) * local, 1e-5));
+    }
+
     #[test]
     fn test_mem_size() {
         let state = State::default();
@@ -135,4 +135,1833 @@ use engine::Engine;
 
 		assert!(dynamic_node.translation.y >= 0.0, "Fast object fell through the floor");
 	}
+
+	#[test]
+	fn contact_events_are_delivered_to_app() {
+		#[derive(Default)]
+		struct TestApp {
+			pub floor_id: Option<ArenaId<Node>>,
+			pub box_id: Option<ArenaId<Node>>,
+			pub begins: usize,
+			pub persists: usize,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+
+				let floor_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Static,
+						stationary: true,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 1.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(10.0, 1.0, 10.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				self.floor_id = Some(state.nodes.insert(floor_node));
+
+				let dynamic_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					lock_rotation: true,
+					translation: Vec3::new(0.0, 5.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(1.0, 1.0, 1.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				self.box_id = Some(state.nodes.insert(dynamic_node));
+			}
+
+			fn on_physics_event(&mut self, event: PhycicsEvent, _state: &mut crate::State) {
+				match event {
+					PhycicsEvent::ContactBegin { node1, node2, impulse, .. } => {
+						let ids = [self.floor_id.unwrap(), self.box_id.unwrap()];
+						assert!(ids.contains(&node1) && ids.contains(&node2));
+						assert!(impulse >= 0.0);
+						self.begins += 1;
+					}
+					PhycicsEvent::ContactPersist { .. } => self.persists += 1,
+					_ => {}
+				}
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..200 {
+			engine.render(0.016);
+		}
+		assert!(engine.app.begins > 0, "Landing on the floor should begin a contact");
+
+		let begins = engine.app.begins;
+		let persists = engine.app.persists;
+		for _ in 0..200 {
+			engine.render(0.016);
+		}
+		assert_eq!(engine.app.begins, begins, "Resting contact should not begin again");
+		assert!(engine.app.persists > persists, "Resting contact should persist");
+	}
+
+	#[test]
+	fn sensor_reports_enter_and_exit_without_blocking() {
+		#[derive(Default)]
+		struct TestApp {
+			pub sensor_id: Option<ArenaId<Node>>,
+			pub box_id: Option<ArenaId<Node>>,
+			pub entered: usize,
+			pub exited: usize,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+
+				let floor_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Static,
+						stationary: true,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 1.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(10.0, 1.0, 10.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				state.nodes.insert(floor_node);
+
+				let sensor_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Static,
+						sensor: true,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 8.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(2.0, 1.0, 2.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				self.sensor_id = Some(state.nodes.insert(sensor_node));
+
+				let dynamic_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					lock_rotation: true,
+					translation: Vec3::new(0.0, 12.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(1.0, 1.0, 1.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				self.box_id = Some(state.nodes.insert(dynamic_node));
+			}
+
+			fn on_physics_event(&mut self, event: PhycicsEvent, _state: &mut crate::State) {
+				match event {
+					PhycicsEvent::TriggerEnter { sensor, node } => {
+						assert_eq!(Some(sensor), self.sensor_id);
+						assert_eq!(Some(node), self.box_id);
+						self.entered += 1;
+					}
+					PhycicsEvent::TriggerExit { sensor, node } => {
+						assert_eq!(Some(sensor), self.sensor_id);
+						assert_eq!(Some(node), self.box_id);
+						self.exited += 1;
+					}
+					_ => {}
+				}
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..300 {
+			engine.render(0.016);
+		}
+
+		assert_eq!(engine.app.entered, 1);
+		assert_eq!(engine.app.exited, 1);
+		let dynamic_node = engine.state.nodes.get(&engine.app.box_id.unwrap()).unwrap();
+		assert!(dynamic_node.translation.y < 4.0, "Sensor should not stop the falling box");
+	}
+
+	#[test]
+	fn masked_bodies_do_not_collide() {
+		const DEBRIS: u32 = 1 << 1;
+
+		#[derive(Default)]
+		struct TestApp {
+			pub debris: Vec<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+
+				let floor_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Static,
+						stationary: true,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 1.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(10.0, 1.0, 10.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				state.nodes.insert(floor_node);
+
+				for y in [4.0, 7.0] {
+					let debris_node = Node {
+						physics: PhysicsProps {
+							typ: PhycisObjectType::Dynamic,
+							mass: 1.0,
+							collision_group: DEBRIS,
+							collision_mask: !DEBRIS,
+							..Default::default()
+						},
+						lock_rotation: true,
+						translation: Vec3::new(0.0, y, 0.0),
+						collision_shape: Some(CollisionShape::Box { size: Vec3::new(1.0, 1.0, 1.0) }),
+						parent: NodeParent::Scene(scene_id),
+						..Default::default()
+					};
+					self.debris.push(state.nodes.insert(debris_node));
+				}
+			}
+
+			fn on_physics_event(&mut self, event: PhycicsEvent, _state: &mut crate::State) {
+				if let PhycicsEvent::ContactBegin { node1, node2, .. } = event {
+					assert!(!(self.debris.contains(&node1) && self.debris.contains(&node2)), "Debris collided with debris");
+				}
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..300 {
+			engine.render(0.016);
+		}
+
+		for id in &engine.app.debris {
+			let node = engine.state.nodes.get(id).unwrap();
+			assert!(node.translation.y < 3.5, "Debris should rest on the floor, not on other debris");
+			assert!(node.translation.y > 2.5, "Debris should not fall through the floor");
+		}
+	}
+
+	#[test]
+	fn lamp_hangs_from_ceiling_joint() {
+		#[derive(Default)]
+		struct TestApp {
+			pub ceiling_id: Option<ArenaId<Node>>,
+			pub lamp_id: Option<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+
+				let ceiling = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Static,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 20.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(5.0, 0.5, 5.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				let ceiling_id = state.nodes.insert(ceiling);
+
+				let lamp = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					translation: Vec3::new(1.0, 17.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(0.3, 0.3, 0.3) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				let lamp_id = state.nodes.insert(lamp);
+
+				let joint = Joint::new(JointKind::Distance { rest_length: 3.0, stiffness: 0.0, damping: 0.0 }, ceiling_id, lamp_id)
+					.anchors(Vec3::new(0.0, -0.5, 0.0), Vec3::ZERO);
+				state.joints.insert(joint);
+
+				self.ceiling_id = Some(ceiling_id);
+				self.lamp_id = Some(lamp_id);
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		let mut min_x: f32 = 1.0;
+		for _ in 0..300 {
+			engine.render(0.016);
+			let lamp = engine.state.nodes.get(&engine.app.lamp_id.unwrap()).unwrap();
+			min_x = min_x.min(lamp.translation.x);
+		}
+
+		let lamp = engine.state.nodes.get(&engine.app.lamp_id.unwrap()).unwrap();
+		let distance = lamp.translation.distance(Vec3::new(0.0, 19.5, 0.0));
+		assert!((distance - 3.0).abs() < 0.1, "Lamp drifted from the rope length: {}", distance);
+		assert!(min_x < 0.0, "Lamp should swing past the anchor");
+	}
+
+	#[test]
+	fn resting_body_sleeps_and_wakes_on_force() {
+		#[derive(Default)]
+		struct TestApp {
+			pub box_id: Option<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+
+				let floor_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Static,
+						stationary: true,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 1.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(10.0, 1.0, 10.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				state.nodes.insert(floor_node);
+
+				let dynamic_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					lock_rotation: true,
+					translation: Vec3::new(0.0, 4.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(1.0, 1.0, 1.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				self.box_id = Some(state.nodes.insert(dynamic_node));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..300 {
+			engine.render(0.016);
+		}
+
+		let box_id = engine.app.box_id.unwrap();
+		let resting = engine.state.nodes.get(&box_id).unwrap().clone();
+		assert!(resting.physics.sleeping, "Resting box should fall asleep");
+
+		for _ in 0..10 {
+			engine.render(0.016);
+		}
+		let node = engine.state.nodes.get(&box_id).unwrap();
+		assert_eq!(node.translation, resting.translation, "Sleeping box should not move");
+
+		engine.state.nodes.get_mut(&box_id).unwrap().physics.force = Vec3::new(100.0, 0.0, 0.0);
+		engine.render(0.016);
+		let node = engine.state.nodes.get(&box_id).unwrap();
+		assert!(!node.physics.sleeping, "Force should wake the box");
+		assert!(node.translation.x > resting.translation.x);
+	}
+
+	#[test]
+	fn physics_stats_report_each_scene_step() {
+		#[derive(Default)]
+		struct TestApp {
+			pub falling_scene: Option<ArenaId<Scene>>,
+			pub bullet_scene: Option<ArenaId<Scene>>,
+			pub paused_scene: Option<ArenaId<Scene>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Static,
+						stationary: true,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 1.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(10.0, 1.0, 10.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					lock_rotation: true,
+					translation: Vec3::new(0.0, 4.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(1.0, 1.0, 1.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				self.falling_scene = Some(scene_id);
+
+				let scene_id = state.scenes.insert(Scene::new());
+				state.nodes.insert(Node {
+					translation: Vec3::new(5.0, 0.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(0.05, 2.0, 2.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 0.1,
+						velocity: Vec3::new(40.0, 0.0, 0.0),
+						gravity_scale: 0.0,
+						ccd: true,
+						..Default::default()
+					},
+					collision_shape: Some(CollisionShape::Sphere { radius: 0.05 }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				self.bullet_scene = Some(scene_id);
+
+				self.paused_scene = Some(state.scenes.insert(Scene {
+					physics_on: false,
+					..Scene::new()
+				}));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		engine.render(1.0 / 60.0);
+		let falling = engine.state.physics_stats[&engine.app.falling_scene.unwrap()].clone();
+		assert_eq!(falling.contacts, 0);
+		assert_eq!(falling.solver_passes, 0);
+		assert_eq!((falling.awake_bodies, falling.sleeping_bodies), (1, 0));
+		assert!(falling.total_time >= falling.broad_phase_time + falling.integration_time);
+		assert!(!engine.state.physics_stats.contains_key(&engine.app.paused_scene.unwrap()));
+
+		let mut toi_substeps = 0;
+		let mut contacts = 0;
+		for _ in 0..300 {
+			engine.render(1.0 / 60.0);
+			toi_substeps += engine.state.physics_stats[&engine.app.bullet_scene.unwrap()].toi_substeps;
+			let falling = &engine.state.physics_stats[&engine.app.falling_scene.unwrap()];
+			if falling.contacts > 0 {
+				assert!(falling.broad_phase_pairs >= falling.contacts);
+				assert!(falling.solver_passes >= 1);
+				contacts += 1;
+			}
+		}
+		assert!(contacts > 0, "Box should land on the floor");
+		assert!(toi_substeps > 0, "Bullet should hit the wall in a sub-step");
+		let falling = &engine.state.physics_stats[&engine.app.falling_scene.unwrap()];
+		assert_eq!((falling.awake_bodies, falling.sleeping_bodies), (0, 1));
+		assert_eq!(falling.contacts, 0, "Resting pairs are not tested");
+	}
+
+	#[test]
+	fn physics_does_not_depend_on_frame_rate() {
+		#[derive(Default)]
+		struct TestApp {
+			pub box_id: Option<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				state.physics_settings.fixed_dt = 1.0 / 64.0;
+				let scene_id = state.scenes.insert(Scene::new());
+
+				let floor_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Static,
+						stationary: true,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 1.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(10.0, 1.0, 10.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				state.nodes.insert(floor_node);
+
+				let dynamic_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						velocity: Vec3::new(1.0, 0.0, 0.0),
+						..Default::default()
+					},
+					lock_rotation: true,
+					translation: Vec3::new(0.0, 4.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(1.0, 1.0, 1.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				self.box_id = Some(state.nodes.insert(dynamic_node));
+			}
+		}
+
+		let mut slow = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..64 {
+			slow.render(1.0 / 32.0);
+		}
+		let mut fast = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..256 {
+			fast.render(1.0 / 128.0);
+		}
+
+		let slow_box = slow.state.nodes.get(&slow.app.box_id.unwrap()).unwrap();
+		let fast_box = fast.state.nodes.get(&fast.app.box_id.unwrap()).unwrap();
+		assert_eq!(slow_box.translation, fast_box.translation);
+		assert_eq!(slow_box.physics.velocity, fast_box.physics.velocity);
+	}
+
+	#[test]
+	fn kinematic_platform_carries_and_pushes_bodies() {
+		#[derive(Default)]
+		struct TestApp {
+			pub platform_id: Option<ArenaId<Node>>,
+			pub rider_id: Option<ArenaId<Node>>,
+			pub pusher_id: Option<ArenaId<Node>>,
+			pub crate_id: Option<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+
+				let platform = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Kinematic,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 0.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(3.0, 0.5, 3.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				self.platform_id = Some(state.nodes.insert(platform));
+
+				let rider = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					lock_rotation: true,
+					translation: Vec3::new(0.0, 1.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(0.5, 0.5, 0.5) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				self.rider_id = Some(state.nodes.insert(rider));
+
+				let floor = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Static,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 0.0, 20.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(10.0, 0.5, 5.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				state.nodes.insert(floor);
+
+				let pusher = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Kinematic,
+						..Default::default()
+					},
+					translation: Vec3::new(-3.0, 1.5, 20.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(0.5, 1.0, 1.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				self.pusher_id = Some(state.nodes.insert(pusher));
+
+				let crate_node = Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					lock_rotation: true,
+					translation: Vec3::new(0.0, 1.0, 20.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(0.5, 0.5, 0.5) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				};
+				self.crate_id = Some(state.nodes.insert(crate_node));
+			}
+
+			fn on_process(&mut self, state: &mut crate::State, delta: f32) {
+				state.nodes.get_mut(&self.platform_id.unwrap()).unwrap().translation.x += delta;
+				state.nodes.get_mut(&self.pusher_id.unwrap()).unwrap().translation.x += 2.0 * delta;
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..180 {
+			engine.render(1.0 / 60.0);
+		}
+
+		let platform = engine.state.nodes.get(&engine.app.platform_id.unwrap()).unwrap();
+		let rider = engine.state.nodes.get(&engine.app.rider_id.unwrap()).unwrap();
+		assert!((platform.physics.velocity.x - 1.0).abs() < 0.01, "Platform velocity {:?}", platform.physics.velocity);
+		assert!(rider.translation.x > 1.5, "Rider should be carried along {:?}", rider.translation);
+		assert!(rider.translation.y > 0.9, "Rider should stay on the platform {:?}", rider.translation);
+
+		let pusher = engine.state.nodes.get(&engine.app.pusher_id.unwrap()).unwrap();
+		let crate_node = engine.state.nodes.get(&engine.app.crate_id.unwrap()).unwrap();
+		assert!(crate_node.translation.x >= pusher.translation.x + 0.95, "Pusher passed through the crate {:?} {:?}", crate_node.translation, pusher.translation);
+	}
+
+	#[test]
+	fn kinematic_moves_in_physics_update_survive_later_steps() {
+		#[derive(Default)]
+		struct TestApp {
+			pub elevator: Option<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				self.elevator = Some(state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Kinematic,
+						..Default::default()
+					},
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(1.0, 0.1, 1.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				}));
+			}
+
+			fn on_phycis_update(&mut self, state: &mut crate::State, _dt: f32) {
+				state.nodes.get_mut(&self.elevator.unwrap()).unwrap().translation.y += 0.1;
+			}
+		}
+
+		// Several steps per frame
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..10 {
+			engine.render(1.0 / 20.0);
+		}
+
+		let elevator = engine.state.nodes.get(&engine.app.elevator.unwrap()).unwrap();
+		let expected = 0.1 * engine.state.physics_step as f32;
+		assert!(engine.state.physics_step > 20);
+		assert!((elevator.translation.y - expected).abs() < 1e-3, "{:?} expected {}", elevator.translation, expected);
+		assert!((elevator.physics.velocity.y - 6.0).abs() < 0.01, "{:?}", elevator.physics.velocity);
+	}
+
+	#[test]
+	fn scenes_use_their_own_gravity() {
+		#[derive(Default)]
+		struct TestApp {
+			pub earth_box: Option<ArenaId<Node>>,
+			pub floating_box: Option<ArenaId<Node>>,
+			pub moon_box: Option<ArenaId<Node>>,
+			pub space_box: Option<ArenaId<Node>>,
+			pub paused_box: Option<ArenaId<Node>>,
+		}
+
+		fn falling_box(state: &mut crate::State, scene_id: ArenaId<Scene>, gravity_scale: f32) -> ArenaId<Node> {
+			state.nodes.insert(Node {
+				physics: PhysicsProps {
+					typ: PhycisObjectType::Dynamic,
+					mass: 1.0,
+					gravity_scale,
+					..Default::default()
+				},
+				translation: Vec3::new(0.0, 100.0, 0.0),
+				collision_shape: Some(CollisionShape::Box { size: Vec3::new(0.5, 0.5, 0.5) }),
+				parent: NodeParent::Scene(scene_id),
+				..Default::default()
+			})
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let earth = state.scenes.insert(Scene::new());
+				self.earth_box = Some(falling_box(state, earth, 1.0));
+				self.floating_box = Some(falling_box(state, earth, 0.0));
+
+				let mut moon = Scene::new();
+				moon.gravity = Vec3::new(0.0, -1.6, 0.0);
+				let moon = state.scenes.insert(moon);
+				self.moon_box = Some(falling_box(state, moon, 1.0));
+
+				let mut space = Scene::new();
+				space.gravity = Vec3::ZERO;
+				let space = state.scenes.insert(space);
+				self.space_box = Some(falling_box(state, space, 1.0));
+
+				let mut paused = Scene::new();
+				paused.physics_on = false;
+				let paused = state.scenes.insert(paused);
+				self.paused_box = Some(falling_box(state, paused, 1.0));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..61 {
+			engine.render(1.0 / 60.0);
+		}
+
+		let velocity = |id: Option<ArenaId<Node>>| engine.state.nodes.get(&id.unwrap()).unwrap().physics.velocity.y;
+		let earth = velocity(engine.app.earth_box);
+		let moon = velocity(engine.app.moon_box);
+		assert!(earth < -9.0, "{}", earth);
+		assert!((moon / earth - 0.16).abs() < 0.001, "{} {}", moon, earth);
+		assert_eq!(velocity(engine.app.floating_box), 0.0);
+		assert_eq!(velocity(engine.app.space_box), 0.0);
+		assert_eq!(velocity(engine.app.paused_box), 0.0);
+		let paused = engine.state.nodes.get(&engine.app.paused_box.unwrap()).unwrap();
+		assert_eq!(paused.translation.y, 100.0);
+	}
+
+	#[test]
+	fn default_scene_keeps_fixed_gravity_behaviour() {
+		// Scenes used to always simulate with a gravity of -10, the defaults keep that
+		for scene in [Scene::new(), Scene::default()] {
+			assert_eq!(scene.gravity, Vec3::new(0.0, -10.0, 0.0));
+			assert!(scene.physics_on);
+			assert_eq!(scene.scale, Vec3::ONE);
+		}
+
+		#[derive(Default)]
+		struct TestApp {
+			pub box_id: Option<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::default());
+				self.box_id = Some(state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.5) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				}));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..30 {
+			engine.render(1.0 / 60.0);
+		}
+		let node = engine.state.nodes.get(&engine.app.box_id.unwrap()).unwrap();
+		let expected = -10.0 * engine.state.physics_step as f32 / 60.0;
+		assert!((node.physics.velocity.y - expected).abs() < 1e-3, "{:?} expected {}", node.physics.velocity, expected);
+		assert_eq!(node.global_transform.to_scale_rotation_translation().0, Vec3::ONE);
+	}
+
+	#[test]
+	fn parented_and_scaled_bodies_collide_in_world_space() {
+		#[derive(Default)]
+		struct TestApp {
+			pub scaled_box: Option<ArenaId<Node>>,
+			pub child_box: Option<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				let platform = state.nodes.insert(Node {
+					translation: Vec3::new(0.0, 5.0, 0.0),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				state.nodes.insert(Node {
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(5.0, 0.5, 5.0) }),
+					parent: NodeParent::Node(platform),
+					..Default::default()
+				});
+				self.scaled_box = Some(state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					translation: Vec3::new(-2.0, 8.0, 0.0),
+					scale: Vec3::splat(2.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.5) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				}));
+				self.child_box = Some(state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					translation: Vec3::new(2.0, 3.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.5) }),
+					parent: NodeParent::Node(platform),
+					..Default::default()
+				}));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..180 {
+			engine.render(1.0 / 60.0);
+		}
+
+		// The floor's top is at 5.5 in world space, the scaled box has a half size of 1
+		let scaled = engine.state.nodes.get(&engine.app.scaled_box.unwrap()).unwrap();
+		assert!((scaled.translation.y - 6.5).abs() < 0.1, "{:?}", scaled.translation);
+		// The child box rests on the floor and keeps its pose relative to the platform
+		let child = engine.state.nodes.get(&engine.app.child_box.unwrap()).unwrap();
+		assert!((child.translation.y - 1.0).abs() < 0.1, "{:?}", child.translation);
+		assert!((child.global_transform.w_axis.y - 6.0).abs() < 0.1, "{:?}", child.global_transform);
+	}
+
+	#[test]
+	fn children_follow_a_body_moved_in_the_same_step() {
+		#[derive(Default)]
+		struct TestApp {
+			pub chain: Vec<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				let body = state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.5) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				self.chain.push(body);
+				for _ in 0..8 {
+					let parent = *self.chain.last().unwrap();
+					self.chain.push(state.nodes.insert(Node {
+						translation: Vec3::new(0.0, 1.0, 0.0),
+						parent: NodeParent::Node(parent),
+						..Default::default()
+					}));
+				}
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..10 {
+			// Several steps per frame
+			engine.render(1.0 / 20.0);
+		}
+
+		let body = engine.state.nodes.get(&engine.app.chain[0]).unwrap();
+		assert!(body.physics.velocity.y < -1.0, "{:?}", body.physics.velocity);
+		for pair in engine.app.chain.windows(2) {
+			let parent = engine.state.nodes.get(&pair[0]).unwrap();
+			let child = engine.state.nodes.get(&pair[1]).unwrap();
+			let expected = parent.global_transform * child.matrix();
+			assert!(child.global_transform.abs_diff_eq(expected, 1e-4), "{:?} expected {:?}", child.global_transform, expected);
+		}
+	}
+
+	#[test]
+	fn node_order_is_only_rebuilt_when_the_hierarchy_changes() {
+		#[derive(Default)]
+		struct TestApp {
+			pub scene_id: Option<ArenaId<Scene>>,
+			pub left: Option<ArenaId<Node>>,
+			pub right: Option<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				self.scene_id = Some(scene_id);
+				for (x, side) in [(-5.0, &mut self.left), (5.0, &mut self.right)] {
+					*side = Some(state.nodes.insert(Node {
+						translation: Vec3::new(x, 0.0, 0.0),
+						parent: NodeParent::Scene(scene_id),
+						..Default::default()
+					}));
+				}
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		engine.render(1.0 / 60.0);
+		let version = engine.state.hierarchy.version();
+		engine.render(1.0 / 60.0);
+		assert_eq!(engine.state.hierarchy.version(), version, "Nothing changed, nothing to rebuild");
+
+		// Inserted directly, picked up by the sync on the next frame
+		let (left, right) = (engine.app.left.unwrap(), engine.app.right.unwrap());
+		let hand = engine.state.nodes.insert(Node {
+			translation: Vec3::new(0.0, 1.0, 0.0),
+			parent: NodeParent::Node(left),
+			..Default::default()
+		});
+		engine.render(1.0 / 60.0);
+		assert!(engine.state.hierarchy.is_current(&engine.state.nodes));
+		let hand_position = |engine: &Engine<TestApp, MockHardware>| engine.state.nodes.get(&hand).unwrap().global_transform.w_axis.truncate();
+		assert_eq!(hand_position(&engine), Vec3::new(-5.0, 1.0, 0.0));
+
+		engine.state.reparent(hand, NodeParent::Node(right), false);
+		assert!(engine.state.hierarchy.is_current(&engine.state.nodes));
+		engine.render(1.0 / 60.0);
+		assert_eq!(hand_position(&engine), Vec3::new(5.0, 1.0, 0.0));
+
+		// Parents assigned directly on the node are followed as well
+		engine.state.nodes.get_mut(&hand).unwrap().parent = NodeParent::Node(left);
+		engine.render(1.0 / 60.0);
+		assert_eq!(hand_position(&engine), Vec3::new(-5.0, 1.0, 0.0));
+		assert_eq!(engine.state.children(left), &[hand]);
+		engine.state.nodes.get_mut(&hand).unwrap().parent = NodeParent::Node(right);
+		engine.render(1.0 / 60.0);
+
+		engine.state.nodes.remove(&hand);
+		assert_eq!(engine.state.children(right), &[hand], "Stale until the next frame");
+		engine.render(1.0 / 60.0);
+		assert!(engine.state.children(right).is_empty());
+	}
+
+	#[test]
+	fn compound_table_rests_on_its_legs() {
+		#[derive(Default)]
+		struct TestApp {
+			pub scene_id: Option<ArenaId<Scene>>,
+			pub table: Option<ArenaId<Node>>,
+			pub toy: Option<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				self.scene_id = Some(scene_id);
+				state.nodes.insert(Node {
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(250.0, 0.5, 250.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+
+				let leg = CollisionShape::Box { size: Vec3::new(0.1, 0.5, 0.1) };
+				let mut parts = vec![Collider::new(CollisionShape::Box { size: Vec3::new(1.0, 0.1, 1.0) }, Vec3::new(0.0, 0.1, 0.0), Quat::IDENTITY)];
+				for (x, z) in [(-0.8, -0.8), (-0.8, 0.8), (0.8, -0.8), (0.8, 0.8)] {
+					parts.push(Collider::new(leg.clone(), Vec3::new(x, -0.5, z), Quat::IDENTITY));
+				}
+				self.table = Some(state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 5.0,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 3.0, 0.0),
+					collision_shape: Some(CollisionShape::Compound { parts }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				}));
+
+				// Sits between the legs, under the table top
+				self.toy = Some(state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1.0,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 0.7, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.2) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				}));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..180 {
+			engine.render(1.0 / 60.0);
+		}
+
+		let table = engine.state.nodes.get(&engine.app.table.unwrap()).unwrap();
+		assert!((table.translation.y - 1.5).abs() < 0.1, "{:?}", table.translation);
+		assert!(table.rotation.angle_between(Quat::IDENTITY) < 0.05, "{:?}", table.rotation);
+		let toy = engine.state.nodes.get(&engine.app.toy.unwrap()).unwrap();
+		assert!(toy.translation.abs_diff_eq(Vec3::new(0.0, 0.7, 0.0), 0.05), "{:?}", toy.translation);
+
+		// Rays pass between the legs
+		let hit = engine.state.raycast(engine.app.scene_id.unwrap(), Vec3::new(-5.0, 0.7, 0.0), Vec3::X, 10.0, &QueryFilter::new()).unwrap();
+		assert_eq!(hit.node_id, engine.app.toy.unwrap());
+		let hit = engine.state.raycast(engine.app.scene_id.unwrap(), Vec3::new(-5.0, 0.7, 0.8), Vec3::X, 10.0, &QueryFilter::new()).unwrap();
+		assert_eq!(hit.node_id, engine.app.table.unwrap());
+		assert!((hit.distance - 4.1).abs() < 0.1, "{}", hit.distance);
+	}
+
+	#[test]
+	fn rotated_compound_parts_scale_along_their_axes() {
+		let tilted = Quat::from_rotation_z(std::f32::consts::FRAC_PI_4);
+		let upright = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+		let shape = CollisionShape::Compound { parts: vec![
+			Collider::new(CollisionShape::Box { size: Vec3::new(1.0, 0.2, 0.2) }, Vec3::new(1.0, 0.0, 0.0), tilted),
+			Collider::new(CollisionShape::Box { size: Vec3::new(1.0, 0.2, 0.2) }, Vec3::ZERO, upright),
+		] };
+
+		let half = |shape: &CollisionShape, index: usize| match &shape.parts()[index].shape {
+			CollisionShape::Box { size } => *size,
+			shape => panic!("{:?}", shape),
+		};
+		let doubled = shape.scaled(Vec3::splat(2.0));
+		assert!(half(&doubled, 0).abs_diff_eq(Vec3::new(2.0, 0.4, 0.4), 1e-5), "{:?}", half(&doubled, 0));
+		assert_eq!(doubled.parts()[0].offset, Vec3::new(2.0, 0.0, 0.0));
+
+		// The upright part's long side lies along the node's y axis
+		let stretched = shape.scaled(Vec3::new(3.0, 1.0, 1.0));
+		assert!(half(&stretched, 1).abs_diff_eq(Vec3::new(1.0, 0.6, 0.2), 1e-5), "{:?}", half(&stretched, 1));
+		assert_eq!(stretched.parts()[1].rotation, upright);
+	}
+
+	#[test]
+	fn center_of_mass_is_placed_like_translation() {
+		#[derive(Default)]
+		struct TestApp {
+			pub centered: Option<ArenaId<Node>>,
+			pub offset: Option<ArenaId<Node>>,
+		}
+
+		fn hammer(state: &mut crate::State, scene_id: ArenaId<Scene>, x: f32) -> ArenaId<Node> {
+			let parts = vec![
+				Collider::new(CollisionShape::Box { size: Vec3::new(0.5, 0.5, 0.5) }, Vec3::new(0.0, 1.0, 0.0), Quat::IDENTITY),
+				Collider::new(CollisionShape::Box { size: Vec3::new(0.1, 1.0, 0.1) }, Vec3::ZERO, Quat::IDENTITY),
+			];
+			state.nodes.insert(Node {
+				physics: PhysicsProps {
+					typ: PhycisObjectType::Dynamic,
+					mass: 1.0,
+					gravity_scale: 0.0,
+					..Default::default()
+				},
+				translation: Vec3::new(x, 2.0, 0.0),
+				rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+				collision_shape: Some(CollisionShape::Compound { parts }),
+				parent: NodeParent::Scene(scene_id),
+				..Default::default()
+			})
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				self.centered = Some(hammer(state, scene_id, 0.0));
+				self.offset = Some(hammer(state, scene_id, 10.0));
+			}
+
+			fn on_phycis_update(&mut self, state: &mut crate::State, _dt: f32) {
+				if state.physics_step != 0 {
+					return;
+				}
+				let centered = state.nodes.get_mut(&self.centered.unwrap()).unwrap();
+				let center = centered.world_center_of_mass();
+				centered.apply_impulse(center, Vec3::new(0.0, 0.0, 1.0));
+				let offset = state.nodes.get_mut(&self.offset.unwrap()).unwrap();
+				let origin = offset.translation;
+				offset.apply_impulse(origin, Vec3::new(0.0, 0.0, 1.0));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		let hammer = engine.state.nodes.get(&engine.app.centered.unwrap()).unwrap();
+		// Weighted by volume, the head holds 1.0 of 1.08
+		let local = hammer.center_of_mass();
+		assert!(local.abs_diff_eq(Vec3::new(0.0, 1.0 / 1.08, 0.0), 1e-5), "{:?}", local);
+		// The head sits on the node's y axis, which the rotation turns towards -x
+		let center = hammer.step_center_of_mass();
+		assert!(center.abs_diff_eq(Vec3::new(-1.0 / 1.08, 2.0, 0.0), 1e-5), "{:?}", center);
+
+		for _ in 0..10 {
+			engine.render(1.0 / 60.0);
+		}
+		let centered = engine.state.nodes.get(&engine.app.centered.unwrap()).unwrap();
+		assert!(centered.physics.angular_velocity.length() < 1e-5, "{:?}", centered.physics.angular_velocity);
+		let offset = engine.state.nodes.get(&engine.app.offset.unwrap()).unwrap();
+		assert!(offset.physics.angular_velocity.length() > 0.1, "{:?}", offset.physics.angular_velocity);
+	}
+
+	#[test]
+	fn bvh_scene_keeps_bodies_on_huge_floor() {
+		#[derive(Default)]
+		struct TestApp {
+			pub scene_id: Option<ArenaId<Scene>>,
+			pub boxes: Vec<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene {
+					broad_phase: BroadPhaseType::Bvh,
+					..Scene::new()
+				});
+				self.scene_id = Some(scene_id);
+				for x in [-100.0, -50.0, 0.0, 50.0, 100.0] {
+					self.boxes.push(state.nodes.insert(Node {
+						physics: PhysicsProps {
+							typ: PhycisObjectType::Dynamic,
+							mass: 1.0,
+							..Default::default()
+						},
+						translation: Vec3::new(x, 3.0, 0.0),
+						collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.25) }),
+						parent: NodeParent::Scene(scene_id),
+						..Default::default()
+					}));
+				}
+				// Covers every cell a grid would use, the tree keeps it in one leaf
+				state.nodes.insert(Node {
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(250.0, 0.5, 250.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..180 {
+			engine.render(1.0 / 60.0);
+		}
+
+		let scene_id = engine.app.scene_id.unwrap();
+		assert!(matches!(engine.state.broad_phases.get(&scene_id), Some(broad_phase::BroadPhase::Bvh(_))));
+		for node_id in &engine.app.boxes {
+			let node = engine.state.nodes.get(node_id).unwrap();
+			assert!((node.translation.y - 0.75).abs() < 0.05, "{:?}", node.translation);
+		}
+
+		let hit = engine.state.raycast(scene_id, Vec3::new(50.0, 10.0, 0.0), Vec3::NEG_Y, 20.0, &QueryFilter::new()).unwrap();
+		assert_eq!(hit.node_id, engine.app.boxes[3]);
+		assert!((hit.distance - 9.0).abs() < 0.05, "{}", hit.distance);
+		let hit = engine.state.raycast(scene_id, Vec3::new(25.0, 10.0, 0.0), Vec3::NEG_Y, 20.0, &QueryFilter::new()).unwrap();
+		assert!((hit.distance - 9.5).abs() < 0.05, "{}", hit.distance);
+	}
+
+	#[test]
+	fn ccd_bullet_does_not_pass_through_thin_wall() {
+		#[derive(Default)]
+		struct TestApp {
+			pub bullet: Option<ArenaId<Node>>,
+			pub unswept_bullet: Option<ArenaId<Node>>,
+		}
+
+		fn bullet(state: &mut crate::State, scene_id: ArenaId<Scene>, z: f32, ccd: bool) -> ArenaId<Node> {
+			state.nodes.insert(Node {
+				physics: PhysicsProps {
+					typ: PhycisObjectType::Dynamic,
+					mass: 0.1,
+					velocity: Vec3::new(40.0, 0.0, 0.0),
+					gravity_scale: 0.0,
+					ccd,
+					..Default::default()
+				},
+				translation: Vec3::new(0.0, 0.0, z),
+				collision_shape: Some(CollisionShape::Sphere { radius: 0.05 }),
+				parent: NodeParent::Scene(scene_id),
+				..Default::default()
+			})
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				state.nodes.insert(Node {
+					translation: Vec3::new(5.0, 0.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(0.05, 2.0, 2.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				self.bullet = Some(bullet(state, scene_id, 1.0, true));
+				self.unswept_bullet = Some(bullet(state, scene_id, -1.0, false));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..60 {
+			engine.render(1.0 / 60.0);
+		}
+
+		let bullet = engine.state.nodes.get(&engine.app.bullet.unwrap()).unwrap();
+		assert!(bullet.translation.x < 4.95, "Bullet tunneled through the wall {:?}", bullet.translation);
+		assert!(bullet.physics.velocity.x <= 0.0, "{:?}", bullet.physics.velocity);
+		// Without sweeping the same bullet skips over the wall between two steps
+		let unswept = engine.state.nodes.get(&engine.app.unswept_bullet.unwrap()).unwrap();
+		assert!(unswept.translation.x > 5.0, "{:?}", unswept.translation);
+	}
+
+	#[test]
+	fn deterministic_replay_gives_identical_state() {
+		#[derive(Default)]
+		struct TestApp {
+			pub boxes: Vec<ArenaId<Node>>,
+			pub hashes: Vec<u64>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				state.physics_settings.deterministic = true;
+				let scene_id = state.scenes.insert(Scene::new());
+				state.nodes.insert(Node {
+					translation: Vec3::new(0.0, -0.5, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(20.0, 0.5, 20.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				for i in 0..6 {
+					self.boxes.push(state.nodes.insert(Node {
+						physics: PhysicsProps {
+							typ: PhycisObjectType::Dynamic,
+							mass: 1.0,
+							..Default::default()
+						},
+						translation: Vec3::new((i % 3) as f32 * 0.6, 1.0 + i as f32 * 1.1, (i / 3) as f32 * 0.3),
+						collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.5) }),
+						parent: NodeParent::Scene(scene_id),
+						..Default::default()
+					}));
+				}
+			}
+
+			fn on_phycis_update(&mut self, state: &mut crate::State, _dt: f32) {
+				self.hashes.push(state.physics_hash());
+				// Recorded input: push the first box sideways for a while
+				let force = if (40..60).contains(&state.physics_step) { Vec3::new(30.0, 0.0, 5.0) } else { Vec3::ZERO };
+				state.nodes.get_mut(&self.boxes[0]).unwrap().physics.force = force;
+			}
+		}
+
+		let mut steady = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..150 {
+			steady.render(1.0 / 60.0);
+		}
+		// Same session at an uneven frame rate, steps land in different frames
+		let mut uneven = Engine::new(TestApp::default(), MockHardware::new());
+		for frame in 0..200 {
+			uneven.render(if frame % 3 == 0 { 1.0 / 30.0 } else { 1.0 / 120.0 });
+		}
+
+		let steps = steady.app.hashes.len().min(uneven.app.hashes.len());
+		assert!(steps >= 140, "{}", steps);
+		assert_eq!(steady.app.hashes[..steps], uneven.app.hashes[..steps]);
+		assert_ne!(steady.app.hashes[0], steady.app.hashes[steps - 1]);
+	}
+
+	#[test]
+	fn parallel_stepping_matches_serial() {
+		#[derive(Default)]
+		struct TestApp {
+			pub threads: usize,
+			pub hashes: Vec<u64>,
+			pub events: Vec<String>,
+		}
+
+		fn dynamic_box(state: &mut crate::State, scene_id: ArenaId<Scene>, translation: Vec3) -> ArenaId<Node> {
+			state.nodes.insert(Node {
+				physics: PhysicsProps {
+					typ: PhycisObjectType::Dynamic,
+					mass: 1.0,
+					..Default::default()
+				},
+				translation,
+				collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.25) }),
+				parent: NodeParent::Scene(scene_id),
+				..Default::default()
+			})
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				state.physics_settings.deterministic = true;
+				state.physics_settings.threads = self.threads;
+				let mut rooms = Vec::new();
+				for _ in 0..4 {
+					let scene_id = state.scenes.insert(Scene {
+						broad_phase: BroadPhaseType::Bvh,
+						..Scene::new()
+					});
+					state.nodes.insert(Node {
+						collision_shape: Some(CollisionShape::Box { size: Vec3::new(20.0, 0.5, 20.0) }),
+						parent: NodeParent::Scene(scene_id),
+						translation: Vec3::new(0.0, -0.5, 0.0),
+						..Default::default()
+					});
+					rooms.push(scene_id);
+				}
+				// Enough separate stacks to split the first room into islands on several threads
+				for i in 0..20 {
+					let x = (i % 5) as f32 * 2.0 - 4.0;
+					let z = (i / 5) as f32 * 2.0 - 4.0;
+					let bottom = dynamic_box(state, rooms[0], Vec3::new(x, 0.5, z));
+					let top = dynamic_box(state, rooms[0], Vec3::new(x + 0.1, 1.2, z));
+					if i == 0 {
+						state.joints.insert(Joint::new(JointKind::BallSocket, bottom, top));
+					}
+				}
+				// A fast bullet resolved at its time of impact
+				let bullet = dynamic_box(state, rooms[1], Vec3::new(-8.0, 0.5, 0.0));
+				state.nodes.get_mut(&bullet).unwrap().physics.velocity = Vec3::new(120.0, 0.0, 0.0);
+				state.nodes.get_mut(&bullet).unwrap().physics.ccd = true;
+				dynamic_box(state, rooms[1], Vec3::new(3.0, 0.5, 0.0));
+				// Rooms joined by a joint have to be stepped together
+				let a = dynamic_box(state, rooms[2], Vec3::new(0.0, 2.0, 0.0));
+				let b = dynamic_box(state, rooms[3], Vec3::new(0.0, 2.0, 0.0));
+				state.joints.insert(Joint::new(JointKind::Distance { rest_length: 1.0, stiffness: 0.0, damping: 0.0 }, a, b));
+				// Enough free bodies to integrate on several threads
+				for i in 0..520 {
+					let translation = Vec3::new((i % 30) as f32 * 0.6 - 9.0, 3.0 + (i / 300) as f32, (i / 30 % 10) as f32 * 0.6 - 3.0);
+					dynamic_box(state, rooms[3], translation);
+				}
+			}
+
+			fn on_phycis_update(&mut self, state: &mut crate::State, _dt: f32) {
+				self.hashes.push(state.physics_hash());
+			}
+
+			fn on_physics_event(&mut self, event: PhycicsEvent, _state: &mut crate::State) {
+				self.events.push(format!("{:?}", event));
+			}
+		}
+
+		let mut serial = Engine::new(TestApp { threads: 1, ..Default::default() }, MockHardware::new());
+		let mut parallel = Engine::new(TestApp { threads: 4, ..Default::default() }, MockHardware::new());
+		for _ in 0..30 {
+			serial.render(1.0 / 60.0);
+			parallel.render(1.0 / 60.0);
+		}
+
+		assert_eq!(serial.app.hashes, parallel.app.hashes);
+		assert_eq!(serial.app.events, parallel.app.events);
+		assert!(!serial.app.events.is_empty());
+	}
+
+	#[test]
+	fn applied_forces_last_one_step_and_spin_off_center() {
+		#[derive(Default)]
+		struct TestApp {
+			pub pushed: Option<ArenaId<Node>>,
+			pub hit: Option<ArenaId<Node>>,
+		}
+
+		fn floating_box(state: &mut crate::State, scene_id: ArenaId<Scene>, x: f32) -> ArenaId<Node> {
+			state.nodes.insert(Node {
+				physics: PhysicsProps {
+					typ: PhycisObjectType::Dynamic,
+					mass: 2.0,
+					gravity_scale: 0.0,
+					..Default::default()
+				},
+				translation: Vec3::new(x, 0.0, 0.0),
+				collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.5) }),
+				parent: NodeParent::Scene(scene_id),
+				..Default::default()
+			})
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				self.pushed = Some(floating_box(state, scene_id, 0.0));
+				self.hit = Some(floating_box(state, scene_id, 10.0));
+			}
+
+			fn on_phycis_update(&mut self, state: &mut crate::State, _dt: f32) {
+				if state.physics_step != 0 {
+					return;
+				}
+				let pushed = state.nodes.get_mut(&self.pushed.unwrap()).unwrap();
+				let top = pushed.translation + Vec3::new(0.0, 0.5, 0.0);
+				pushed.apply_force(top, Vec3::new(60.0, 0.0, 0.0));
+				let hit = state.nodes.get_mut(&self.hit.unwrap()).unwrap();
+				let center = hit.translation;
+				hit.apply_impulse(center, Vec3::new(0.0, 0.0, 4.0));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..30 {
+			engine.render(1.0 / 60.0);
+		}
+
+		// 60 N on 2 kg for a single step
+		let pushed = engine.state.nodes.get(&engine.app.pushed.unwrap()).unwrap();
+		assert!((pushed.physics.velocity.x - 0.5).abs() < 1e-4, "{:?}", pushed.physics.velocity);
+		assert!(pushed.physics.angular_velocity.z < -0.1, "{:?}", pushed.physics.angular_velocity);
+		assert_eq!(pushed.physics.applied_force, Vec3::ZERO);
+
+		let hit = engine.state.nodes.get(&engine.app.hit.unwrap()).unwrap();
+		assert!(hit.physics.velocity.abs_diff_eq(Vec3::new(0.0, 0.0, 2.0), 1e-5), "{:?}", hit.physics.velocity);
+		assert_eq!(hit.physics.angular_velocity, Vec3::ZERO);
+	}
+
+	#[test]
+	fn impulses_take_world_points_and_rotated_inertia() {
+		#[derive(Default)]
+		struct TestApp {
+			pub child: Option<ArenaId<Node>>,
+			pub plank: Option<ArenaId<Node>>,
+		}
+
+		fn floating(size: Vec3, translation: Vec3, rotation: Quat, parent: NodeParent) -> Node {
+			Node {
+				physics: PhysicsProps {
+					typ: PhycisObjectType::Dynamic,
+					mass: 1.0,
+					gravity_scale: 0.0,
+					..Default::default()
+				},
+				translation,
+				rotation,
+				collision_shape: Some(CollisionShape::Box { size }),
+				parent,
+				..Default::default()
+			}
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				let holder = state.nodes.insert(Node {
+					translation: Vec3::new(10.0, 0.0, 0.0),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				self.child = Some(state.nodes.insert(floating(Vec3::splat(0.5), Vec3::ZERO, Quat::IDENTITY, NodeParent::Node(holder))));
+				// Long axis along world y
+				let upright = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+				self.plank = Some(state.nodes.insert(floating(Vec3::new(2.0, 0.2, 0.2), Vec3::new(0.0, 5.0, 0.0), upright, NodeParent::Scene(scene_id))));
+			}
+
+			fn on_phycis_update(&mut self, state: &mut crate::State, _dt: f32) {
+				if state.physics_step != 0 {
+					return;
+				}
+				let child = state.nodes.get_mut(&self.child.unwrap()).unwrap();
+				let center = child.world_center_of_mass();
+				assert_eq!(center, Vec3::new(10.0, 0.0, 0.0));
+				child.apply_impulse(center, Vec3::new(0.0, 0.0, 1.0));
+
+				let plank = state.nodes.get_mut(&self.plank.unwrap()).unwrap();
+				let end = plank.world_center_of_mass() + Vec3::new(0.0, 1.0, 0.0);
+				plank.apply_impulse(end, Vec3::new(0.0, 0.0, 1.0));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		engine.render(1.0 / 60.0);
+
+		let child = engine.state.nodes.get(&engine.app.child.unwrap()).unwrap();
+		assert!(child.physics.velocity.abs_diff_eq(Vec3::new(0.0, 0.0, 1.0), 1e-5), "{:?}", child.physics.velocity);
+		assert_eq!(child.physics.angular_velocity, Vec3::ZERO);
+
+		// Tipping the plank over its long side, 1 / (m / 12 * (4^2 + 0.4^2))
+		let plank = engine.state.nodes.get(&engine.app.plank.unwrap()).unwrap();
+		let expected = Vec3::new(12.0 / 16.16, 0.0, 0.0);
+		assert!(plank.physics.angular_velocity.abs_diff_eq(expected, 1e-3), "{:?}", plank.physics.angular_velocity);
+	}
+
+	#[test]
+	fn vehicle_rests_on_suspension_drives_steers_and_brakes() {
+		#[derive(Default)]
+		struct TestApp {
+			pub chassis: Option<ArenaId<Node>>,
+			pub vehicle: Option<ArenaId<Vehicle>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				let chassis = state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Dynamic,
+						mass: 1000.0,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 1.0, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(0.9, 0.3, 2.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Static,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, -0.5, 0.0),
+					collision_shape: Some(CollisionShape::Box { size: Vec3::new(100.0, 0.5, 100.0) }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				let mut vehicle = Vehicle::new(chassis);
+				for (x, z) in [(-0.8, -1.4), (0.8, -1.4), (-0.8, 1.4), (0.8, 1.4)] {
+					let wheel = Wheel::new(Vec3::new(x, -0.2, z), 0.35);
+					vehicle = match z < 0.0 {
+						true => vehicle.wheel(wheel.steered()),
+						false => vehicle.wheel(wheel.powered()),
+					};
+				}
+				self.chassis = Some(chassis);
+				self.vehicle = Some(state.vehicles.insert(vehicle));
+			}
+		}
+
+		fn run(engine: &mut Engine<TestApp, MockHardware>, frames: usize, throttle: f32, brake: f32, steering: f32) {
+			let vehicle = engine.state.vehicles.get_mut(&engine.app.vehicle.unwrap()).unwrap();
+			vehicle.throttle = throttle;
+			vehicle.brake = brake;
+			vehicle.steering = steering;
+			for _ in 0..frames {
+				engine.render(1.0 / 60.0);
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		run(&mut engine, 120, 0.0, 0.0, 0.0);
+		let chassis = engine.state.nodes.get(&engine.app.chassis.unwrap()).unwrap().clone();
+		let vehicle = engine.state.vehicles.get(&engine.app.vehicle.unwrap()).unwrap();
+		assert!(vehicle.wheels.iter().all(|wheel| wheel.contact.is_some()));
+		let load: f32 = vehicle.wheels.iter().map(|wheel| wheel.suspension_force).sum();
+		assert!((load - 1000.0 * 9.81).abs() < 500.0, "Suspension should carry the chassis {}", load);
+		assert!(chassis.translation.y > 0.7 && chassis.translation.y < 1.0, "{:?}", chassis.translation);
+		assert!(chassis.physics.velocity.length() < 0.1, "{:?}", chassis.physics.velocity);
+
+		// Forward is negative z
+		run(&mut engine, 120, 1.0, 0.0, 0.0);
+		let chassis = engine.state.nodes.get(&engine.app.chassis.unwrap()).unwrap().clone();
+		let vehicle = engine.state.vehicles.get(&engine.app.vehicle.unwrap()).unwrap();
+		assert!(chassis.translation.z < -3.0, "Vehicle should drive forward {:?}", chassis.translation);
+		assert!(chassis.translation.x.abs() < 0.1, "{:?}", chassis.translation);
+		assert!(vehicle.wheels.iter().all(|wheel| wheel.angular_velocity > 0.0));
+
+		run(&mut engine, 60, 1.0, 0.0, 1.0);
+		let chassis = engine.state.nodes.get(&engine.app.chassis.unwrap()).unwrap().clone();
+		assert!(chassis.physics.velocity.x < -1.0, "Vehicle should turn left {:?}", chassis.physics.velocity);
+		assert!(chassis.physics.angular_velocity.y > 0.1, "{:?}", chassis.physics.angular_velocity);
+
+		run(&mut engine, 180, 0.0, 1.0, 0.0);
+		let chassis = engine.state.nodes.get(&engine.app.chassis.unwrap()).unwrap().clone();
+		assert!(chassis.physics.velocity.length() < 0.2, "Vehicle should brake to a stop {:?}", chassis.physics.velocity);
+		assert!(chassis.physics.angular_velocity.length() < 0.1, "{:?}", chassis.physics.angular_velocity);
+		assert!(chassis.translation.y > 0.7, "{:?}", chassis.translation);
+	}
+
+	#[test]
+	fn wheel_transform_places_wheels_in_world_space() {
+		// A chassis whose parent is moved and turned, so its local pose
+		// differs from where it is drawn
+		let chassis = Node {
+			translation: Vec3::new(0.0, 1.0, 0.0),
+			global_transform: glam::Mat4::from_rotation_translation(
+				glam::Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+				Vec3::new(10.0, 1.0, 0.0),
+			),
+			..Default::default()
+		};
+		let mut arena = crate::Arena::new();
+		let mut vehicle = Vehicle::new(arena.insert(Node::new())).wheel(Wheel::new(Vec3::new(0.8, -0.2, -1.4), 0.35));
+		vehicle.wheels[0].suspension_length = 0.0;
+
+		let transform = vehicle.wheel_transform(&chassis, 0);
+		let position = transform.transform_point3(Vec3::ZERO);
+		assert!(position.abs_diff_eq(Vec3::new(8.6, 0.8, -0.8), 1e-4), "{:?}", position);
+		let axle = transform.transform_vector3(Vec3::X);
+		assert!(axle.abs_diff_eq(Vec3::new(0.0, 0.0, -1.0), 1e-4), "{:?}", axle);
+	}
+
+	#[test]
+	fn character_walks_up_step_and_slides_along_wall() {
+		#[derive(Default)]
+		struct TestApp {
+			pub character: Option<ArenaId<CharacterController>>,
+		}
+
+		fn static_box(state: &mut crate::State, scene_id: ArenaId<Scene>, translation: Vec3, size: Vec3) {
+			state.nodes.insert(Node {
+				physics: PhysicsProps {
+					typ: PhycisObjectType::Static,
+					..Default::default()
+				},
+				translation,
+				collision_shape: Some(CollisionShape::Box { size }),
+				parent: NodeParent::Scene(scene_id),
+				..Default::default()
+			});
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				static_box(state, scene_id, Vec3::ZERO, Vec3::new(20.0, 0.5, 20.0));
+				// 0.25 high step starting at x = 2 and a wall at z = 3
+				static_box(state, scene_id, Vec3::new(4.0, 0.625, 0.0), Vec3::new(2.0, 0.125, 4.0));
+				static_box(state, scene_id, Vec3::new(0.0, 2.5, 3.5), Vec3::new(10.0, 2.0, 0.5));
+				let node_id = state.nodes.insert(Node {
+					physics: PhysicsProps {
+						typ: PhycisObjectType::Kinematic,
+						..Default::default()
+					},
+					translation: Vec3::new(0.0, 1.5, 0.0),
+					collision_shape: Some(CollisionShape::Capsule { radius: 0.5, half_height: 0.5 }),
+					parent: NodeParent::Scene(scene_id),
+					..Default::default()
+				});
+				self.character = Some(state.characters.insert(CharacterController::new(node_id)));
+			}
+
+			fn on_phycis_update(&mut self, state: &mut crate::State, dt: f32) {
+				let controller = state.characters.get_mut(&self.character.unwrap()).unwrap();
+				controller.velocity = Vec3::new(2.0, controller.velocity.y, 2.0);
+				controller.velocity.y = match controller.grounded {
+					true => 0.0,
+					false => controller.velocity.y - 9.81 * dt,
+				};
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..120 {
+			engine.render(1.0 / 60.0);
+		}
+
+		let controller = engine.state.characters.get(&engine.app.character.unwrap()).unwrap();
+		let node = engine.state.nodes.get(&controller.node_id).unwrap();
+		assert!(controller.grounded);
+		assert!(node.translation.x > 3.0, "Character should walk onto the step {:?}", node.translation);
+		assert!((node.translation.y - 1.75).abs() < 0.05, "{:?}", node.translation);
+		assert!((node.translation.z - 2.5).abs() < 0.05, "Wall should stop the character {:?}", node.translation);
+		assert!(controller.actual_velocity.x > 1.5, "Character should slide along the wall {:?}", controller.actual_velocity);
+	}
+
+	fn field_test_box(state: &mut crate::State, scene_id: ArenaId<Scene>, translation: Vec3, mass: f32, gravity_scale: f32) -> ArenaId<Node> {
+		state.nodes.insert(Node {
+			physics: PhysicsProps {
+				typ: PhycisObjectType::Dynamic,
+				mass,
+				gravity_scale,
+				..Default::default()
+			},
+			translation,
+			collision_shape: Some(CollisionShape::Box { size: Vec3::splat(0.5) }),
+			parent: NodeParent::Scene(scene_id),
+			..Default::default()
+		})
+	}
+
+	fn field_node(state: &mut crate::State, scene_id: ArenaId<Scene>, translation: Vec3) -> ArenaId<Node> {
+		state.nodes.insert(Node {
+			translation,
+			parent: NodeParent::Scene(scene_id),
+			..Default::default()
+		})
+	}
+
+	#[test]
+	fn buoyancy_floats_box_at_its_waterline() {
+		#[derive(Default)]
+		struct TestApp {
+			pub floating: Option<ArenaId<Node>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				// Water 10 deep with its surface at y = 0
+				let water = field_node(state, scene_id, Vec3::new(0.0, -5.0, 0.0));
+				state.force_fields.insert(ForceField::new(water, CollisionShape::Box { size: Vec3::new(20.0, 5.0, 20.0) }, ForceFieldKind::Buoyancy {
+					surface: 5.0,
+					density: 1000.0,
+					drag: 3000.0,
+					angular_drag: 100.0,
+				}));
+				// Half as dense as the water
+				self.floating = Some(field_test_box(state, scene_id, Vec3::new(0.0, 2.0, 0.0), 500.0, 1.0));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..300 {
+			engine.render(1.0 / 60.0);
+		}
+		let floating = engine.state.nodes.get(&engine.app.floating.unwrap()).unwrap();
+		assert!(floating.translation.y.abs() < 0.05, "Box should float half submerged {:?}", floating.translation);
+		assert!(floating.physics.velocity.length() < 0.1, "{:?}", floating.physics.velocity);
+	}
+
+	#[test]
+	fn steady_applied_forces_let_bodies_sleep() {
+		#[derive(Default)]
+		struct TestApp {
+			pub floating: Option<ArenaId<Node>>,
+			pub push: bool,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				let water = field_node(state, scene_id, Vec3::new(0.0, -5.0, 0.0));
+				state.force_fields.insert(ForceField::new(water, CollisionShape::Box { size: Vec3::new(20.0, 5.0, 20.0) }, ForceFieldKind::Buoyancy {
+					surface: 5.0,
+					density: 1000.0,
+					drag: 3000.0,
+					angular_drag: 100.0,
+				}));
+				self.floating = Some(field_test_box(state, scene_id, Vec3::new(0.0, 0.5, 0.0), 500.0, 1.0));
+			}
+
+			fn on_phycis_update(&mut self, state: &mut crate::State, _dt: f32) {
+				if self.push {
+					let node = state.nodes.get_mut(&self.floating.unwrap()).unwrap();
+					let center = node.world_center_of_mass();
+					node.apply_force(center, Vec3::new(0.0, 0.0, 2000.0));
+				}
+			}
+		}
+
+		// Buoyancy pushes the box every step, but holds it still once it floats
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..300 {
+			engine.render(1.0 / 60.0);
+		}
+		let floating = engine.state.nodes.get(&engine.app.floating.unwrap()).unwrap();
+		assert!(floating.physics.sleeping, "Floating box should sleep {:?}", floating.physics.velocity);
+		assert!(floating.translation.y.abs() < 0.05, "{:?}", floating.translation);
+
+		// The load keeps coming every step, but the body is left out of the simulation
+		let rest = floating.translation;
+		let scene_id = floating.scene_id.unwrap();
+		for _ in 0..60 {
+			engine.render(1.0 / 60.0);
+			let floating = engine.state.nodes.get(&engine.app.floating.unwrap()).unwrap();
+			assert!(floating.physics.sleeping);
+			assert_eq!(floating.translation, rest);
+			assert_eq!(floating.physics.velocity, Vec3::ZERO);
+			let stats = &engine.state.physics_stats[&scene_id];
+			assert_eq!((stats.awake_bodies, stats.sleeping_bodies), (0, 1));
+		}
+
+		engine.app.push = true;
+		for _ in 0..30 {
+			engine.render(1.0 / 60.0);
+		}
+		let floating = engine.state.nodes.get(&engine.app.floating.unwrap()).unwrap();
+		assert!(!floating.physics.sleeping);
+		assert!(floating.physics.velocity.z > 0.5, "{:?}", floating.physics.velocity);
+	}
+
+	#[test]
+	fn fields_push_with_their_pose_of_the_current_step() {
+		#[derive(Default)]
+		struct TestApp {
+			pub field: Option<ArenaId<Node>>,
+			pub pushed: Option<ArenaId<Node>>,
+			pub velocities: Vec<f32>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				let field = field_node(state, scene_id, Vec3::new(100.0, 0.0, 0.0));
+				state.force_fields.insert(ForceField::new(field, CollisionShape::Box { size: Vec3::splat(1.0) }, ForceFieldKind::Wind {
+					velocity: Vec3::new(0.0, 10.0, 0.0),
+					drag: 2.0,
+					turbulence: 0.0,
+				}));
+				self.field = Some(field);
+				self.pushed = Some(field_test_box(state, scene_id, Vec3::ZERO, 1.0, 0.0));
+			}
+
+			fn on_phycis_update(&mut self, state: &mut crate::State, _dt: f32) {
+				self.velocities.push(state.nodes.get(&self.pushed.unwrap()).unwrap().physics.velocity.y);
+				// The field sweeps over the box for a single step
+				let x = match state.physics_step {
+					3 => 0.0,
+					_ => 100.0,
+				};
+				state.nodes.get_mut(&self.field.unwrap()).unwrap().translation.x = x;
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		engine.render(1.0 / 60.0);
+		engine.render(6.5 / 60.0);
+		let velocities = &engine.app.velocities;
+		assert_eq!(velocities[3], 0.0);
+		assert!(velocities[4] > 0.0, "Wind should push the box in the step it covers it {:?}", velocities);
+	}
+
+	#[test]
+	fn explosion_wind_and_attractor_push_bodies_inside_them() {
+		#[derive(Default)]
+		struct TestApp {
+			pub blasted: Vec<ArenaId<Node>>,
+			pub outside: Option<ArenaId<Node>>,
+			pub blown: Option<ArenaId<Node>>,
+			pub pulled: Option<ArenaId<Node>>,
+			pub explosion: Option<ArenaId<ForceField>>,
+		}
+
+		impl App for TestApp {
+			fn on_create(&mut self, state: &mut crate::State) {
+				let scene_id = state.scenes.insert(Scene::new());
+				let blast = field_node(state, scene_id, Vec3::ZERO);
+				self.explosion = Some(state.force_fields.insert(
+					ForceField::new(blast, CollisionShape::Sphere { radius: 3.0 }, ForceFieldKind::Explosion { impulse: 12.0 })
+						.falloff(Falloff::Linear { radius: 3.0 })
+				));
+				self.blasted = vec![
+					field_test_box(state, scene_id, Vec3::new(-1.0, 0.0, 0.0), 2.0, 0.0),
+					field_test_box(state, scene_id, Vec3::new(0.0, 0.0, 2.0), 2.0, 0.0),
+				];
+				self.outside = Some(field_test_box(state, scene_id, Vec3::new(5.0, 0.0, 0.0), 2.0, 0.0));
+
+				let wind = field_node(state, scene_id, Vec3::new(30.0, 0.0, 0.0));
+				state.force_fields.insert(ForceField::new(wind, CollisionShape::Box { size: Vec3::splat(10.0) }, ForceFieldKind::Wind {
+					velocity: Vec3::new(10.0, 0.0, 0.0),
+					drag: 2.0,
+					turbulence: 0.2,
+				}));
+				self.blown = Some(field_test_box(state, scene_id, Vec3::new(25.0, 0.0, 0.0), 1.0, 0.0));
+
+				let attractor = field_node(state, scene_id, Vec3::new(60.0, 0.0, 0.0));
+				state.force_fields.insert(ForceField::new(attractor, CollisionShape::Sphere { radius: 10.0 }, ForceFieldKind::Attractor { strength: 4.0 }));
+				self.pulled = Some(field_test_box(state, scene_id, Vec3::new(60.0, 0.0, 6.0), 3.0, 0.0));
+			}
+		}
+
+		let mut engine = Engine::new(TestApp::default(), MockHardware::new());
+		for _ in 0..30 {
+			engine.render(1.0 / 60.0);
+		}
+		let velocity = |engine: &Engine<TestApp, MockHardware>, node_id: ArenaId<Node>| engine.state.nodes.get(&node_id).unwrap().physics.velocity;
+
+		// Two thirds of 12 N s at 1 m and a third of it at 2 m, on 2 kg
+		assert!(velocity(&engine, engine.app.blasted[0]).abs_diff_eq(Vec3::new(-4.0, 0.0, 0.0), 1e-3), "{:?}", velocity(&engine, engine.app.blasted[0]));
+		assert!(velocity(&engine, engine.app.blasted[1]).abs_diff_eq(Vec3::new(0.0, 0.0, 2.0), 1e-3), "{:?}", velocity(&engine, engine.app.blasted[1]));
+		assert_eq!(velocity(&engine, engine.app.outside.unwrap()), Vec3::ZERO);
+		assert!(engine.state.force_fields.get(&engine.app.explosion.unwrap()).is_none(), "Explosion should go off once");
+
+		let blown = velocity(&engine, engine.app.blown.unwrap());
+		assert!(blown.x > 4.0 && blown.x < 10.0, "{:?}", blown);
+		let pulled = velocity(&engine, engine.app.pulled.unwrap());
+		assert!(pulled.z < -1.5 && pulled.x.abs() < 1e-3, "Attractor should pull the box in {:?}", pulled);
+	}
 }
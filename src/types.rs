@@ -1,8 +1,11 @@
 use std::collections::HashMap;
 use std::path::Path;
+use std::time::Duration;
 use glam::Mat3;
 use glam::Quat;
 use glam::Vec3;
+use serde::Deserialize;
+use serde::Serialize;
 use crate::arena::Arena;
 use crate::arena::ArenaId;
 use crate::gltf::load_gltf;
@@ -107,8 +110,40 @@ pub enum InputEvent {
 	KeyboardEvent(KeyboardEvent) ,
 }
 
+/// Contact events emitted by the physics system after each step.
+///
+/// `normal` points from `node2` towards `node1` and `impulse` is the magnitude
+/// of the normal impulse applied while resolving the contact.
+#[derive(Debug, Clone)]
 pub enum PhycicsEvent {
-	Collision { id: usize }
+	ContactBegin {
+		node1: ArenaId<Node>,
+		node2: ArenaId<Node>,
+		point: glam::Vec3,
+		normal: glam::Vec3,
+		impulse: f32,
+	},
+	ContactPersist {
+		node1: ArenaId<Node>,
+		node2: ArenaId<Node>,
+		point: glam::Vec3,
+		normal: glam::Vec3,
+		impulse: f32,
+	},
+	ContactEnd {
+		node1: ArenaId<Node>,
+		node2: ArenaId<Node>,
+	},
+	/// `node` started overlapping the sensor node `sensor`.
+	TriggerEnter {
+		sensor: ArenaId<Node>,
+		node: ArenaId<Node>,
+	},
+	/// `node` stopped overlapping the sensor node `sensor`.
+	TriggerExit {
+		sensor: ArenaId<Node>,
+		node: ArenaId<Node>,
+	},
 }
 
 #[derive(Debug, Clone)]
@@ -122,10 +157,14 @@ pub enum PhysicsType {
 	Dynamic,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PhycisObjectType {
 	Static,
 	Dynamic,
+	/// Moved by the user or by animation. Physics does not move it but derives
+	/// its velocity from how its transform changes, so it pushes dynamic
+	/// bodies and carries bodies resting on it.
+	Kinematic,
 	None
 }
 
@@ -135,7 +174,8 @@ impl Default for PhycisObjectType {
 	}
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PhysicsProps {
 	pub typ: PhycisObjectType,
 	pub velocity: glam::Vec3,
@@ -146,13 +186,150 @@ pub struct PhysicsProps {
 	pub angular_velocity: glam::Vec3,
     pub angular_acceleration: glam::Vec3,
 	pub torque: glam::Vec3,
+	/// Sensors detect overlaps and emit trigger events but are never pushed
+	/// and never push other bodies.
+	pub sensor: bool,
+	/// Bits of the collision groups this body belongs to.
+	pub collision_group: u32,
+	/// Bits of the collision groups this body collides with.
+	pub collision_mask: u32,
+	/// Nodes this body never collides with, regardless of groups.
+	#[serde(skip)]
+	pub ignored_nodes: Vec<ArenaId<Node>>,
+	/// Whether the body may be put to sleep when it comes to rest.
+	pub can_sleep: bool,
+	/// Sleeping bodies are not integrated until woken by a contact or force.
+	pub sleeping: bool,
+	/// Multiplier of the scene gravity for this body. Zero makes it float.
+	pub gravity_scale: f32,
+	/// Continuous collision detection. The shape is swept along the motion of
+	/// each step so fast bodies like bullets don't pass through thin walls.
+	pub ccd: bool,
+	/// Force added with `Node::apply_force` for the next step. Unlike `force`
+	/// it is cleared after every step.
+	pub applied_force: glam::Vec3,
+	/// Torque added with `Node::apply_torque` or by off-center forces for the
+	/// next step. Cleared after every step.
+	pub applied_torque: glam::Vec3,
+}
+
+impl Default for PhysicsProps {
+	fn default() -> Self {
+		Self {
+			typ: PhycisObjectType::default(),
+			velocity: glam::Vec3::ZERO,
+			acceleration: glam::Vec3::ZERO,
+			mass: 0.0,
+			stationary: false,
+			force: Vec3::ZERO,
+			angular_velocity: glam::Vec3::ZERO,
+			angular_acceleration: glam::Vec3::ZERO,
+			torque: glam::Vec3::ZERO,
+			sensor: false,
+			collision_group: 1,
+			collision_mask: u32::MAX,
+			ignored_nodes: vec![],
+			can_sleep: true,
+			sleeping: false,
+			gravity_scale: 1.0,
+			ccd: false,
+			applied_force: glam::Vec3::ZERO,
+			applied_torque: glam::Vec3::ZERO,
+		}
+	}
+}
+
+impl PhysicsProps {
+	pub fn wake_up(&mut self) {
+		self.sleeping = false;
+	}
+
+	/// Dynamic bodies that are not sleeping.
+	pub fn is_awake(&self) -> bool {
+		self.typ == PhycisObjectType::Dynamic && !self.sleeping
+	}
+
+	/// Bodies that may move this step: awake dynamic bodies and moving kinematic ones.
+	pub fn is_active(&self) -> bool {
+		match self.typ {
+			PhycisObjectType::Kinematic => self.velocity != glam::Vec3::ZERO || self.angular_velocity != glam::Vec3::ZERO,
+			_ => self.is_awake(),
+		}
+	}
+
+	/// Inverse mass used when resolving contacts. Only dynamic bodies can be pushed.
+	pub fn inv_mass(&self) -> f32 {
+		if self.typ == PhycisObjectType::Dynamic && self.mass > 0.0 {
+			1.0 / self.mass
+		} else {
+			0.0
+		}
+	}
+
+	/// Returns true if group and mask bits of both bodies allow a collision.
+	pub fn collides_with(&self, other: &PhysicsProps) -> bool {
+		(self.collision_group & other.collision_mask) != 0 &&
+		(other.collision_group & self.collision_mask) != 0
+	}
+}
+
+/// Decides which nodes a ray or shape query reports.
+#[derive(Debug, Clone)]
+pub struct QueryFilter {
+	/// Only nodes whose collision group intersects this mask are reported.
+	pub mask: u32,
+	/// Skip sensor nodes, e.g. for line of sight checks.
+	pub exclude_sensors: bool,
+	/// Nodes that are never reported.
+	pub exclude: Vec<ArenaId<Node>>,
+}
+
+impl Default for QueryFilter {
+	fn default() -> Self {
+		Self {
+			mask: u32::MAX,
+			exclude_sensors: false,
+			exclude: vec![],
+		}
+	}
+}
+
+impl QueryFilter {
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	pub fn accepts(&self, node_id: ArenaId<Node>, node: &Node) -> bool {
+		if self.exclude_sensors && node.physics.sensor {
+			return false;
+		}
+		if node.physics.collision_group & self.mask == 0 {
+			return false;
+		}
+		!self.exclude.contains(&node_id)
+	}
+}
+
+/// Hit reported by a ray or shape query.
+#[derive(Debug, Clone)]
+pub struct RayHit {
+	pub node_id: ArenaId<Node>,
+	/// Point on the hit node's surface.
+	pub point: glam::Vec3,
+	/// Surface normal of the hit node at `point`.
+	pub normal: glam::Vec3,
+	/// Distance travelled along the query direction.
+	pub distance: f32,
 }
 
 #[derive(Debug, Clone)]
 pub struct RayCast {
 	pub node_id: ArenaId<Node>,
 	pub len: f32,
+	pub filter: QueryFilter,
 	pub intersects: Vec<ArenaId<Node>>,
+	/// Hits along the ray sorted by distance.
+	pub hits: Vec<RayHit>,
 }
 
 impl RayCast {
@@ -160,11 +337,575 @@ impl RayCast {
 		Self {
 			node_id: node_inx,
 			len,
-			intersects: vec![]
+			filter: QueryFilter::default(),
+			intersects: vec![],
+			hits: vec![],
+		}
+	}
+}
+
+/// Drives a hinge towards a target angular velocity.
+#[derive(Debug, Clone)]
+pub struct JointMotor {
+	/// Target angular velocity around the hinge axis in radians per second.
+	pub target_velocity: f32,
+	/// Maximum torque the motor can apply.
+	pub max_torque: f32,
+}
+
+#[derive(Debug, Clone)]
+pub enum JointKind {
+	/// Locks relative position and rotation.
+	Fixed,
+	/// Rotation around `Joint::axis` only, optionally limited (radians) and motorized.
+	Hinge {
+		limits: Option<(f32, f32)>,
+		motor: Option<JointMotor>,
+	},
+	/// Anchors are kept together, rotation is free.
+	BallSocket,
+	/// Translation along `Joint::axis` only, optionally limited.
+	Slider {
+		limits: Option<(f32, f32)>,
+	},
+	/// Keeps anchors `rest_length` apart. With zero stiffness the distance is
+	/// rigid, otherwise the joint acts as a damped spring.
+	Distance {
+		rest_length: f32,
+		stiffness: f32,
+		damping: f32,
+	},
+}
+
+/// Constraint between two nodes solved by the physics system.
+#[derive(Debug, Clone)]
+pub struct Joint {
+	pub kind: JointKind,
+	pub node1: ArenaId<Node>,
+	pub node2: ArenaId<Node>,
+	/// Anchor point in node1's local space.
+	pub anchor1: glam::Vec3,
+	/// Anchor point in node2's local space.
+	pub anchor2: glam::Vec3,
+	/// Hinge or slider axis in node1's local space.
+	pub axis: glam::Vec3,
+	/// Rotation of node2 relative to node1 when the joint was first solved.
+	pub rest_rotation: Option<glam::Quat>,
+	/// Whether the two connected nodes still collide with each other.
+	pub collide_connected: bool,
+}
+
+impl Joint {
+	pub fn new(kind: JointKind, node1: ArenaId<Node>, node2: ArenaId<Node>) -> Self {
+		Self {
+			kind,
+			node1,
+			node2,
+			anchor1: glam::Vec3::ZERO,
+			anchor2: glam::Vec3::ZERO,
+			axis: glam::Vec3::Y,
+			rest_rotation: None,
+			collide_connected: false,
+		}
+	}
+
+	pub fn anchors(mut self, anchor1: glam::Vec3, anchor2: glam::Vec3) -> Self {
+		self.anchor1 = anchor1;
+		self.anchor2 = anchor2;
+		self
+	}
+
+	pub fn axis(mut self, axis: glam::Vec3) -> Self {
+		self.axis = axis.normalize_or_zero();
+		self
+	}
+}
+
+/// Moves a node through the world by a desired velocity instead of by forces.
+/// The node is pushed out of static geometry, slides along walls, steps over
+/// small ledges and sticks to the ground when walking down slopes. The node
+/// should be kinematic so that dynamic bodies are pushed away by it with the
+/// velocity of its moves. Its shape is used as a capsule; box shapes are
+/// treated as the capsule that fits inside.
+#[derive(Debug, Clone)]
+pub struct CharacterController {
+	pub node_id: ArenaId<Node>,
+	/// Velocity the character tries to move with.
+	pub velocity: glam::Vec3,
+	/// Highest ledge the character climbs without jumping.
+	pub step_height: f32,
+	/// Steepest walkable slope in radians.
+	pub max_slope: f32,
+	/// How far down the character is pulled to stay on the ground.
+	pub snap_distance: f32,
+	/// Contacts closer than this still count as touching.
+	pub skin_width: f32,
+	/// Whether the character stood on walkable ground after the last move.
+	pub grounded: bool,
+	/// Normal of the ground the character stands on.
+	pub ground_normal: glam::Vec3,
+	/// Velocity the character actually moved with in the last step.
+	pub actual_velocity: glam::Vec3,
+}
+
+impl CharacterController {
+	pub fn new(node_id: ArenaId<Node>) -> Self {
+		Self {
+			node_id,
+			velocity: glam::Vec3::ZERO,
+			step_height: 0.3,
+			max_slope: 45.0_f32.to_radians(),
+			snap_distance: 0.3,
+			skin_width: 0.02,
+			grounded: false,
+			ground_normal: glam::Vec3::Y,
+			actual_velocity: glam::Vec3::ZERO,
+		}
+	}
+}
+
+/// Grip of a tire as a function of its slip. Grip rises linearly up to the
+/// extremum and falls off towards the asymptote once the tire slides.
+#[derive(Debug, Clone)]
+pub struct FrictionCurve {
+	pub extremum_slip: f32,
+	pub extremum_value: f32,
+	pub asymptote_slip: f32,
+	pub asymptote_value: f32,
+}
+
+impl FrictionCurve {
+	/// Friction coefficient at `slip`, the sign of `slip` is ignored.
+	pub fn evaluate(&self, slip: f32) -> f32 {
+		let slip = slip.abs();
+		if slip <= self.extremum_slip {
+			slip / self.extremum_slip * self.extremum_value
+		} else if slip < self.asymptote_slip {
+			let t = (slip - self.extremum_slip) / (self.asymptote_slip - self.extremum_slip);
+			self.extremum_value + (self.asymptote_value - self.extremum_value) * t
+		} else {
+			self.asymptote_value
+		}
+	}
+}
+
+/// Ground a wheel touched in the last physics step.
+#[derive(Debug, Clone)]
+pub struct WheelContact {
+	pub node_id: ArenaId<Node>,
+	pub point: glam::Vec3,
+	pub normal: glam::Vec3,
+	/// Tire force along the rolling direction.
+	pub longitudinal_force: f32,
+	/// Tire force along the axle.
+	pub lateral_force: f32,
+	/// Difference between the tire surface speed and the ground speed,
+	/// relative to the ground speed. Positive when spinning, -1 when locked.
+	pub slip_ratio: f32,
+	/// Angle between the rolling direction and the direction of travel in radians.
+	pub slip_angle: f32,
+}
+
+/// Wheel of a `Vehicle`. The suspension is a ray cast from `position` along
+/// `direction` and the wheel rolls around `axle`, all in the chassis' local space.
+#[derive(Debug, Clone)]
+pub struct Wheel {
+	/// Point the suspension is attached to.
+	pub position: glam::Vec3,
+	/// Direction the suspension extends in.
+	pub direction: glam::Vec3,
+	pub axle: glam::Vec3,
+	pub radius: f32,
+	/// Suspension length without load.
+	pub rest_length: f32,
+	/// Spring force per meter of compression.
+	pub stiffness: f32,
+	/// Damper force per meter per second of compression.
+	pub damping: f32,
+	/// Whether the engine drives the wheel.
+	pub powered: bool,
+	/// Whether the wheel turns with the steering.
+	pub steered: bool,
+	pub longitudinal_friction: FrictionCurve,
+	pub lateral_friction: FrictionCurve,
+	/// Current steering angle in radians.
+	pub steer_angle: f32,
+	/// Current suspension length.
+	pub suspension_length: f32,
+	/// Force the suspension pushed the chassis with in the last step.
+	pub suspension_force: f32,
+	/// Spin in radians per second, positive when rolling forward.
+	pub angular_velocity: f32,
+	/// Angle the wheel has rolled by, for drawing it.
+	pub rotation: f32,
+	pub contact: Option<WheelContact>,
+}
+
+impl Wheel {
+	pub fn new(position: glam::Vec3, radius: f32) -> Self {
+		Self {
+			position,
+			direction: glam::Vec3::NEG_Y,
+			axle: glam::Vec3::X,
+			radius,
+			rest_length: 0.4,
+			stiffness: 35000.0,
+			damping: 4000.0,
+			powered: false,
+			steered: false,
+			longitudinal_friction: FrictionCurve {
+				extremum_slip: 0.2,
+				extremum_value: 1.0,
+				asymptote_slip: 0.8,
+				asymptote_value: 0.7,
+			},
+			lateral_friction: FrictionCurve {
+				extremum_slip: 0.2,
+				extremum_value: 1.0,
+				asymptote_slip: 0.6,
+				asymptote_value: 0.75,
+			},
+			steer_angle: 0.0,
+			suspension_length: 0.4,
+			suspension_force: 0.0,
+			angular_velocity: 0.0,
+			rotation: 0.0,
+			contact: None,
+		}
+	}
+
+	pub fn suspension(mut self, rest_length: f32, stiffness: f32, damping: f32) -> Self {
+		self.rest_length = rest_length;
+		self.suspension_length = rest_length;
+		self.stiffness = stiffness;
+		self.damping = damping;
+		self
+	}
+
+	pub fn powered(mut self) -> Self {
+		self.powered = true;
+		self
+	}
+
+	pub fn steered(mut self) -> Self {
+		self.steered = true;
+		self
+	}
+
+	/// Direction the suspension is compressed in, in the chassis' local space.
+	pub fn up(&self) -> glam::Vec3 {
+		-self.direction.normalize_or_zero()
+	}
+
+	/// Direction the wheel rolls in with positive spin, in the chassis' local
+	/// space. With the default axes that is negative z.
+	pub fn forward(&self) -> glam::Vec3 {
+		glam::Quat::from_axis_angle(self.up(), self.steer_angle) * self.up().cross(self.axle).normalize_or_zero()
+	}
+}
+
+/// Car driven by ray cast wheels. Each step the wheels push the chassis up
+/// with their suspension and along the ground with their tires. The chassis
+/// should be a dynamic node whose own shape stays clear of the ground.
+#[derive(Debug, Clone)]
+pub struct Vehicle {
+	pub chassis: ArenaId<Node>,
+	pub wheels: Vec<Wheel>,
+	/// From -1 for full reverse to 1 for full throttle.
+	pub throttle: f32,
+	/// From 0 to 1.
+	pub brake: f32,
+	/// From -1 for full right to 1 for full left.
+	pub steering: f32,
+	/// Torque at full throttle, split evenly between the powered wheels.
+	pub engine_torque: f32,
+	/// Torque of each wheel's brake at full brake.
+	pub brake_torque: f32,
+	/// Steering angle at full lock in radians.
+	pub max_steer_angle: f32,
+	/// How much tire forces roll and pitch the chassis. At 1 they push at the
+	/// contact point, at 0 at the height of the center of mass.
+	pub roll_influence: f32,
+	/// Nodes the wheels drive on. The chassis is always ignored.
+	pub filter: QueryFilter,
+}
+
+impl Vehicle {
+	pub fn new(chassis: ArenaId<Node>) -> Self {
+		Self {
+			chassis,
+			wheels: vec![],
+			throttle: 0.0,
+			brake: 0.0,
+			steering: 0.0,
+			engine_torque: 1500.0,
+			brake_torque: 3000.0,
+			max_steer_angle: 35.0_f32.to_radians(),
+			roll_influence: 0.1,
+			filter: QueryFilter {
+				exclude_sensors: true,
+				..Default::default()
+			},
+		}
+	}
+
+	pub fn wheel(mut self, wheel: Wheel) -> Self {
+		self.wheels.push(wheel);
+		self
+	}
+
+	/// World transform of a wheel's center, for drawing it. `chassis` is the
+	/// node the vehicle drives, placed by its `global_transform`.
+	pub fn wheel_transform(&self, chassis: &Node, wheel: usize) -> glam::Mat4 {
+		let wheel = &self.wheels[wheel];
+		let center = wheel.position + wheel.direction.normalize_or_zero() * wheel.suspension_length;
+		let rotation = glam::Quat::from_axis_angle(wheel.up(), wheel.steer_angle)
+			* glam::Quat::from_axis_angle(wheel.axle.normalize_or_zero(), -wheel.rotation);
+		chassis.global_transform * glam::Mat4::from_rotation_translation(rotation, center)
+	}
+}
+
+/// Cloth vertex that moves with a node instead of being simulated.
+#[derive(Debug, Clone)]
+pub struct ClothPin {
+	pub vertex: usize,
+	pub node_id: ArenaId<Node>,
+	/// Position of the vertex in the node's local space. Taken from where the
+	/// vertex starts when `None`.
+	pub offset: Option<glam::Vec3>,
+}
+
+/// Cloth or rope simulated on the vertices of a mesh primitive. Triangles
+/// become cloth and lines become ropes. Edges keep their rest length, the
+/// cloth resists folding, collides with the box, sphere and capsule colliders
+/// of its scene, and the moved vertices and normals are written back into the
+/// primitive every physics step. Colliders are not pushed back by the cloth.
+#[derive(Debug, Clone)]
+pub struct Cloth {
+	/// Node drawing the mesh. Its transform places the cloth in the scene.
+	pub node_id: ArenaId<Node>,
+	/// Mesh the simulated vertices are written into. When other nodes or
+	/// cloths use it too, like the instances of a prefab, the cloth copies it
+	/// on its first step and points this and the node's `mesh` at the copy.
+	pub mesh_id: ArenaId<Mesh>,
+	/// Index of the simulated primitive in the mesh.
+	pub primitive: usize,
+	pub pins: Vec<ClothPin>,
+	/// How strongly edges keep their rest length, from 0 to 1.
+	pub stiffness: f32,
+	/// How strongly the cloth resists folding, from 0 to 1.
+	pub bend_stiffness: f32,
+	/// Fraction of the velocity lost every step.
+	pub damping: f32,
+	/// Constraint solver iterations per step.
+	pub iterations: usize,
+	/// Distance kept between the cloth and colliders.
+	pub thickness: f32,
+	/// Fraction of the sliding velocity lost when touching a collider.
+	pub friction: f32,
+	/// Velocity of the air. Triangles facing it are pushed along their normal.
+	pub wind: glam::Vec3,
+	/// Acceleration per meter per second of air blowing against a triangle.
+	pub drag: f32,
+	/// Colliders the cloth collides with.
+	pub filter: QueryFilter,
+	/// Simulated positions in world space. Vertices at the same position,
+	/// like the seams of a textured mesh, share one particle.
+	pub particles: Vec<glam::Vec3>,
+	pub(crate) previous: Vec<glam::Vec3>,
+	/// Particle of every vertex of the primitive.
+	pub(crate) vertex_particles: Vec<usize>,
+	pub(crate) edges: Vec<(usize, usize, f32)>,
+	pub(crate) bends: Vec<(usize, usize, f32)>,
+}
+
+impl Cloth {
+	pub fn new(node_id: ArenaId<Node>, mesh_id: ArenaId<Mesh>) -> Self {
+		Self {
+			node_id,
+			mesh_id,
+			primitive: 0,
+			pins: vec![],
+			stiffness: 1.0,
+			bend_stiffness: 0.1,
+			damping: 0.01,
+			iterations: 8,
+			thickness: 0.02,
+			friction: 0.5,
+			wind: glam::Vec3::ZERO,
+			drag: 1.0,
+			filter: QueryFilter {
+				exclude_sensors: true,
+				..Default::default()
+			},
+			particles: vec![],
+			previous: vec![],
+			vertex_particles: vec![],
+			edges: vec![],
+			bends: vec![],
+		}
+	}
+
+	pub fn pin(mut self, vertex: usize, node_id: ArenaId<Node>) -> Self {
+		self.pins.push(ClothPin {
+			vertex,
+			node_id,
+			offset: None,
+		});
+		self
+	}
+}
+
+/// How the strength of a force field fades with the distance from its node.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Falloff {
+	/// Full strength everywhere inside the volume.
+	Constant,
+	/// Fades from full strength at the node to nothing at `radius`.
+	Linear { radius: f32 },
+	/// Full strength within `radius`, dropping with the square of the distance beyond it.
+	InverseSquare { radius: f32 },
+}
+
+impl Falloff {
+	/// Fraction of the full strength at `distance` from the node.
+	pub fn factor(&self, distance: f32) -> f32 {
+		match self {
+			Falloff::Constant => 1.0,
+			Falloff::Linear { radius } => (1.0 - distance / radius).max(0.0),
+			Falloff::InverseSquare { radius } => match distance > *radius {
+				true => (radius / distance).powi(2),
+				false => 1.0,
+			},
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub enum ForceFieldKind {
+	/// Pulls bodies towards the wind velocity with a force of `drag` per
+	/// m/s of difference. `velocity` is in the node's local space and
+	/// `turbulence` adds gusts as a fraction of the wind speed.
+	Wind {
+		velocity: glam::Vec3,
+		drag: f32,
+		turbulence: f32,
+	},
+	/// Pushes bodies away from the node with an impulse of `impulse`, once on
+	/// the next step. The field is removed afterwards.
+	Explosion { impulse: f32 },
+	/// Water below a plane `surface` above the node. Bodies are lifted by the
+	/// weight of the water they displace, which weighs `density` per cubic
+	/// meter, and slowed by `drag` and `angular_drag` per m/s and rad/s while
+	/// submerged.
+	Buoyancy {
+		surface: f32,
+		density: f32,
+		drag: f32,
+		angular_drag: f32,
+	},
+	/// Accelerates bodies towards the node, or away with a negative strength.
+	Attractor { strength: f32 },
+}
+
+/// Volume that pushes the dynamic bodies overlapping it every physics step.
+/// The volume is `shape` placed at the node.
+#[derive(Debug, Clone)]
+pub struct ForceField {
+	pub node_id: ArenaId<Node>,
+	pub shape: CollisionShape,
+	pub kind: ForceFieldKind,
+	pub falloff: Falloff,
+	/// Bodies the field acts on.
+	pub filter: QueryFilter,
+}
+
+impl ForceField {
+	pub fn new(node_id: ArenaId<Node>, shape: CollisionShape, kind: ForceFieldKind) -> Self {
+		Self {
+			node_id,
+			shape,
+			kind,
+			falloff: Falloff::Constant,
+			filter: QueryFilter {
+				exclude_sensors: true,
+				..Default::default()
+			},
+		}
+	}
+
+	pub fn falloff(mut self, falloff: Falloff) -> Self {
+		self.falloff = falloff;
+		self
+	}
+}
+
+/// Global settings of the physics simulation.
+#[derive(Debug, Clone)]
+pub struct PhysicsSettings {
+	/// Length of one physics step in seconds. Physics always advances in
+	/// steps of this size regardless of the frame rate.
+	pub fixed_dt: f32,
+	/// Maximum number of physics steps per rendered frame. Time beyond that
+	/// is dropped so a slow frame does not snowball into slower frames.
+	pub max_substeps: u32,
+	/// Interpolate rendered transforms between the last two physics steps.
+	pub interpolate: bool,
+	/// Resolve contacts and report events in an order that doesn't depend on
+	/// hash maps, so the same inputs give bit-identical results. Inputs and
+	/// kinematic motion have to be applied in `on_phycis_update` for that,
+	/// since frames don't line up with steps.
+	pub deterministic: bool,
+	/// Threads used to step scenes, and islands within a scene, in
+	/// parallel. 1 runs everything on the calling thread. The results are
+	/// the same for any count.
+	pub threads: usize,
+}
+
+impl Default for PhysicsSettings {
+	fn default() -> Self {
+		Self {
+			fixed_dt: 1.0 / 60.0,
+			max_substeps: 8,
+			interpolate: true,
+			deterministic: false,
+			threads: 1,
 		}
 	}
 }
 
+/// What the last physics step of a scene did and how long each phase took.
+/// Counts are summed over the passes of the step, so a pair found again
+/// after a time of impact sub-step is counted twice.
+#[derive(Debug, Clone, Default)]
+pub struct PhysicsStats {
+	/// Pairs of bodies whose rects overlap in the broad phase.
+	pub broad_phase_pairs: usize,
+	/// Pairs whose shapes actually touch.
+	pub contacts: usize,
+	/// Times the contact solver ran over the islands, one for the step plus
+	/// one per time of impact sub-step.
+	pub solver_passes: usize,
+	/// Times the step was split at the time of impact of a fast body.
+	pub toi_substeps: usize,
+	/// Dynamic bodies asleep at the end of the step.
+	pub sleeping_bodies: usize,
+	/// Dynamic bodies awake at the end of the step.
+	pub awake_bodies: usize,
+	/// Refreshing rects and collecting the overlapping pairs.
+	pub broad_phase_time: Duration,
+	/// Testing the shapes of the pairs and sweeping continuous bodies.
+	pub narrow_phase_time: Duration,
+	/// Resolving contacts and solving joints.
+	pub solver_time: Duration,
+	/// Moving the bodies.
+	pub integration_time: Duration,
+	/// Waking and putting bodies to sleep.
+	pub sleeping_time: Duration,
+	/// The whole step, events included.
+	pub total_time: Duration,
+}
+
 pub struct SphereCast {
 	pub origin: glam::Vec3,
 	pub radius: f32,
@@ -189,6 +930,14 @@ impl AABB {
 		}
 	}
 
+	/// Smallest box that contains both boxes.
+	pub fn merge(&self, other: &AABB) -> AABB {
+		AABB {
+			min: self.min.min(other.min),
+			max: self.max.max(other.max),
+		}
+	}
+
     pub fn contains(&self, point: glam::Vec3) -> bool {
         point.x >= self.min.x && point.x <= self.max.x &&
         point.y >= self.min.y && point.y <= self.max.y &&
@@ -263,6 +1012,30 @@ impl AABB {
 		}
     }
 
+	/// Distances along the ray at which it enters and leaves the box, limited
+	/// to `0..=max_dist`. `dir` has to be normalized.
+	pub fn ray_range(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Option<(f32, f32)> {
+		let mut enter = 0.0f32;
+		let mut exit = max_dist;
+		for axis in 0..3 {
+			if dir[axis] == 0.0 {
+				if origin[axis] < self.min[axis] || origin[axis] > self.max[axis] {
+					return None;
+				}
+				continue;
+			}
+			let t1 = (self.min[axis] - origin[axis]) / dir[axis];
+			let t2 = (self.max[axis] - origin[axis]) / dir[axis];
+			enter = enter.max(t1.min(t2));
+			exit = exit.min(t1.max(t2));
+		}
+		if enter <= exit {
+			Some((enter, exit))
+		} else {
+			None
+		}
+	}
+
 	pub fn intersect_sphere(&self, sphere: &SphereCast) -> bool {
         let mut closest_point = Vec3::ZERO;
 
@@ -283,31 +1056,153 @@ impl AABB {
 	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CollisionShape {
 	Box { size: glam::Vec3 },
+	/// Upright capsule. `half_height` is half the length of the straight part.
+	Capsule { radius: f32, half_height: f32 },
+	Sphere { radius: f32 },
+	/// Several shapes placed relative to the node, e.g. the legs and top of a
+	/// table. The mass of the body is spread over the parts by their volume.
+	Compound { parts: Vec<Collider> },
 }
 
 impl CollisionShape {
     pub fn aabb(&self, translation: glam::Vec3) -> AABB {
-        match self {
-            Self::Box { size } => AABB {
-                min: translation - *size,
-                max: translation + *size,
-            }
-        }
+		if let Self::Compound { .. } = self {
+			return self.bounds(translation, glam::Quat::IDENTITY);
+		}
+		let half = self.half_extents();
+		AABB {
+			min: translation - half,
+			max: translation + half,
+		}
     }
 
+	/// Boxes around each part of the shape for a node at `translation` and
+	/// `rotation`. Single shapes keep their unrotated box, compound parts
+	/// follow the rotation of the node.
+	pub fn part_aabbs(&self, translation: glam::Vec3, rotation: glam::Quat) -> Vec<AABB> {
+		match self {
+			Self::Compound { .. } => self.parts()
+				.iter()
+				.map(|part| {
+					let center = translation + rotation * part.offset;
+					let axes = glam::Mat3::from_quat(rotation * part.rotation);
+					let half = glam::Mat3::from_cols(axes.x_axis.abs(), axes.y_axis.abs(), axes.z_axis.abs()) * part.shape.half_extents();
+					AABB::new(center - half, center + half)
+				})
+				.collect(),
+			_ => vec![self.aabb(translation)],
+		}
+	}
+
+	/// Box around all parts of the shape, see `part_aabbs`.
+	pub fn bounds(&self, translation: glam::Vec3, rotation: glam::Quat) -> AABB {
+		self.part_aabbs(translation, rotation)
+			.into_iter()
+			.reduce(|a, b| a.merge(&b))
+			.unwrap_or(AABB::new(translation, translation))
+	}
+
+	/// Primitive shapes this shape is made of, placed relative to the node.
+	/// Nested compound shapes are flattened.
+	pub fn parts(&self) -> Vec<Collider> {
+		match self {
+			Self::Compound { parts } => parts
+				.iter()
+				.flat_map(|part| part.shape.parts().into_iter().map(move |inner| Collider {
+					shape: inner.shape,
+					offset: part.offset + part.rotation * inner.offset,
+					rotation: part.rotation * inner.rotation,
+				}))
+				.collect(),
+			_ => vec![Collider::new(self.clone(), glam::Vec3::ZERO, glam::Quat::IDENTITY)],
+		}
+	}
+
+	/// Half size of the box that bounds the shape.
+	pub fn half_extents(&self) -> glam::Vec3 {
+		match self {
+			Self::Box { size } => *size,
+			Self::Capsule { radius, half_height } => glam::Vec3::new(*radius, half_height + radius, *radius),
+			Self::Sphere { radius } => glam::Vec3::splat(*radius),
+			Self::Compound { .. } => {
+				let aabb = self.aabb(glam::Vec3::ZERO);
+				aabb.min.abs().max(aabb.max.abs())
+			}
+		}
+	}
+
+	/// Shape resized by a node scale. Round shapes take the largest axis that
+	/// affects their radius.
+	pub fn scaled(&self, scale: glam::Vec3) -> CollisionShape {
+		let scale = scale.abs();
+		match self {
+			Self::Box { size } => Self::Box { size: *size * scale },
+			Self::Capsule { radius, half_height } => Self::Capsule {
+				radius: radius * scale.x.max(scale.z),
+				half_height: half_height * scale.y,
+			},
+			Self::Sphere { radius } => Self::Sphere { radius: radius * scale.max_element() },
+			// Each part is scaled along its own axes. That is exact for parts
+			// aligned with the node axes or under uniform scale. Other parts
+			// would be sheared, they keep their rotation and get the scaled
+			// length of each axis instead.
+			Self::Compound { parts } => Self::Compound {
+				parts: parts
+					.iter()
+					.map(|part| {
+						let axes = glam::Mat3::from_quat(part.rotation);
+						let part_scale = glam::Vec3::new(
+							(scale * axes.x_axis).length(),
+							(scale * axes.y_axis).length(),
+							(scale * axes.z_axis).length(),
+						);
+						Collider {
+							shape: part.shape.scaled(part_scale),
+							offset: part.offset * scale,
+							rotation: part.rotation,
+						}
+					})
+					.collect(),
+			},
+		}
+	}
+
+	pub fn volume(&self) -> f32 {
+		match self {
+			Self::Box { size } => 8.0 * size.x * size.y * size.z,
+			Self::Capsule { radius, half_height } => {
+				std::f32::consts::PI * radius * radius * (2.0 * half_height + 4.0 / 3.0 * radius)
+			}
+			Self::Sphere { radius } => 4.0 / 3.0 * std::f32::consts::PI * radius.powi(3),
+			Self::Compound { parts } => parts.iter().map(|part| part.shape.volume()).sum(),
+		}
+	}
+
 	pub fn center_of_mass(&self) -> glam::Vec3 {
 		match self {
 			Self::Box { .. } => glam::Vec3::ZERO,
+			Self::Capsule { .. } => glam::Vec3::ZERO,
+			Self::Sphere { .. } => glam::Vec3::ZERO,
+			Self::Compound { .. } => {
+				let parts = self.parts();
+				let volume: f32 = parts.iter().map(|part| part.shape.volume()).sum();
+				if volume <= 0.0 {
+					return glam::Vec3::ZERO;
+				}
+				parts.iter().map(|part| part.offset * part.shape.volume()).sum::<glam::Vec3>() / volume
+			}
 		}
 	}
 
+	/// Inertia tensor for a unit mass, around the center of mass.
     pub fn inertia_tensor(&self) -> glam::Mat3 {
         match self {
-            Self::Box { size } => {
-                // Assuming `size` represents the half-extents of the box
+            // Capsules use their bounding box, which is close enough for upright bodies
+            Self::Box { .. } | Self::Capsule { .. } => {
+                let size = self.half_extents();
                 let width = size.x * 2.0;
                 let height = size.y * 2.0;
                 let depth = size.z * 2.0;
@@ -322,6 +1217,25 @@ impl CollisionShape {
                     glam::Vec3::new(0.0, 0.0, Izz),
                 )
             },
+            Self::Sphere { radius } => glam::Mat3::from_diagonal(glam::Vec3::splat(0.4 * radius * radius)),
+            // Parts are rotated into the node frame and moved to the common
+            // center of mass with the parallel axis theorem
+            Self::Compound { .. } => {
+                let parts = self.parts();
+                let volume: f32 = parts.iter().map(|part| part.shape.volume()).sum();
+                if volume <= 0.0 {
+                    return glam::Mat3::ZERO;
+                }
+                let center = self.center_of_mass();
+                parts.iter().fold(glam::Mat3::ZERO, |inertia, part| {
+                    let weight = part.shape.volume() / volume;
+                    let rotation = glam::Mat3::from_quat(part.rotation);
+                    let d = part.offset - center;
+                    let shift = glam::Mat3::from_diagonal(glam::Vec3::splat(d.dot(d))) -
+                        glam::Mat3::from_cols(d * d.x, d * d.y, d * d.z);
+                    inertia + (rotation * part.shape.inertia_tensor() * rotation.transpose() + shift) * weight
+                })
+            }
         }
     }
 
@@ -330,7 +1244,27 @@ impl CollisionShape {
 	}
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// Part of a compound collision shape.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Collider {
+	pub shape: CollisionShape,
+	/// Position of the part relative to the node.
+	pub offset: glam::Vec3,
+	/// Rotation of the part relative to the node.
+	pub rotation: glam::Quat,
+}
+
+impl Collider {
+	pub fn new(shape: CollisionShape, offset: glam::Vec3, rotation: glam::Quat) -> Self {
+		Self {
+			shape,
+			offset,
+			rotation,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum NodeParent {
 	Node(ArenaId<Node>),
 	Scene(ArenaId<Scene>),
@@ -350,19 +1284,28 @@ pub struct ContactInfo {
 	pub node_id: ArenaId<Node>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Node {
 	pub name: Option<String>,
+	/// The engine picks up changes on the next frame. Use `State::reparent`
+	/// for `State::children` and the other hierarchy queries to see them
+	/// right away.
+	#[serde(skip)]
 	pub parent: NodeParent,
+	#[serde(skip)]
 	pub mesh: Option<ArenaId<Mesh>>,
 	pub translation: glam::Vec3,
 	pub rotation: glam::Quat,
 	pub scale: glam::Vec3,
 	pub physics: PhysicsProps,
 	pub collision_shape: Option<CollisionShape>,
+	#[serde(skip)]
 	pub global_transform: glam::Mat4,
+	#[serde(skip)]
 	pub scene_id: Option<ArenaId<Scene>>,
 	pub lock_rotation: bool,
+	#[serde(skip)]
 	pub contacts: Vec<ContactInfo>,
 }
 
@@ -428,6 +1371,7 @@ impl Node {
 		translation * rotation * scale
 	}
 
+	/// Offset of the center of mass from the node origin, in node space.
 	pub fn center_of_mass(&self) -> glam::Vec3 {
 		match &self.collision_shape {
 			Some(shape) => shape.center_of_mass(),
@@ -435,6 +1379,12 @@ impl Node {
 		}
 	}
 
+	/// Center of mass in the same space as `translation`, which is world
+	/// space while a physics step runs.
+	pub(crate) fn step_center_of_mass(&self) -> glam::Vec3 {
+		self.translation + self.rotation * self.center_of_mass()
+	}
+
 	pub fn size(&self) -> glam::Vec3 {
 		match &self.collision_shape {
 			Some(shape) => {
@@ -447,6 +1397,9 @@ impl Node {
 
 	pub fn inertia_tensor(&self) -> glam::Mat3 {
         let mass = self.physics.mass;
+		if let Some(shape @ CollisionShape::Compound { .. }) = &self.collision_shape {
+			return shape.inertia_tensor() * mass;
+		}
 		let size = self.size();
         let width = size.x;
         let height = size.y;
@@ -465,9 +1418,82 @@ impl Node {
 			_ => glam::Mat3::ZERO
 		}*/
 	}
+
+	pub(crate) fn inv_inertia_tensor(&self) -> glam::Mat3 {
+		let inertia_tensor = self.inertia_tensor();
+		if self.lock_rotation || inertia_tensor.determinant().abs() <= 1e-6 {
+			glam::Mat3::ZERO
+		} else {
+			inertia_tensor.inverse()
+		}
+	}
+
+	/// Inverse inertia tensor for a body turned by `rotation`, R * I^-1 * R^T.
+	pub(crate) fn rotated_inv_inertia_tensor(&self, rotation: glam::Quat) -> glam::Mat3 {
+		let rotation = glam::Mat3::from_quat(rotation);
+		rotation * self.inv_inertia_tensor() * rotation.transpose()
+	}
+
+	/// Center of mass in world space, from `global_transform`.
+	pub fn world_center_of_mass(&self) -> glam::Vec3 {
+		self.global_transform.transform_point3(self.center_of_mass())
+	}
+
+	/// Applies `force` at `point` during the next physics step. Both are in
+	/// world space. Points off the center of mass also spin the body. Sleeping
+	/// bodies wake up once the applied load differs from the last step.
+	pub fn apply_force(&mut self, point: glam::Vec3, force: glam::Vec3) {
+		let r = point - self.world_center_of_mass();
+		self.apply_force_offset(r, force);
+	}
+
+	/// Applies `force` at offset `r` from the center of mass, in world space.
+	pub(crate) fn apply_force_offset(&mut self, r: glam::Vec3, force: glam::Vec3) {
+		self.physics.applied_force += force;
+		self.physics.applied_torque += r.cross(force);
+	}
+
+	/// Changes the velocity at once as if hit at `point`, see `apply_force`.
+	pub fn apply_impulse(&mut self, point: glam::Vec3, impulse: glam::Vec3) {
+		let (_, rotation, _) = self.global_transform.to_scale_rotation_translation();
+		let r = point - self.world_center_of_mass();
+		self.apply_impulse_offset(r, rotation, impulse);
+	}
+
+	/// Applies `impulse` at offset `r` from the center of mass of a body
+	/// turned by `rotation`, all in world space.
+	pub(crate) fn apply_impulse_offset(&mut self, r: glam::Vec3, rotation: glam::Quat, impulse: glam::Vec3) {
+		let inv_mass = self.physics.inv_mass();
+		if inv_mass == 0.0 {
+			return;
+		}
+		self.physics.velocity += impulse * inv_mass;
+		self.physics.angular_velocity += self.rotated_inv_inertia_tensor(rotation) * r.cross(impulse);
+		self.physics.wake_up();
+	}
+
+	/// Applies `torque` during the next physics step.
+	pub fn apply_torque(&mut self, torque: glam::Vec3) {
+		self.physics.applied_torque += torque;
+	}
+
+	/// Changes the angular velocity at once. `impulse` is in world space.
+	pub fn apply_torque_impulse(&mut self, impulse: glam::Vec3) {
+		if self.physics.inv_mass() == 0.0 {
+			return;
+		}
+		let (_, rotation, _) = self.global_transform.to_scale_rotation_translation();
+		self.physics.angular_velocity += self.rotated_inv_inertia_tensor(rotation) * impulse;
+		self.physics.wake_up();
+	}
+
+	pub fn set_velocity(&mut self, velocity: glam::Vec3) {
+		self.physics.velocity = velocity;
+		self.physics.wake_up();
+	}
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PrimitiveTopology {
 	PointList,
 	LineList,
@@ -489,13 +1515,14 @@ impl PrimitiveTopology {
 	}
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Primitive {
 	pub topology: PrimitiveTopology,
 	pub vertices: Vec<[f32; 3]>,
 	pub indices: Vec<u16>,
 	pub normals: Vec<[f32; 3]>,
 	pub tex_coords: Vec<[f32; 2]>,
+	#[serde(skip)]
 	pub material: Option<ArenaId<Material>>,
 }
 
@@ -515,7 +1542,7 @@ impl Primitive {
 #[derive(Debug, Clone)]
 pub struct MeshId;
 
-#[derive(Debug, Clone, Default, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Mesh {
 	pub name: Option<String>,
 	pub primitives: Vec<Primitive>,
@@ -539,33 +1566,66 @@ pub struct Asset {
 	ascenes: Vec<Scene>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Scene {
 	pub name: Option<String>,
 	pub scale: glam::Vec3,
+	/// Acceleration applied to every dynamic body in the scene. Defaults to
+	/// the fixed gravity every scene had before it could be changed.
 	pub gravity: glam::Vec3,
+	/// Whether the physics of the scene is simulated at all. On by default.
 	pub physics_on: bool,
+	/// How the scene finds bodies that may collide.
+	pub broad_phase: BroadPhaseType,
+	#[serde(skip)]
 	pub _3d_model: Option<ArenaId<Model3D>>,
 }
 
-impl Scene {
-	pub fn new() -> Self {
+impl Default for Scene {
+	fn default() -> Self {
 		Self {
 			name: None,
 			scale: glam::Vec3::splat(1.0),
-			gravity: glam::Vec3::ZERO,
-			physics_on: false,
+			gravity: glam::Vec3::new(0.0, -10.0, 0.0),
+			physics_on: true,
+			broad_phase: BroadPhaseType::default(),
 			_3d_model: None,
 		}
 	}
 }
 
-#[derive(Debug, Clone, Default)]
+impl Scene {
+	pub fn new() -> Self {
+		Default::default()
+	}
+}
+
+/// Structure a scene uses to find bodies that may collide and to speed up
+/// its ray and shape queries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BroadPhaseType {
+	/// Uniform grid. Works well when bodies have similar sizes, but large
+	/// bodies fill many cells and crowded cells compare every pair.
+	Grid { cell_size: f32 },
+	/// Tree of bounding boxes that adapts to bodies of any size. Queries only
+	/// test the nodes whose boxes they reach.
+	Bvh,
+}
+
+impl Default for BroadPhaseType {
+	fn default() -> Self {
+		Self::Grid { cell_size: 5.0 }
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Camera {
     pub aspect: f32,
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
+	#[serde(skip)]
 	pub node_id: Option<ArenaId<Node>>
 }
 
@@ -658,7 +1718,7 @@ impl Animation {
 	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TextureSource {
 	None,
 	File(String),
@@ -675,7 +1735,7 @@ impl Default for TextureSource {
 	}
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Texture {
     pub name: String,
     pub source: TextureSource,
@@ -692,22 +1752,28 @@ impl Texture {
 	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Material {
     pub name: Option<String>,
+	#[serde(skip)]
 	pub base_color_texture: Option<ArenaId<Texture>>,
 	pub base_color_tex_coords: Option<Vec<[f32; 2]>>,
 	pub base_color_factor: [f32; 4],
+	#[serde(skip)]
 	pub metallic_roughness_texture: Option<ArenaId<Texture>>,
 	pub metallic_roughness_tex_coords: Option<Vec<[f32; 2]>>,
 	pub metallic_factor: f32,
 	pub roughness_factor: f32,
+	#[serde(skip)]
 	pub normal_texture: Option<ArenaId<Texture>>,
 	pub normal_tex_coords: Option<Vec<[f32; 2]>>,
 	pub normal_texture_scale: f32,
+	#[serde(skip)]
 	pub occlusion_texture: Option<ArenaId<Texture>>,
 	pub occlusion_tex_coords: Option<Vec<[f32; 2]>>,
 	pub occlusion_strength: f32,
+	#[serde(skip)]
 	pub emissive_texture: Option<ArenaId<Texture>>,
 	pub emissive_tex_coords: Option<Vec<[f32; 2]>>,
 	pub emissive_factor: [f32; 3],
@@ -737,10 +1803,11 @@ impl Default for Material {
 	}
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PointLight {
 	pub color: [f32; 3],
 	pub intensity: f32,
+	#[serde(skip)]
 	pub node_id: Option<ArenaId<Node>>
 }
 
@@ -786,8 +1853,10 @@ pub trait App {
 	fn on_mouse_input(&mut self, window_id: ArenaId<Window>, event: MouseEvent, state: &mut State) {}
 	/// Run before rendering
 	fn on_process(&mut self, state: &mut State, delta: f32) {}
-	/// Run before physics properties are updated
+	/// Run before every fixed physics step
 	fn on_phycis_update(&mut self, state: &mut State, delta: f32) {}
+	/// Run for every contact event produced by the physics step
+	fn on_physics_event(&mut self, event: PhycicsEvent, state: &mut State) {}
 }
 
 
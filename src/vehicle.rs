@@ -0,0 +1,201 @@
+use glam::Vec3;
+use crate::query::raycast;
+use crate::state::State;
+use crate::ArenaId;
+use crate::Node;
+use crate::PhycisObjectType;
+use crate::RayHit;
+use crate::Vehicle;
+use crate::Wheel;
+use crate::WheelContact;
+
+/// Slip is measured against at least this speed so that the tiny velocities
+/// of a car at rest don't read as huge slip.
+const MIN_SLIP_SPEED: f32 = 1.0;
+
+/// Casts the suspension ray and updates the spring. Returns the ground hit.
+fn update_suspension(wheel: &mut Wheel, hit: Option<RayHit>, dt: f32) -> Option<RayHit> {
+	let previous = wheel.suspension_length;
+	let hit = match hit {
+		Some(hit) => hit,
+		None => {
+			wheel.suspension_length = wheel.rest_length;
+			wheel.suspension_force = 0.0;
+			return None;
+		}
+	};
+	wheel.suspension_length = (hit.distance - wheel.radius).clamp(0.0, wheel.rest_length);
+	let compression = wheel.rest_length - wheel.suspension_length;
+	let compression_speed = (previous - wheel.suspension_length) / dt;
+	wheel.suspension_force = (wheel.stiffness * compression + wheel.damping * compression_speed).max(0.0);
+	Some(hit)
+}
+
+/// Ground motion under a wheel's contact point.
+struct TireContact {
+	forward: Vec3,
+	side: Vec3,
+	/// Chassis velocity at the contact relative to the ground.
+	velocity: Vec3,
+	/// Share of the chassis' effective mass at the contact along `forward`.
+	forward_mass: f32,
+	/// Share of the chassis' effective mass at the contact along `side`.
+	side_mass: f32,
+}
+
+/// Tire forces of a wheel on the ground. Friction never pushes harder than it
+/// takes to stop the sliding within the step, so tires don't jitter at low speed.
+fn tire_forces(wheel: &mut Wheel, contact: &TireContact, drive_torque: f32, brake_torque: f32, dt: f32) -> (f32, f32, f32, f32) {
+	let load = wheel.suspension_force;
+	let v_long = contact.velocity.dot(contact.forward);
+	let v_lat = contact.velocity.dot(contact.side);
+
+	let slip_angle = v_lat.atan2(v_long.abs());
+	let stop_lat = contact.side_mass * v_lat.abs() / dt;
+	let lateral = (-v_lat.signum() * wheel.lateral_friction.evaluate(slip_angle) * load).clamp(-stop_lat, stop_lat);
+
+	let stop_long = contact.forward_mass * v_long.abs() / dt;
+	let drive = match wheel.powered {
+		true => drive_torque / wheel.radius,
+		false => 0.0,
+	};
+	let brake = -v_long.signum() * (brake_torque / wheel.radius).min(stop_long);
+	let demand = drive + brake;
+	let curve = &wheel.longitudinal_friction;
+	let grip = curve.extremum_value * load;
+	let reference = v_long.abs().max(MIN_SLIP_SPEED);
+
+	let (longitudinal, slip_ratio) = if demand.abs() <= grip {
+		// Rolling, the tire slips only as much as the curve needs for the force
+		let slip = if grip > 0.0 { demand / grip * curve.extremum_slip } else { 0.0 };
+		wheel.angular_velocity = (v_long + slip * reference) / wheel.radius;
+		(demand, slip)
+	} else if brake.abs() > drive.abs() {
+		// Locked by the brakes, sliding to a stop
+		let slip = -v_long.signum();
+		wheel.angular_velocity = 0.0;
+		((slip * curve.evaluate(slip) * load).clamp(-stop_long, stop_long), slip)
+	} else {
+		// Spinning under throttle
+		let slip = demand.signum() * curve.asymptote_slip;
+		wheel.angular_velocity = (v_long + slip * reference) / wheel.radius;
+		(slip.signum() * curve.evaluate(slip) * load, slip)
+	};
+	(longitudinal, lateral, slip_ratio, slip_angle)
+}
+
+/// Forces the vehicle's wheels apply this step, as points and forces.
+fn wheel_forces(vehicle: &mut Vehicle, state: &State, dt: f32) -> Vec<(ArenaId<Node>, Vec3, Vec3)> {
+	let chassis = match state.nodes.get(&vehicle.chassis) {
+		Some(node) if node.physics.typ == PhycisObjectType::Dynamic => node,
+		_ => return vec![],
+	};
+	let scene_id = match chassis.scene_id {
+		Some(scene_id) => scene_id,
+		None => return vec![],
+	};
+	let mut filter = vehicle.filter.clone();
+	filter.exclude.push(vehicle.chassis);
+
+	let powered = vehicle.wheels.iter().filter(|wheel| wheel.powered).count().max(1);
+	let drive_torque = vehicle.engine_torque * vehicle.throttle.clamp(-1.0, 1.0) / powered as f32;
+	let brake_torque = vehicle.brake_torque * vehicle.brake.clamp(0.0, 1.0);
+	let steer_angle = vehicle.max_steer_angle * vehicle.steering.clamp(-1.0, 1.0);
+
+	// Suspension first, the tires grip with the load it carries
+	let hits: Vec<_> = vehicle.wheels.iter_mut().map(|wheel| {
+		wheel.steer_angle = match wheel.steered {
+			true => steer_angle,
+			false => 0.0,
+		};
+		let origin = chassis.translation + chassis.rotation * wheel.position;
+		let dir = chassis.rotation * wheel.direction;
+		let hit = raycast(state, scene_id, origin, dir, wheel.rest_length + wheel.radius, &filter);
+		update_suspension(wheel, hit, dt)
+	}).collect();
+
+	// Every grounded wheel takes its share of the mass felt at its contact
+	let grounded = hits.iter().filter(|hit| hit.is_some()).count().max(1) as f32;
+	let center = chassis.step_center_of_mass();
+	let inv_mass = chassis.physics.inv_mass();
+	let inv_inertia = chassis.inv_inertia_tensor();
+	let effective_mass = |r: Vec3, dir: Vec3| {
+		let angular = r.cross(dir);
+		let inv = inv_mass + angular.dot(inv_inertia * angular);
+		if inv > 0.0 { 1.0 / (inv * grounded) } else { 0.0 }
+	};
+	let mut forces = Vec::new();
+	for (wheel, hit) in vehicle.wheels.iter_mut().zip(hits) {
+		let hit = match hit {
+			Some(hit) => hit,
+			None => {
+				if brake_torque > 0.0 {
+					wheel.angular_velocity = 0.0;
+				}
+				wheel.rotation += wheel.angular_velocity * dt;
+				wheel.contact = None;
+				continue;
+			}
+		};
+		let ground = state.nodes.get(&hit.node_id);
+		let ground_velocity = ground.map(|ground| ground.physics.velocity).unwrap_or(Vec3::ZERO);
+		let velocity = chassis.physics.velocity + chassis.physics.angular_velocity.cross(hit.point - center) - ground_velocity;
+
+		let forward = chassis.rotation * wheel.forward();
+		let forward = (forward - hit.normal * forward.dot(hit.normal)).normalize_or_zero();
+		let side = hit.normal.cross(forward);
+		// Tire forces push closer to the height of the center of mass so they
+		// don't tip the chassis over
+		let up = chassis.rotation * wheel.up();
+		let below_center = (center - hit.point).dot(up);
+		let tire_point = hit.point + up * below_center * (1.0 - vehicle.roll_influence.clamp(0.0, 1.0));
+		let contact = TireContact {
+			forward,
+			side,
+			velocity,
+			forward_mass: effective_mass(tire_point - center, forward),
+			side_mass: effective_mass(tire_point - center, side),
+		};
+		let (longitudinal, lateral, slip_ratio, slip_angle) = tire_forces(wheel, &contact, drive_torque, brake_torque, dt);
+		wheel.rotation += wheel.angular_velocity * dt;
+
+		let suspension = up * wheel.suspension_force;
+		let tire = forward * longitudinal + side * lateral;
+		forces.push((vehicle.chassis, hit.point, suspension));
+		forces.push((vehicle.chassis, tire_point, tire));
+		if ground.is_some_and(|ground| ground.physics.typ == PhycisObjectType::Dynamic) {
+			forces.push((hit.node_id, hit.point, -suspension - tire));
+		}
+		wheel.contact = Some(WheelContact {
+			node_id: hit.node_id,
+			point: hit.point,
+			normal: hit.normal,
+			longitudinal_force: longitudinal,
+			lateral_force: lateral,
+			slip_ratio,
+			slip_angle,
+		});
+	}
+	forces
+}
+
+/// Applies the suspension and tire forces of every vehicle for one physics step.
+pub fn drive_vehicles(state: &mut State, dt: f32) {
+	let ids: Vec<_> = state.vehicles.iter().map(|(id, _)| id).collect();
+	for id in ids {
+		let mut vehicle = match state.vehicles.get(&id) {
+			Some(vehicle) => vehicle.clone(),
+			None => continue,
+		};
+		let forces = wheel_forces(&mut vehicle, state, dt);
+		for (node_id, point, force) in forces {
+			if let Some(node) = state.nodes.get_mut(&node_id) {
+				let r = point - node.step_center_of_mass();
+				node.apply_force_offset(r, force);
+			}
+		}
+		if let Some(v) = state.vehicles.get_mut(&id) {
+			*v = vehicle;
+		}
+	}
+}